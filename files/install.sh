@@ -23,8 +23,10 @@ Description=Figma Agent for Linux Service
 Requires=figma-agent.socket
 
 [Service]
-Type=exec
+Type=notify
+WatchdogSec=30
 ExecStart="$XDG_DATA_HOME/figma-agent/figma-agent"
+ExecReload=kill -HUP \$MAINPID
 
 [Install]
 WantedBy=default.target
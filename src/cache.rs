@@ -0,0 +1,179 @@
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use crate::XDG_DIRECTORIES;
+
+/// Upper bound of the files cached in each namespace.
+const MAX_CACHED_FILES: usize = 256;
+
+static TEMPORARY_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Return the path of a file derived from a font file, building it into the cache
+/// directory first if needed. The cache key covers the source path and its
+/// modification time, so entries are rebuilt after the font changes. Beyond
+/// [`MAX_CACHED_FILES`], the least recently used files of the namespace are removed.
+pub fn cached_font_file<E: From<io::Error>>(
+    namespace: &str,
+    font_path: &Path,
+    key: impl Hash,
+    build: impl FnOnce() -> Result<(Vec<u8>, &'static str), E>,
+) -> Result<PathBuf, E> {
+    let directory = XDG_DIRECTORIES.create_cache_directory(namespace)?;
+    cached_file_in(&directory, font_path, key, build)
+}
+
+fn cached_file_in<E: From<io::Error>>(
+    directory: &Path,
+    font_path: &Path,
+    key: impl Hash,
+    build: impl FnOnce() -> Result<(Vec<u8>, &'static str), E>,
+) -> Result<PathBuf, E> {
+    let mut hasher = DefaultHasher::new();
+    font_path.hash(&mut hasher);
+    fs::metadata(font_path)?
+        .modified()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+        .hash(&mut hasher);
+    key.hash(&mut hasher);
+    let name = format!("{:016x}", hasher.finish());
+
+    for extension in ["ttf", "otf"] {
+        let path = directory.join(format!("{name}.{extension}"));
+        if path.is_file() {
+            // The modification time tracks the last use of the file.
+            if let Err(error) = fs::File::options()
+                .append(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+            {
+                tracing::warn!("Failed to touch cached font file: {path:?}, error: {error:?}");
+            }
+            return Ok(path);
+        }
+    }
+
+    let (data, extension) = build()?;
+    let cache_path = directory.join(format!("{name}.{extension}"));
+    // Concurrent builds of the same file write to their own temporary file, and
+    // the last rename wins.
+    let temporary_path = directory.join(format!(
+        "{name}.{}-{}.tmp",
+        process::id(),
+        TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    if let Err(error) =
+        fs::write(&temporary_path, data).and_then(|_| fs::rename(&temporary_path, &cache_path))
+    {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(error.into());
+    }
+    tracing::debug!("Cached font file: {cache_path:?}");
+
+    evict_cached_files(directory, MAX_CACHED_FILES);
+
+    Ok(cache_path)
+}
+
+/// Remove the least recently used font files of a cache directory, keeping at most
+/// `max_files` of them.
+fn evict_cached_files(directory: &Path, max_files: usize) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    let mut files = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "ttf" || extension == "otf")
+        })
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .collect::<Vec<_>>();
+    if files.len() <= max_files {
+        return;
+    }
+
+    files.sort();
+    for (_, path) in &files[..files.len() - max_files] {
+        match fs::remove_file(path) {
+            Ok(()) => tracing::debug!("Evicted cached font file: {path:?}"),
+            Err(error) => {
+                tracing::warn!("Failed to evict cached font file: {path:?}, error: {error:?}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, time::Duration};
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn test_cached_file_in() {
+        let directory = env::temp_dir().join(format!("figma-agent-cache-in-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let font_path = fixture("FiraMono-Medium.ttf");
+
+        let mut builds = 0;
+        let mut cached_file = |key: usize| {
+            cached_file_in(&directory, &font_path, key, || {
+                builds += 1;
+                Ok::<_, io::Error>((vec![key as u8], "otf"))
+            })
+            .unwrap()
+        };
+
+        let path = cached_file(1);
+        assert_eq!(path.parent(), Some(directory.as_path()));
+        assert_eq!(path.extension().unwrap(), "otf");
+        assert_eq!(fs::read(&path).unwrap(), [1]);
+        assert_eq!(cached_file(1), path);
+        assert_ne!(cached_file(2), path);
+        assert_eq!(builds, 2);
+
+        // No temporary file is left behind.
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+
+        let error = cached_file_in(&directory, &font_path, 3, || {
+            Err::<(Vec<u8>, _), _>(io::Error::other("build failed"))
+        });
+        assert!(error.is_err());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_evict_cached_files() {
+        let directory = env::temp_dir().join(format!("figma-agent-cache-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let now = SystemTime::now();
+        for (index, name) in ["a.ttf", "b.otf", "c.ttf", "d.ttf"].into_iter().enumerate() {
+            let file = fs::File::create(directory.join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(60 * (4 - index as u64)))
+                .unwrap();
+        }
+        fs::write(directory.join("e.tmp"), []).unwrap();
+
+        evict_cached_files(&directory, 2);
+        let mut names = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["c.ttf", "d.ttf", "e.tmp"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
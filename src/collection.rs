@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use read_fonts::{FontRef, TableProvider};
+use write_fonts::FontBuilder;
+
+use crate::cache::cached_font_file;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    #[error("Failed to read or write font file")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse font file")]
+    Parse(#[from] read_fonts::ReadError),
+}
+
+/// Return the path of a standalone font extracted from a font collection,
+/// building it into the cache directory first if needed.
+pub fn cached_face(font: (&Path, usize)) -> Result<PathBuf, ExtractError> {
+    let (font_path, font_index) = font;
+    cached_font_file("faces", font_path, font_index, || extract_face(font))
+}
+
+/// Rebuild a single face of a font collection as a standalone font. Tables shared
+/// between faces of the collection are written once, like any other table.
+pub fn extract_face(
+    (font_path, font_index): (&Path, usize),
+) -> Result<(Vec<u8>, &'static str), ExtractError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+
+    let extension = if font.cff().is_ok() || font.cff2().is_ok() {
+        "otf"
+    } else {
+        "ttf"
+    };
+
+    let mut font_builder = FontBuilder::new();
+    font_builder.copy_missing_tables(font);
+
+    Ok((font_builder.build(), extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use read_fonts::{FileRef, types::Tag};
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    /// Build a font collection of the given fonts, moving each of them after the
+    /// collection header and shifting its table offsets accordingly.
+    fn build_collection(fonts: &[&[u8]]) -> Vec<u8> {
+        let header_size = 12 + 4 * fonts.len();
+        let mut data = b"ttcf\0\x01\0\0".to_vec();
+        data.extend((fonts.len() as u32).to_be_bytes());
+        let mut offset = header_size;
+        for font in fonts {
+            data.extend((offset as u32).to_be_bytes());
+            offset += font.len().next_multiple_of(4);
+        }
+        for font in fonts {
+            let base = data.len() as u32;
+            let mut font = font.to_vec();
+            let num_tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+            for record in (12..12 + 16 * num_tables).step_by(16) {
+                let table_offset = &mut font[record + 8..record + 12];
+                let shifted = u32::from_be_bytes(table_offset.try_into().unwrap()) + base;
+                table_offset.copy_from_slice(&shifted.to_be_bytes());
+            }
+            data.extend(font);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        data
+    }
+
+    #[test]
+    fn test_extract_face() {
+        let fonts = ["FiraMono-Medium.ttf", "Cantarell-VF.otf"]
+            .map(|name| fs::read(fixture(name)).unwrap());
+        let collection_path =
+            env::temp_dir().join(format!("figma-agent-collection-{}.ttc", process::id()));
+        fs::write(&collection_path, build_collection(&[&fonts[0], &fonts[1]])).unwrap();
+        assert!(matches!(
+            FileRef::new(&fs::read(&collection_path).unwrap()).unwrap(),
+            FileRef::Collection(collection) if collection.len() == 2
+        ));
+
+        for (index, (font_data, expected_extension)) in fonts.iter().zip(["ttf", "otf"]).enumerate()
+        {
+            let (data, extension) = extract_face((&collection_path, index)).unwrap();
+            assert_eq!(extension, expected_extension);
+
+            let face = FontRef::new(&data).unwrap();
+            let font = FontRef::new(font_data).unwrap();
+            let tags = |font: &FontRef| {
+                let mut tags = font
+                    .table_directory()
+                    .table_records()
+                    .iter()
+                    .map(|record| record.tag())
+                    .collect::<Vec<Tag>>();
+                tags.sort();
+                tags
+            };
+            assert_eq!(tags(&face), tags(&font));
+            for tag in tags(&font) {
+                // The checksum adjustment of `head` is recomputed for the new file.
+                let table_data = |font: &FontRef| {
+                    let mut data = font.data_for_tag(tag).unwrap().as_bytes().to_vec();
+                    if tag == Tag::new(b"head") {
+                        data[8..12].fill(0);
+                    }
+                    data
+                };
+                assert_eq!(table_data(&face), table_data(&font), "{tag}");
+            }
+        }
+
+        assert!(matches!(
+            extract_face((&collection_path, 2)),
+            Err(ExtractError::Parse(_))
+        ));
+
+        fs::remove_file(&collection_path).unwrap();
+    }
+}
@@ -0,0 +1,1810 @@
+use std::{collections::HashMap, io::Read, mem};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use flate2::read::GzDecoder;
+use kurbo::{Affine, BezPath, PathEl, Rect, Shape};
+use read_fonts::TableProvider;
+use skrifa::{
+    GlyphId, MetadataProvider,
+    bitmap::{BitmapData, BitmapGlyph, Origin},
+    color::{Brush, Color, ColorPainter, ColorStop, CompositeMode, Extend, Transform},
+    instance::Size,
+    outline::{DrawError, DrawSettings, OutlinePen},
+    raw::types::{BoundingBox, Point},
+};
+use svg::{
+    Document, Node,
+    node::{Blob, element::Element},
+};
+use tiny_skia::{
+    BlendMode, FillRule, FilterQuality, GradientStop, LinearGradient, Mask, MaskType, Paint,
+    PathBuilder, Pixmap, PixmapPaint, RadialGradient, Shader, SpreadMode,
+};
+
+use crate::renderer::{
+    GlyphPen, MAX_PIXELS, OutlinePath, RasterPath, RenderError, RunFont, SvgPen,
+};
+
+/// Palette index that stands for the text color rather than a palette entry.
+const FOREGROUND_PALETTE_INDEX: u16 = 0xFFFF;
+
+/// Half the size of the rectangle that fills the current clip, in font units.
+const FILL_EXTENT: f32 = 1048576.0;
+
+/// Number of wedges that approximate a sweep gradient.
+const SWEEP_WEDGES: usize = 180;
+
+/// Wraps an SVG pen to draw the glyphs that have COLR layers or paint graphs,
+/// OpenType-SVG documents or PNG bitmaps in color, and the others with the pen.
+pub struct ColorGlyphs<P> {
+    pen: P,
+    origin_x: f32,
+    origin_y: f32,
+    defs: Vec<Box<dyn Node>>,
+    elements: Vec<Box<dyn Node>>,
+    next_id: usize,
+    /// Id prefixes of the OpenType-SVG documents already embedded by their
+    /// start, since a document can hold several glyphs.
+    svg_documents: HashMap<usize, String>,
+}
+
+impl<P: SvgPen> ColorGlyphs<P> {
+    pub fn new(pen: P) -> Self {
+        ColorGlyphs {
+            pen,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            defs: Vec::new(),
+            elements: Vec::new(),
+            next_id: 0,
+            svg_documents: HashMap::new(),
+        }
+    }
+
+    fn draw_colr_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        let Some(color_glyph) = font.font.color_glyphs().get(glyph_id) else {
+            return Ok(false);
+        };
+
+        let scale = size.linear_scale(font.font.head()?.units_per_em());
+        // Paint in font units, with the Y-axis pointing up like in the font.
+        let mut root = Element::new("g");
+        root.assign(
+            "transform",
+            format!(
+                "translate({} {}) scale({scale} {})",
+                self.origin_x, self.origin_y, -scale
+            ),
+        );
+        root.assign("style", "isolation:isolate");
+
+        let palettes = font.font.color_palettes();
+        let palette = palettes.get(0);
+        let mut painter = SvgPainter {
+            font,
+            palette: palette.as_ref().map_or(&[], |palette| palette.colors()),
+            defs: &mut self.defs,
+            next_id: &mut self.next_id,
+            clip_ids: HashMap::new(),
+            stack: vec![root],
+            layers: Vec::new(),
+            alpha_filter_id: None,
+            error: None,
+        };
+        color_glyph.paint(&font.location, &mut painter)?;
+        if let Some(error) = painter.error {
+            return Err(error);
+        }
+
+        self.elements.extend(
+            painter
+                .stack
+                .into_iter()
+                .next()
+                .map(|root| Box::new(root) as Box<dyn Node>),
+        );
+        Ok(true)
+    }
+
+    /// Place the glyph from its OpenType-SVG document, embedding the document
+    /// the first time.
+    fn draw_svg_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        let Ok(svg) = font.font.svg() else {
+            return Ok(false);
+        };
+        let Some(data) = svg.glyph_data(glyph_id)? else {
+            return Ok(false);
+        };
+
+        let prefix = match self.svg_documents.get(&(data.as_ptr() as usize)) {
+            Some(prefix) => prefix.clone(),
+            None => {
+                let Some(document) = svg_document(data) else {
+                    return Ok(false);
+                };
+                // Documents of different fonts can use the same ids.
+                self.next_id += 1;
+                let prefix = format!("svg{}-", self.next_id);
+                self.defs
+                    .push(Box::new(Blob::new(namespace_ids(&document, &prefix))));
+                self.svg_documents
+                    .insert(data.as_ptr() as usize, prefix.clone());
+                prefix
+            }
+        };
+
+        // Documents are in font units with the Y-axis pointing down already.
+        let scale = size.linear_scale(font.font.head()?.units_per_em());
+        let mut element = Element::new("use");
+        element.assign("href", format!("#{prefix}glyph{}", glyph_id.to_u32()));
+        element.assign(
+            "transform",
+            format!(
+                "translate({} {}) scale({scale})",
+                self.origin_x, self.origin_y
+            ),
+        );
+        self.elements.push(Box::new(element));
+        Ok(true)
+    }
+
+    /// Place the PNG of the strike closest to the size, from CBDT or sbix.
+    fn draw_bitmap_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        let Some(bitmap) = font.font.bitmap_strikes().glyph_for_size(size, glyph_id) else {
+            return Ok(false);
+        };
+        let png = match bitmap.data {
+            BitmapData::Png(data) => data.to_vec(),
+            BitmapData::Bgra(data) => bgra_pixmap(data, bitmap.width, bitmap.height)?
+                .encode_png()
+                .map_err(|error| RenderError::Encode(error.into()))?,
+            BitmapData::Mask(_) => return Ok(false),
+        };
+
+        let (x, y, width, height) =
+            bitmap_rect(font, &bitmap, size, (self.origin_x, self.origin_y))?;
+        let mut element = Element::new("image");
+        element.assign("x", x);
+        element.assign("y", y);
+        element.assign("width", width);
+        element.assign("height", height);
+        element.assign("preserveAspectRatio", "none");
+        element.assign(
+            "href",
+            format!("data:image/png;base64,{}", BASE64_STANDARD.encode(png)),
+        );
+        self.elements.push(Box::new(element));
+        Ok(true)
+    }
+}
+
+impl<P: SvgPen> GlyphPen for ColorGlyphs<P> {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin_x = x;
+        self.origin_y = y;
+        self.pen.set_origin(x, y);
+    }
+
+    fn draw_color_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        Ok(self.draw_colr_glyph(font, glyph_id, size)?
+            || self.draw_svg_glyph(font, glyph_id, size)?
+            || self.draw_bitmap_glyph(font, glyph_id, size)?)
+    }
+}
+
+impl<P: SvgPen> SvgPen for ColorGlyphs<P> {
+    fn into_document(self, width: f32, height: f32) -> Document {
+        let mut document = self.pen.into_document(width, height);
+        if !self.defs.is_empty() {
+            let mut defs = Element::new("defs");
+            for node in self.defs {
+                defs.append(node);
+            }
+            document.append(defs);
+        }
+        for element in self.elements {
+            document.append(element);
+        }
+        document
+    }
+}
+
+impl<P: SvgPen> OutlinePen for ColorGlyphs<P> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.pen.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.pen.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.pen.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.pen.curve_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.pen.close();
+    }
+}
+
+/// Wraps a raster path to paint the glyphs that have COLR layers or paint graphs,
+/// or PNG or BGRA bitmaps, into images placed over the outlines of the others.
+/// OpenType-SVG glyphs are drawn with their outlines.
+pub struct RasterColorGlyphs {
+    path: RasterPath,
+    origin_x: f32,
+    origin_y: f32,
+    pixel_ratio: f32,
+    /// Images of the color glyphs, with their transforms into the pixmap.
+    images: Vec<(Pixmap, tiny_skia::Transform)>,
+}
+
+impl RasterColorGlyphs {
+    pub fn new(pixel_ratio: f32) -> Self {
+        RasterColorGlyphs {
+            path: RasterPath::new(pixel_ratio),
+            origin_x: 0.0,
+            origin_y: 0.0,
+            pixel_ratio,
+            images: Vec::new(),
+        }
+    }
+
+    /// Fill the outlines in black, then draw the color glyphs over them.
+    pub fn draw(self, pixmap: &mut Pixmap) {
+        self.path.draw(pixmap);
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        for (image, transform) in &self.images {
+            pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, *transform, None);
+        }
+    }
+
+    fn draw_colr_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        let Some(color_glyph) = font.font.color_glyphs().get(glyph_id) else {
+            return Ok(false);
+        };
+
+        let mut bounds_painter = BoundsPainter {
+            font,
+            transforms: vec![Affine::IDENTITY],
+            bounds: None,
+        };
+        color_glyph.paint(&font.location, &mut bounds_painter)?;
+        let Some(bounds) = bounds_painter.bounds else {
+            return Ok(true);
+        };
+
+        // Paint in font units, with the Y-axis pointing up like in the font, into
+        // an image covering the pixels the glyph touches.
+        let scale = size.linear_scale(font.font.head()?.units_per_em()) * self.pixel_ratio;
+        let (origin_x, origin_y) = (
+            self.origin_x * self.pixel_ratio,
+            self.origin_y * self.pixel_ratio,
+        );
+        let left = (origin_x + bounds.x0 as f32 * scale).floor();
+        let top = (origin_y - bounds.y1 as f32 * scale).floor();
+        let width = (origin_x + bounds.x1 as f32 * scale).ceil() - left;
+        let height = (origin_y - bounds.y0 as f32 * scale).ceil() - top;
+        if width * height > MAX_PIXELS as f32 {
+            return Err(RenderError::TooLarge(format!(
+                "color glyph of {width}x{height} pixels exceeds {MAX_PIXELS} pixels"
+            )));
+        }
+        let Some(pixmap) = Pixmap::new(width as u32, height as u32) else {
+            return Ok(true);
+        };
+
+        let palettes = font.font.color_palettes();
+        let palette = palettes.get(0);
+        let mut painter = RasterPainter {
+            font,
+            palette: palette.as_ref().map_or(&[], |palette| palette.colors()),
+            transforms: vec![tiny_skia::Transform::from_row(
+                scale,
+                0.0,
+                0.0,
+                -scale,
+                origin_x - left,
+                origin_y - top,
+            )],
+            clips: Vec::new(),
+            layers: vec![(pixmap, CompositeMode::SrcOver)],
+            error: None,
+        };
+        color_glyph.paint(&font.location, &mut painter)?;
+        if let Some(error) = painter.error {
+            return Err(error);
+        }
+
+        if let Some((pixmap, _)) = painter.layers.into_iter().next() {
+            self.images
+                .push((pixmap, tiny_skia::Transform::from_translate(left, top)));
+        }
+        Ok(true)
+    }
+
+    /// Draw the PNG or BGRA bitmap of the strike closest to the size, from CBDT
+    /// or sbix.
+    fn draw_bitmap_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        let Some(bitmap) = font.font.bitmap_strikes().glyph_for_size(size, glyph_id) else {
+            return Ok(false);
+        };
+        let image = match bitmap.data {
+            BitmapData::Png(data) => {
+                Pixmap::decode_png(data).map_err(|error| RenderError::Encode(error.into()))?
+            }
+            BitmapData::Bgra(data) => bgra_pixmap(data, bitmap.width, bitmap.height)?,
+            BitmapData::Mask(_) => return Ok(false),
+        };
+
+        let (x, y, width, height) =
+            bitmap_rect(font, &bitmap, size, (self.origin_x, self.origin_y))?;
+        let transform = tiny_skia::Transform::from_row(
+            width * self.pixel_ratio / image.width() as f32,
+            0.0,
+            0.0,
+            height * self.pixel_ratio / image.height() as f32,
+            x * self.pixel_ratio,
+            y * self.pixel_ratio,
+        );
+        self.images.push((image, transform));
+        Ok(true)
+    }
+}
+
+impl GlyphPen for RasterColorGlyphs {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin_x = x;
+        self.origin_y = y;
+        self.path.set_origin(x, y);
+    }
+
+    fn draw_color_glyph(
+        &mut self,
+        font: &RunFont,
+        glyph_id: GlyphId,
+        size: Size,
+    ) -> Result<bool, RenderError> {
+        Ok(self.draw_colr_glyph(font, glyph_id, size)?
+            || self.draw_bitmap_glyph(font, glyph_id, size)?)
+    }
+}
+
+impl OutlinePen for RasterColorGlyphs {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.path.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.path.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.path.curve_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.path.close();
+    }
+}
+
+/// The OpenType-SVG document as markup that can be nested in another document,
+/// decompressed and without the XML declaration or doctype.
+fn svg_document(data: &[u8]) -> Option<String> {
+    let mut text = String::new();
+    if data.starts_with(&[0x1F, 0x8B]) {
+        GzDecoder::new(data).read_to_string(&mut text).ok()?;
+    } else {
+        text = String::from_utf8(data.to_vec()).ok()?;
+    }
+    let start = text.find("<svg")?;
+    Some(text[start..].to_string())
+}
+
+/// Prefix the ids of the document and the references to them, so that it can
+/// share the `<defs>` with other documents and the painted glyphs.
+fn namespace_ids(document: &str, prefix: &str) -> String {
+    let mut result = String::with_capacity(document.len());
+    let mut last = 0;
+    for (index, delimiter) in document.match_indices(['"', '\'', '(']) {
+        let head = &document[..index];
+        let tail = &document[index + 1..];
+        let is_id = delimiter != "("
+            && head
+                .strip_suffix("id=")
+                .is_some_and(|head| head.ends_with(char::is_whitespace));
+        let is_reference = tail.starts_with('#')
+            && if delimiter == "(" {
+                head.ends_with("url")
+            } else {
+                head.ends_with("href=") || head.ends_with("url(")
+            };
+        let start = match (is_id, is_reference) {
+            (true, _) => index + 1,
+            (_, true) => index + 2,
+            _ => continue,
+        };
+        result.push_str(&document[last..start]);
+        result.push_str(prefix);
+        last = start;
+    }
+    result.push_str(&document[last..]);
+    result
+}
+
+/// Where the bitmap goes around the origin, as its x, y, width and height.
+fn bitmap_rect(
+    font: &RunFont,
+    bitmap: &BitmapGlyph,
+    size: Size,
+    (origin_x, origin_y): (f32, f32),
+) -> Result<(f32, f32, f32, f32), RenderError> {
+    // Outer bearings are in font units and inner bearings in strike pixels.
+    let font_scale = size.linear_scale(font.font.head()?.units_per_em());
+    let ppem = size.ppem().unwrap_or(bitmap.ppem_y);
+    let (scale_x, scale_y) = (ppem / bitmap.ppem_x, ppem / bitmap.ppem_y);
+    let (width, height) = (
+        bitmap.width as f32 * scale_x,
+        bitmap.height as f32 * scale_y,
+    );
+    let x = origin_x + bitmap.bearing_x * font_scale + bitmap.inner_bearing_x * scale_x;
+    let y = origin_y - bitmap.bearing_y * font_scale - bitmap.inner_bearing_y * scale_y;
+    let y = match bitmap.placement_origin {
+        Origin::TopLeft => y,
+        Origin::BottomLeft => y - height,
+    };
+    Ok((x, y, width, height))
+}
+
+fn bgra_pixmap(data: &[u8], width: u32, height: u32) -> Result<Pixmap, RenderError> {
+    let mut rgba = data.to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    tiny_skia::IntSize::from_wh(width, height)
+        .and_then(|size| Pixmap::from_vec(rgba, size))
+        .ok_or_else(|| RenderError::Encode(format!("invalid bitmap size: {width}x{height}").into()))
+}
+
+/// The glyph outline in font units, with the Y-axis pointing up.
+fn glyph_path(font: &RunFont, glyph_id: GlyphId) -> Result<BezPath, DrawError> {
+    let mut outline_path = OutlinePath::default();
+    font.outlines
+        .get(glyph_id)
+        .ok_or(DrawError::GlyphNotFound(glyph_id))?
+        .draw(
+            DrawSettings::unhinted(Size::unscaled(), &font.location),
+            &mut outline_path,
+        )?;
+
+    // The outline path points down, so flip it back.
+    let mut path = outline_path.into_path();
+    path.apply_affine(Affine::FLIP_Y);
+    Ok(path)
+}
+
+/// Position of the wedge starting at the angle along the color line of a sweep
+/// gradient.
+fn sweep_offset(angle: f32, step: f32, start_angle: f32, end_angle: f32, extend: Extend) -> f32 {
+    let offset = if end_angle > start_angle {
+        (angle + step / 2.0 - start_angle) / (end_angle - start_angle)
+    } else if angle < start_angle {
+        0.0
+    } else {
+        1.0
+    };
+    match extend {
+        Extend::Repeat => offset - offset.floor(),
+        Extend::Reflect => 1.0 - (offset.rem_euclid(2.0) - 1.0).abs(),
+        _ => offset.clamp(0.0, 1.0),
+    }
+}
+
+/// The color stops around the offset of the color line, and how far it is from
+/// the first to the second.
+fn stops_around(color_stops: &[ColorStop], offset: f32) -> Option<(&ColorStop, &ColorStop, f32)> {
+    let (first, last) = (color_stops.first()?, color_stops.last()?);
+    let (stop0, stop1) = match color_stops.iter().position(|stop| stop.offset > offset) {
+        Some(0) => (first, first),
+        Some(index) => (&color_stops[index - 1], &color_stops[index]),
+        None => (last, last),
+    };
+    let t = if stop1.offset > stop0.offset {
+        (offset - stop0.offset) / (stop1.offset - stop0.offset)
+    } else {
+        0.0
+    };
+    Some((stop0, stop1, t))
+}
+
+/// Maps the paint graph of a COLR glyph to nested SVG groups, with clip paths
+/// and gradients in `<defs>`.
+struct SvgPainter<'a, 'b> {
+    font: &'b RunFont<'a>,
+    palette: &'b [Color],
+    defs: &'b mut Vec<Box<dyn Node>>,
+    next_id: &'b mut usize,
+    clip_ids: HashMap<GlyphId, String>,
+    /// Groups that are still open, the outermost first.
+    stack: Vec<Element>,
+    /// Composite modes of the layers that are still open.
+    layers: Vec<CompositeMode>,
+    /// Filter that turns content black, keeping its alpha.
+    alpha_filter_id: Option<String>,
+    /// The first error while drawing, since the painter callbacks can't fail.
+    error: Option<RenderError>,
+}
+
+impl SvgPainter<'_, '_> {
+    fn id(&mut self, prefix: &str) -> String {
+        *self.next_id += 1;
+        format!("{prefix}{}", self.next_id)
+    }
+
+    fn push(&mut self, element: Element) {
+        self.stack.push(element);
+    }
+
+    fn pop(&mut self) {
+        if self.stack.len() > 1
+            && let Some(element) = self.stack.pop()
+            && let Some(parent) = self.stack.last_mut()
+        {
+            parent.append(element);
+        }
+    }
+
+    fn append(&mut self, element: Element) {
+        if let Some(parent) = self.stack.last_mut() {
+            parent.append(element);
+        }
+    }
+
+    /// The glyph outline in font units.
+    fn glyph_path(&mut self, glyph_id: GlyphId) -> BezPath {
+        glyph_path(self.font, glyph_id).unwrap_or_else(|error| {
+            self.error.get_or_insert(error.into());
+            BezPath::new()
+        })
+    }
+
+    /// CSS color and opacity of a palette entry.
+    fn color(&self, palette_index: u16, alpha: f32) -> (String, f32) {
+        if palette_index == FOREGROUND_PALETTE_INDEX {
+            return ("currentColor".into(), alpha);
+        }
+        match self.palette.get(palette_index as usize) {
+            Some(color) => (
+                format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue),
+                alpha * color.alpha as f32 / 255.0,
+            ),
+            None => ("black".into(), alpha),
+        }
+    }
+
+    /// Append the element filled with the brush, defining gradients as needed.
+    fn append_filled(
+        &mut self,
+        mut element: Element,
+        bounds: Rect,
+        brush: Brush,
+        transform: Option<Transform>,
+    ) {
+        let mut gradient = match brush {
+            Brush::Solid {
+                palette_index,
+                alpha,
+            } => {
+                self.set_solid_fill(&mut element, palette_index, alpha);
+                self.append(element);
+                return;
+            }
+            Brush::LinearGradient {
+                p0,
+                p1,
+                color_stops,
+                extend,
+            } => {
+                let mut gradient = self.gradient("linearGradient", color_stops, extend);
+                gradient.assign("x1", p0.x);
+                gradient.assign("y1", p0.y);
+                gradient.assign("x2", p1.x);
+                gradient.assign("y2", p1.y);
+                gradient
+            }
+            Brush::RadialGradient {
+                c0,
+                r0,
+                c1,
+                r1,
+                color_stops,
+                extend,
+            } => {
+                let mut gradient = self.gradient("radialGradient", color_stops, extend);
+                gradient.assign("fx", c0.x);
+                gradient.assign("fy", c0.y);
+                gradient.assign("fr", r0);
+                gradient.assign("cx", c1.x);
+                gradient.assign("cy", c1.y);
+                gradient.assign("r", r1);
+                gradient
+            }
+            // SVG has no sweep gradients, so draw wedges clipped to the element.
+            Brush::SweepGradient {
+                c0,
+                start_angle,
+                end_angle,
+                color_stops,
+                extend,
+            } => {
+                // The wedges reach the corners of the element in the brush space,
+                // as huge ones break some renderers.
+                let center = kurbo::Point::new(c0.x as f64, c0.y as f64);
+                let inverse = transform
+                    .map(|transform| affine(&transform).inverse())
+                    .unwrap_or(Affine::IDENTITY);
+                let radius = [
+                    (bounds.x0, bounds.y0),
+                    (bounds.x1, bounds.y0),
+                    (bounds.x0, bounds.y1),
+                    (bounds.x1, bounds.y1),
+                ]
+                .into_iter()
+                .map(|corner| (inverse * kurbo::Point::from(corner)).distance(center))
+                .fold(0.0, f64::max);
+                if !radius.is_finite() {
+                    return;
+                }
+
+                let mut wedges = self.sweep_gradient(
+                    c0,
+                    radius as f32 * 1.01,
+                    start_angle,
+                    end_angle,
+                    color_stops,
+                    extend,
+                );
+                if let Some(transform) = transform {
+                    wedges.assign("transform", matrix(&transform));
+                }
+                let id = self.id("clip");
+                let mut clip_path = Element::new("clipPath");
+                clip_path.assign("id", id.clone());
+                clip_path.append(element);
+                self.defs.push(Box::new(clip_path));
+                self.push_clip(&id);
+                self.append(wedges);
+                self.pop();
+                return;
+            }
+        };
+
+        if let Some(transform) = transform {
+            gradient.assign("gradientTransform", matrix(&transform));
+        }
+        let id = self.id("gradient");
+        gradient.assign("id", id.clone());
+        self.defs.push(Box::new(gradient));
+        element.assign("fill", format!("url(#{id})"));
+        self.append(element);
+    }
+
+    /// Wedges around the center, each filled with the color at its middle. The
+    /// angles are in degrees clockwise from the X-axis as skrifa passes them,
+    /// the counter-clockwise ones of the font subtracted from 360.
+    fn sweep_gradient(
+        &self,
+        center: Point<f32>,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        color_stops: &[ColorStop],
+        extend: Extend,
+    ) -> Element {
+        let mut group = Element::new("g");
+        let step = 360.0 / SWEEP_WEDGES as f32;
+        let point = |angle: f32| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            (center.x + radius * cos, center.y - radius * sin)
+        };
+        for wedge in 0..SWEEP_WEDGES {
+            let angle = wedge as f32 * step;
+            let offset = sweep_offset(angle, step, start_angle, end_angle, extend);
+            let (color, opacity) = self.color_at(color_stops, offset);
+
+            // Overlap the next wedge a little to hide the seams.
+            let (x0, y0) = point(angle);
+            let (x1, y1) = point(angle + step * 1.5);
+            let mut path = Element::new("path");
+            path.assign(
+                "d",
+                format!("M{} {}L{x0} {y0}L{x1} {y1}Z", center.x, center.y),
+            );
+            path.assign("fill", color);
+            if opacity < 1.0 {
+                path.assign("fill-opacity", opacity);
+            }
+            group.append(path);
+        }
+        group
+    }
+
+    /// CSS color and opacity at the offset of the color line, interpolated
+    /// between the palette entries around it.
+    fn color_at(&self, color_stops: &[ColorStop], offset: f32) -> (String, f32) {
+        let Some((stop0, stop1, t)) = stops_around(color_stops, offset) else {
+            return ("black".into(), 0.0);
+        };
+        match (self.rgba(stop0), self.rgba(stop1)) {
+            (Some(rgba0), Some(rgba1)) => {
+                let [red, green, blue, alpha] =
+                    std::array::from_fn(|index| rgba0[index] + (rgba1[index] - rgba0[index]) * t);
+                (
+                    format!(
+                        "#{:02x}{:02x}{:02x}",
+                        red.round() as u8,
+                        green.round() as u8,
+                        blue.round() as u8
+                    ),
+                    alpha,
+                )
+            }
+            // The text color can't be mixed, so take the closest stop.
+            _ => {
+                let stop = if t < 0.5 { stop0 } else { stop1 };
+                self.color(stop.palette_index, stop.alpha)
+            }
+        }
+    }
+
+    /// Channels of a color stop, with the alpha from 0 to 1, unless it is the
+    /// text color.
+    fn rgba(&self, color_stop: &ColorStop) -> Option<[f32; 4]> {
+        if color_stop.palette_index == FOREGROUND_PALETTE_INDEX {
+            return None;
+        }
+        let color = self
+            .palette
+            .get(color_stop.palette_index as usize)
+            .copied()
+            .unwrap_or(Color {
+                red: 0,
+                green: 0,
+                blue: 0,
+                alpha: 255,
+            });
+        Some([
+            color.red as f32,
+            color.green as f32,
+            color.blue as f32,
+            color_stop.alpha * color.alpha as f32 / 255.0,
+        ])
+    }
+
+    fn set_solid_fill(&self, element: &mut Element, palette_index: u16, alpha: f32) {
+        let (color, opacity) = self.color(palette_index, alpha);
+        element.assign("fill", color);
+        if opacity < 1.0 {
+            element.assign("fill-opacity", opacity);
+        }
+    }
+
+    fn gradient(&self, name: &str, color_stops: &[ColorStop], extend: Extend) -> Element {
+        let mut gradient = Element::new(name);
+        gradient.assign("gradientUnits", "userSpaceOnUse");
+        gradient.assign(
+            "spreadMethod",
+            match extend {
+                Extend::Repeat => "repeat",
+                Extend::Reflect => "reflect",
+                _ => "pad",
+            },
+        );
+        for color_stop in color_stops {
+            let (color, opacity) = self.color(color_stop.palette_index, color_stop.alpha);
+            let mut stop = Element::new("stop");
+            stop.assign("offset", color_stop.offset);
+            stop.assign("stop-color", color);
+            if opacity < 1.0 {
+                stop.assign("stop-opacity", opacity);
+            }
+            gradient.append(stop);
+        }
+        gradient
+    }
+
+    /// The content scaled by the alpha of the other content or its complement,
+    /// with a mask.
+    fn composite_term(
+        &mut self,
+        content: Element,
+        factor: Factor,
+        other: &Element,
+    ) -> Option<Element> {
+        let mut mask = Element::new("mask");
+        match factor {
+            Factor::Zero => return None,
+            Factor::One => return Some(content),
+            Factor::Alpha => {
+                mask.assign("style", "mask-type:alpha");
+                mask.append(other.clone());
+            }
+            // White where the other content is transparent and black where it
+            // is opaque.
+            Factor::InverseAlpha => {
+                let mut rect = extent_rect();
+                rect.assign("fill", "white");
+                mask.append(rect);
+                let mut group = Element::new("g");
+                group.assign("filter", format!("url(#{})", self.alpha_filter_id()));
+                group.append(other.clone());
+                mask.append(group);
+            }
+        }
+        let id = self.id("mask");
+        mask.assign("id", id.clone());
+        self.defs.push(Box::new(mask));
+
+        let mut group = Element::new("g");
+        group.assign("mask", format!("url(#{id})"));
+        group.append(content);
+        Some(group)
+    }
+
+    fn alpha_filter_id(&mut self) -> String {
+        if let Some(id) = &self.alpha_filter_id {
+            return id.clone();
+        }
+        let id = self.id("filter");
+        let mut color_matrix = Element::new("feColorMatrix");
+        color_matrix.assign("type", "matrix");
+        color_matrix.assign("values", "0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 1 0");
+        let mut filter = Element::new("filter");
+        filter.assign("id", id.clone());
+        filter.append(color_matrix);
+        self.defs.push(Box::new(filter));
+        self.alpha_filter_id = Some(id.clone());
+        id
+    }
+
+    fn push_clip(&mut self, id: &str) {
+        let mut group = Element::new("g");
+        group.assign("clip-path", format!("url(#{id})"));
+        self.push(group);
+    }
+}
+
+impl ColorPainter for SvgPainter<'_, '_> {
+    fn push_transform(&mut self, transform: Transform) {
+        let mut group = Element::new("g");
+        group.assign("transform", matrix(&transform));
+        self.push(group);
+    }
+
+    fn pop_transform(&mut self) {
+        self.pop();
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        let id = match self.clip_ids.get(&glyph_id) {
+            Some(id) => id.clone(),
+            None => {
+                let id = self.id("clip");
+                let mut path = Element::new("path");
+                path.assign("d", self.glyph_path(glyph_id).to_svg());
+                let mut clip_path = Element::new("clipPath");
+                clip_path.assign("id", id.clone());
+                clip_path.append(path);
+                self.defs.push(Box::new(clip_path));
+                self.clip_ids.insert(glyph_id, id.clone());
+                id
+            }
+        };
+        self.push_clip(&id);
+    }
+
+    fn push_clip_box(&mut self, clip_box: BoundingBox<f32>) {
+        let id = self.id("clip");
+        let mut rect = Element::new("rect");
+        rect.assign("x", clip_box.x_min);
+        rect.assign("y", clip_box.y_min);
+        rect.assign("width", clip_box.x_max - clip_box.x_min);
+        rect.assign("height", clip_box.y_max - clip_box.y_min);
+        let mut clip_path = Element::new("clipPath");
+        clip_path.assign("id", id.clone());
+        clip_path.append(rect);
+        self.defs.push(Box::new(clip_path));
+        self.push_clip(&id);
+    }
+
+    fn pop_clip(&mut self) {
+        self.pop();
+    }
+
+    fn fill(&mut self, brush: Brush<'_>) {
+        let extent = FILL_EXTENT as f64;
+        let bounds = Rect::new(-extent, -extent, extent, extent);
+        self.append_filled(extent_rect(), bounds, brush, None);
+    }
+
+    fn fill_glyph(
+        &mut self,
+        glyph_id: GlyphId,
+        brush_transform: Option<Transform>,
+        brush: Brush<'_>,
+    ) {
+        let mut path = Element::new("path");
+        let glyph_path = self.glyph_path(glyph_id);
+        path.assign("d", glyph_path.to_svg());
+        self.append_filled(path, glyph_path.bounding_box(), brush, brush_transform);
+    }
+
+    fn push_layer(&mut self, composite_mode: CompositeMode) {
+        let mut group = Element::new("g");
+        // Layers are composited with what the enclosing layer drew only.
+        match blend_mode(composite_mode) {
+            Some(blend_mode) => group.assign("style", format!("mix-blend-mode:{blend_mode}")),
+            None => group.assign("style", "isolation:isolate"),
+        }
+        self.layers.push(composite_mode);
+        self.push(group);
+    }
+
+    fn pop_layer(&mut self) {
+        let Some((source_factor, backdrop_factor)) = self.layers.pop().and_then(porter_duff) else {
+            self.pop();
+            return;
+        };
+        if self.stack.len() < 2 {
+            return;
+        }
+        let Some(source) = self.stack.pop() else {
+            return;
+        };
+        let mut backdrop = Element::new("g");
+        if let Some(parent) = self.stack.last_mut() {
+            for node in mem::take(parent.get_children_mut()) {
+                backdrop.append(node);
+            }
+        }
+
+        let backdrop_term = self.composite_term(backdrop.clone(), backdrop_factor, &source);
+        let source_term = self.composite_term(source, source_factor, &backdrop);
+        let mut terms = backdrop_term.into_iter().chain(source_term);
+        if let Some(term) = terms.next() {
+            self.append(term);
+        }
+        // The terms cover complementary parts of each pixel, so they add up.
+        if let Some(term) = terms.next() {
+            let mut group = Element::new("g");
+            group.assign("style", "mix-blend-mode:plus-lighter");
+            group.append(term);
+            self.append(group);
+        }
+    }
+}
+
+/// Finds the area of a COLR glyph in font units, from the outlines and clip boxes
+/// its paint graph uses.
+struct BoundsPainter<'a, 'b> {
+    font: &'b RunFont<'a>,
+    transforms: Vec<Affine>,
+    bounds: Option<Rect>,
+}
+
+impl BoundsPainter<'_, '_> {
+    fn add(&mut self, rect: Rect) {
+        let transform = self.transforms.last().copied().unwrap_or_default();
+        let rect = transform.transform_rect_bbox(rect);
+        if rect.is_finite() {
+            self.bounds = Some(self.bounds.map_or(rect, |bounds| bounds.union(rect)));
+        }
+    }
+
+    fn add_glyph(&mut self, glyph_id: GlyphId) {
+        if let Ok(path) = glyph_path(self.font, glyph_id)
+            && !path.is_empty()
+        {
+            self.add(path.bounding_box());
+        }
+    }
+}
+
+impl ColorPainter for BoundsPainter<'_, '_> {
+    fn push_transform(&mut self, transform: Transform) {
+        let current = self.transforms.last().copied().unwrap_or_default();
+        self.transforms.push(current * affine(&transform));
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        self.add_glyph(glyph_id);
+    }
+
+    fn push_clip_box(&mut self, clip_box: BoundingBox<f32>) {
+        self.add(Rect::new(
+            clip_box.x_min as f64,
+            clip_box.y_min as f64,
+            clip_box.x_max as f64,
+            clip_box.y_max as f64,
+        ));
+    }
+
+    fn pop_clip(&mut self) {}
+
+    fn fill(&mut self, _brush: Brush<'_>) {}
+
+    fn fill_glyph(
+        &mut self,
+        glyph_id: GlyphId,
+        _brush_transform: Option<Transform>,
+        _brush: Brush<'_>,
+    ) {
+        self.add_glyph(glyph_id);
+    }
+
+    fn push_layer(&mut self, _composite_mode: CompositeMode) {}
+}
+
+/// Paints the paint graph of a COLR glyph into a pixmap, with a mask for each
+/// clip and a pixmap for each layer. The text color is black like the outlines.
+struct RasterPainter<'a, 'b> {
+    font: &'b RunFont<'a>,
+    palette: &'b [Color],
+    /// Transforms from the current space into the pixmap, the outermost first.
+    transforms: Vec<tiny_skia::Transform>,
+    /// Clips that are still open, each one intersected with the ones before.
+    clips: Vec<Mask>,
+    /// The pixmap of the glyph, then the layers that are still open.
+    layers: Vec<(Pixmap, CompositeMode)>,
+    /// The first error while drawing, since the painter callbacks can't fail.
+    error: Option<RenderError>,
+}
+
+impl RasterPainter<'_, '_> {
+    fn transform(&self) -> tiny_skia::Transform {
+        self.transforms.last().copied().unwrap_or_default()
+    }
+
+    /// The glyph outline in font units.
+    fn glyph_path(&mut self, glyph_id: GlyphId) -> Option<tiny_skia::Path> {
+        let path = glyph_path(self.font, glyph_id)
+            .map_err(|error| self.error.get_or_insert(error.into()))
+            .ok()?;
+        let mut builder = PathBuilder::new();
+        for element in path.elements() {
+            match *element {
+                PathEl::MoveTo(p) => builder.move_to(p.x as f32, p.y as f32),
+                PathEl::LineTo(p) => builder.line_to(p.x as f32, p.y as f32),
+                PathEl::QuadTo(p1, p) => {
+                    builder.quad_to(p1.x as f32, p1.y as f32, p.x as f32, p.y as f32)
+                }
+                PathEl::CurveTo(p1, p2, p) => builder.cubic_to(
+                    p1.x as f32,
+                    p1.y as f32,
+                    p2.x as f32,
+                    p2.y as f32,
+                    p.x as f32,
+                    p.y as f32,
+                ),
+                PathEl::ClosePath => builder.close(),
+            }
+        }
+        builder.finish()
+    }
+
+    /// Open a clip of the path in the current space, which is empty without one.
+    fn push_clip_path(&mut self, path: Option<tiny_skia::Path>) {
+        let mut mask = match self.clips.last() {
+            Some(mask) => mask.clone(),
+            None => {
+                let mut mask = Mask::from_pixmap(self.layers[0].0.as_ref(), MaskType::Alpha);
+                mask.data_mut().fill(u8::MAX);
+                mask
+            }
+        };
+        match path {
+            Some(path) => mask.intersect_path(&path, FillRule::Winding, true, self.transform()),
+            None => mask.clear(),
+        }
+        self.clips.push(mask);
+    }
+
+    /// Color of a palette entry, from 0 to 1.
+    fn rgba(&self, palette_index: u16, alpha: f32) -> [f32; 4] {
+        let color = match self.palette.get(palette_index as usize) {
+            Some(color) if palette_index != FOREGROUND_PALETTE_INDEX => *color,
+            _ => Color {
+                red: 0,
+                green: 0,
+                blue: 0,
+                alpha: 255,
+            },
+        };
+        [
+            color.red as f32 / 255.0,
+            color.green as f32 / 255.0,
+            color.blue as f32 / 255.0,
+            alpha * color.alpha as f32 / 255.0,
+        ]
+    }
+
+    fn color(&self, palette_index: u16, alpha: f32) -> tiny_skia::Color {
+        let [red, green, blue, alpha] = self.rgba(palette_index, alpha);
+        tiny_skia::Color::from_rgba(red, green, blue, alpha.clamp(0.0, 1.0))
+            .unwrap_or(tiny_skia::Color::BLACK)
+    }
+
+    /// Color at the offset of the color line, interpolated between the palette
+    /// entries around it.
+    fn color_at(&self, color_stops: &[ColorStop], offset: f32) -> tiny_skia::Color {
+        let Some((stop0, stop1, t)) = stops_around(color_stops, offset) else {
+            return tiny_skia::Color::TRANSPARENT;
+        };
+        let rgba0 = self.rgba(stop0.palette_index, stop0.alpha);
+        let rgba1 = self.rgba(stop1.palette_index, stop1.alpha);
+        let [red, green, blue, alpha] =
+            std::array::from_fn(|index| rgba0[index] + (rgba1[index] - rgba0[index]) * t);
+        tiny_skia::Color::from_rgba(red, green, blue, alpha.clamp(0.0, 1.0))
+            .unwrap_or(tiny_skia::Color::BLACK)
+    }
+
+    /// Shader of the brush, in the space of the brush transform.
+    fn shader(&self, brush: &Brush, transform: tiny_skia::Transform) -> Option<Shader<'static>> {
+        let stops = |color_stops: &[ColorStop]| {
+            color_stops
+                .iter()
+                .map(|stop| {
+                    GradientStop::new(stop.offset, self.color(stop.palette_index, stop.alpha))
+                })
+                .collect::<Vec<_>>()
+        };
+        let spread_mode = |extend: Extend| match extend {
+            Extend::Repeat => SpreadMode::Repeat,
+            Extend::Reflect => SpreadMode::Reflect,
+            _ => SpreadMode::Pad,
+        };
+        match *brush {
+            Brush::Solid {
+                palette_index,
+                alpha,
+            } => Some(Shader::SolidColor(self.color(palette_index, alpha))),
+            Brush::LinearGradient {
+                p0,
+                p1,
+                color_stops,
+                extend,
+            } => LinearGradient::new(
+                tiny_skia::Point::from_xy(p0.x, p0.y),
+                tiny_skia::Point::from_xy(p1.x, p1.y),
+                stops(color_stops),
+                spread_mode(extend),
+                transform,
+            ),
+            // tiny-skia has no start radius, so the gradient starts at the focus.
+            Brush::RadialGradient {
+                c0,
+                c1,
+                r1,
+                color_stops,
+                extend,
+                ..
+            } => RadialGradient::new(
+                tiny_skia::Point::from_xy(c0.x, c0.y),
+                tiny_skia::Point::from_xy(c1.x, c1.y),
+                r1,
+                stops(color_stops),
+                spread_mode(extend),
+                transform,
+            ),
+            Brush::SweepGradient { .. } => None,
+        }
+    }
+
+    /// Fill the current clip with the brush, in the space of the brush transform.
+    fn fill_clip(&mut self, brush: Brush<'_>, brush_transform: tiny_skia::Transform) {
+        let transform = self.transform().pre_concat(brush_transform);
+        let Some((pixmap, _)) = self.layers.last() else {
+            return;
+        };
+        let (width, height) = (pixmap.width() as f32, pixmap.height() as f32);
+        let Some(rect) = tiny_skia::Rect::from_xywh(0.0, 0.0, width, height) else {
+            return;
+        };
+
+        if let Brush::SweepGradient {
+            c0,
+            start_angle,
+            end_angle,
+            color_stops,
+            extend,
+        } = brush
+        {
+            // Wedges that reach the corners of the pixmap in the brush space.
+            let Some(inverse) = transform.invert() else {
+                return;
+            };
+            let mut corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+                .map(|(x, y)| tiny_skia::Point::from_xy(x, y));
+            inverse.map_points(&mut corners);
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(tiny_skia::Point::from_xy(c0.x, c0.y)))
+                .fold(0.0, f32::max)
+                * 1.01;
+            if !radius.is_finite() {
+                return;
+            }
+
+            let step = 360.0 / SWEEP_WEDGES as f32;
+            let point = |angle: f32| {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                (c0.x + radius * cos, c0.y - radius * sin)
+            };
+            for wedge in 0..SWEEP_WEDGES {
+                let angle = wedge as f32 * step;
+                let offset = sweep_offset(angle, step, start_angle, end_angle, extend);
+                let mut paint = Paint::default();
+                paint.set_color(self.color_at(color_stops, offset));
+                paint.anti_alias = true;
+
+                // Overlap the next wedge a little to hide the seams.
+                let (x0, y0) = point(angle);
+                let (x1, y1) = point(angle + step * 1.5);
+                let mut builder = PathBuilder::new();
+                builder.move_to(c0.x, c0.y);
+                builder.line_to(x0, y0);
+                builder.line_to(x1, y1);
+                builder.close();
+                if let Some(path) = builder.finish()
+                    && let Some((pixmap, _)) = self.layers.last_mut()
+                {
+                    pixmap.fill_path(
+                        &path,
+                        &paint,
+                        FillRule::Winding,
+                        transform,
+                        self.clips.last(),
+                    );
+                }
+            }
+            return;
+        }
+
+        let Some(shader) = self.shader(&brush, transform) else {
+            return;
+        };
+        let paint = Paint {
+            shader,
+            anti_alias: true,
+            ..Paint::default()
+        };
+        if let Some((pixmap, _)) = self.layers.last_mut() {
+            pixmap.fill_rect(
+                rect,
+                &paint,
+                tiny_skia::Transform::identity(),
+                self.clips.last(),
+            );
+        }
+    }
+}
+
+impl ColorPainter for RasterPainter<'_, '_> {
+    fn push_transform(&mut self, transform: Transform) {
+        let transform = self.transform().pre_concat(tiny_skia::Transform::from_row(
+            transform.xx,
+            transform.yx,
+            transform.xy,
+            transform.yy,
+            transform.dx,
+            transform.dy,
+        ));
+        self.transforms.push(transform);
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        let path = self.glyph_path(glyph_id);
+        self.push_clip_path(path);
+    }
+
+    fn push_clip_box(&mut self, clip_box: BoundingBox<f32>) {
+        let path = tiny_skia::Rect::from_ltrb(
+            clip_box.x_min,
+            clip_box.y_min,
+            clip_box.x_max,
+            clip_box.y_max,
+        )
+        .map(PathBuilder::from_rect);
+        self.push_clip_path(path);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn fill(&mut self, brush: Brush<'_>) {
+        self.fill_clip(brush, tiny_skia::Transform::identity());
+    }
+
+    fn fill_glyph(
+        &mut self,
+        glyph_id: GlyphId,
+        brush_transform: Option<Transform>,
+        brush: Brush<'_>,
+    ) {
+        self.push_clip_glyph(glyph_id);
+        let brush_transform = brush_transform.map_or_else(tiny_skia::Transform::identity, |t| {
+            tiny_skia::Transform::from_row(t.xx, t.yx, t.xy, t.yy, t.dx, t.dy)
+        });
+        self.fill_clip(brush, brush_transform);
+        self.pop_clip();
+    }
+
+    fn push_layer(&mut self, composite_mode: CompositeMode) {
+        let Some((pixmap, _)) = self.layers.first() else {
+            return;
+        };
+        if let Some(layer) = Pixmap::new(pixmap.width(), pixmap.height()) {
+            self.layers.push((layer, composite_mode));
+        }
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let Some((layer, composite_mode)) = self.layers.pop() else {
+            return;
+        };
+        let paint = PixmapPaint {
+            blend_mode: blend_mode_of(composite_mode),
+            ..PixmapPaint::default()
+        };
+        if let Some((pixmap, _)) = self.layers.last_mut() {
+            pixmap.draw_pixmap(
+                0,
+                0,
+                layer.as_ref(),
+                &paint,
+                tiny_skia::Transform::identity(),
+                self.clips.last(),
+            );
+        }
+    }
+}
+
+/// tiny-skia blend mode of the composite mode.
+fn blend_mode_of(composite_mode: CompositeMode) -> BlendMode {
+    match composite_mode {
+        CompositeMode::Clear => BlendMode::Clear,
+        CompositeMode::Src => BlendMode::Source,
+        CompositeMode::Dest => BlendMode::Destination,
+        CompositeMode::DestOver => BlendMode::DestinationOver,
+        CompositeMode::SrcIn => BlendMode::SourceIn,
+        CompositeMode::DestIn => BlendMode::DestinationIn,
+        CompositeMode::SrcOut => BlendMode::SourceOut,
+        CompositeMode::DestOut => BlendMode::DestinationOut,
+        CompositeMode::SrcAtop => BlendMode::SourceAtop,
+        CompositeMode::DestAtop => BlendMode::DestinationAtop,
+        CompositeMode::Xor => BlendMode::Xor,
+        CompositeMode::Plus => BlendMode::Plus,
+        CompositeMode::Screen => BlendMode::Screen,
+        CompositeMode::Overlay => BlendMode::Overlay,
+        CompositeMode::Darken => BlendMode::Darken,
+        CompositeMode::Lighten => BlendMode::Lighten,
+        CompositeMode::ColorDodge => BlendMode::ColorDodge,
+        CompositeMode::ColorBurn => BlendMode::ColorBurn,
+        CompositeMode::HardLight => BlendMode::HardLight,
+        CompositeMode::SoftLight => BlendMode::SoftLight,
+        CompositeMode::Difference => BlendMode::Difference,
+        CompositeMode::Exclusion => BlendMode::Exclusion,
+        CompositeMode::Multiply => BlendMode::Multiply,
+        CompositeMode::HslHue => BlendMode::Hue,
+        CompositeMode::HslSaturation => BlendMode::Saturation,
+        CompositeMode::HslColor => BlendMode::Color,
+        CompositeMode::HslLuminosity => BlendMode::Luminosity,
+        _ => BlendMode::SourceOver,
+    }
+}
+
+/// Rectangle that fills the current clip.
+fn extent_rect() -> Element {
+    let mut rect = Element::new("rect");
+    rect.assign("x", -FILL_EXTENT);
+    rect.assign("y", -FILL_EXTENT);
+    rect.assign("width", FILL_EXTENT * 2.0);
+    rect.assign("height", FILL_EXTENT * 2.0);
+    rect
+}
+
+fn affine(transform: &Transform) -> Affine {
+    Affine::new(
+        [
+            transform.xx,
+            transform.yx,
+            transform.xy,
+            transform.yy,
+            transform.dx,
+            transform.dy,
+        ]
+        .map(f64::from),
+    )
+}
+
+fn matrix(transform: &Transform) -> String {
+    format!(
+        "matrix({} {} {} {} {} {})",
+        transform.xx, transform.yx, transform.xy, transform.yy, transform.dx, transform.dy
+    )
+}
+
+/// Factor of the source or the backdrop in a Porter-Duff composite mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Factor {
+    Zero,
+    One,
+    /// The alpha of the other one.
+    Alpha,
+    /// One minus the alpha of the other one.
+    InverseAlpha,
+}
+
+/// Factors of the source and the backdrop in the Porter-Duff composite modes
+/// that CSS lacks, which are drawn with masks.
+fn porter_duff(composite_mode: CompositeMode) -> Option<(Factor, Factor)> {
+    use Factor::*;
+    Some(match composite_mode {
+        CompositeMode::Clear => (Zero, Zero),
+        CompositeMode::Src => (One, Zero),
+        CompositeMode::Dest => (Zero, One),
+        CompositeMode::DestOver => (InverseAlpha, One),
+        CompositeMode::SrcIn => (Alpha, Zero),
+        CompositeMode::DestIn => (Zero, Alpha),
+        CompositeMode::SrcOut => (InverseAlpha, Zero),
+        CompositeMode::DestOut => (Zero, InverseAlpha),
+        CompositeMode::SrcAtop => (Alpha, InverseAlpha),
+        CompositeMode::DestAtop => (InverseAlpha, Alpha),
+        CompositeMode::Xor => (InverseAlpha, InverseAlpha),
+        _ => return None,
+    })
+}
+
+/// CSS blend mode of the composite mode, if it has one.
+fn blend_mode(composite_mode: CompositeMode) -> Option<&'static str> {
+    Some(match composite_mode {
+        CompositeMode::Plus => "plus-lighter",
+        CompositeMode::Screen => "screen",
+        CompositeMode::Overlay => "overlay",
+        CompositeMode::Darken => "darken",
+        CompositeMode::Lighten => "lighten",
+        CompositeMode::ColorDodge => "color-dodge",
+        CompositeMode::ColorBurn => "color-burn",
+        CompositeMode::HardLight => "hard-light",
+        CompositeMode::SoftLight => "soft-light",
+        CompositeMode::Difference => "difference",
+        CompositeMode::Exclusion => "exclusion",
+        CompositeMode::Multiply => "multiply",
+        CompositeMode::HslHue => "hue",
+        CompositeMode::HslSaturation => "saturation",
+        CompositeMode::HslColor => "color",
+        CompositeMode::HslLuminosity => "luminosity",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use read_fonts::FontRef;
+    use skrifa::{MetadataProvider, Tag};
+    use write_fonts::FontBuilder;
+
+    use super::*;
+    use crate::{
+        fixtures::{fixture, render_options},
+        renderer::{RenderFormat, RenderOptions, render_text},
+    };
+
+    fn render_svg(path: &Path, text: &str) -> String {
+        let content = render_text(text, render_options(path)).unwrap().unwrap();
+        String::from_utf8(content).unwrap()
+    }
+
+    /// A copy of Fira Mono with the table added, and the glyph ID of M.
+    fn build_font(
+        name: &str,
+        tag: Tag,
+        table: impl FnOnce(GlyphId, u16) -> Vec<u8>,
+    ) -> (PathBuf, GlyphId) {
+        let path = fixture("FiraMono-Medium.ttf");
+        let data = fs::read(&path).unwrap();
+        let font = FontRef::new(&data).unwrap();
+        let glyph_id = font.charmap().map('M').unwrap();
+        let table = table(glyph_id, font.maxp().unwrap().num_glyphs());
+
+        let mut font_builder = FontBuilder::new();
+        font_builder.add_raw(tag, table);
+        font_builder.copy_missing_tables(font);
+        let path = env::temp_dir().join(format!("figma-agent-{name}-{}.ttf", process::id()));
+        fs::write(&path, font_builder.build()).unwrap();
+        (path, glyph_id)
+    }
+
+    #[test]
+    fn test_render_color_glyphs() {
+        let path = fixture("colr_1.ttf");
+        // Linear gradients, then a sweep gradient in a glyph.
+        let text = "\u{F0100}\u{F0101}\u{F0200}";
+
+        let content = render_svg(&path, text);
+        assert_eq!(content.matches("<linearGradient").count(), 2);
+        assert!(content.matches("<path").count() > SWEEP_WEDGES);
+        assert_eq!(content.matches("stop-color=\"#ff0000\"").count(), 2);
+        assert_eq!(content.matches("style=\"isolation:isolate\"").count(), 3);
+        assert!(content.contains("fill=\"url(#gradient2)\""));
+
+        assert_eq!(blend_mode(CompositeMode::SrcOver), None);
+        assert_eq!(blend_mode(CompositeMode::Multiply), Some("multiply"));
+        assert_eq!(
+            svg_document(b"<?xml version=\"1.0\"?>\n<svg id=\"glyph1\"/>").as_deref(),
+            Some("<svg id=\"glyph1\"/>")
+        );
+    }
+
+    #[test]
+    fn test_render_sweep_gradient() {
+        let path = fixture("colr_1.ttf");
+
+        let content = render_svg(&path, "\u{F0200}");
+        let colors = content
+            .split("fill=\"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect::<HashSet<_>>();
+        assert!(colors.len() > 10);
+        // The clip box of the glyph, then its outline around the wedges.
+        assert_eq!(content.matches("clip-path=").count(), 2);
+    }
+
+    #[test]
+    fn test_render_composite_modes() {
+        let path = fixture("colr_1.ttf");
+        let render = |composite_mode: u32| {
+            render_svg(
+                &path,
+                &char::from_u32(0xF0A00 + composite_mode)
+                    .unwrap()
+                    .to_string(),
+            )
+        };
+
+        // Clear leaves out both layers, and Src and Dest one of them.
+        let paths = |content: &str| content.matches("<path").count();
+        let clear = paths(&render(0));
+        assert_eq!(paths(&render(1)), clear + 1);
+        assert_eq!(paths(&render(2)), clear + 1);
+        // SrcOver and the blend modes stack the layers.
+        assert!(!render(3).contains("<mask"));
+        assert!(render(23).contains("mix-blend-mode:multiply"));
+        // SrcIn masks the source with the alpha of the backdrop.
+        let content = render(5);
+        assert_eq!(content.matches("<mask").count(), 1);
+        assert!(content.contains("mask-type:alpha"));
+        // SrcOut masks it with the complement instead.
+        let content = render(7);
+        assert!(content.contains("<feColorMatrix"));
+        assert!(content.contains("fill=\"white\""));
+        // Xor adds both masked terms.
+        let content = render(11);
+        assert_eq!(content.matches("<mask").count(), 2);
+        assert!(content.contains("mix-blend-mode:plus-lighter"));
+
+        assert_eq!(porter_duff(CompositeMode::SrcOver), None);
+        assert_eq!(
+            porter_duff(CompositeMode::SrcAtop),
+            Some((Factor::Alpha, Factor::InverseAlpha))
+        );
+    }
+
+    #[test]
+    fn test_render_svg_glyphs() {
+        let (path, glyph_id) = build_font("svg", Tag::new(b"SVG "), |glyph_id, _| {
+            let document = format!(
+                "<?xml version=\"1.0\"?>\
+                 <svg xmlns=\"http://www.w3.org/2000/svg\">\
+                 <linearGradient id=\"fill\"><stop offset=\"0\" stop-color=\"red\"/></linearGradient>\
+                 <path id=\"glyph{0}\" fill=\"url(#fill)\" d=\"M0 0h500v-700H0z\"/>\
+                 </svg>",
+                glyph_id.to_u32()
+            );
+            let glyph_id = glyph_id.to_u32() as u16;
+            let mut table = Vec::new();
+            table.extend(0u16.to_be_bytes());
+            table.extend(10u32.to_be_bytes());
+            table.extend(0u32.to_be_bytes());
+            // Document list with one entry, the document right after it.
+            table.extend(1u16.to_be_bytes());
+            table.extend(glyph_id.to_be_bytes());
+            table.extend(glyph_id.to_be_bytes());
+            table.extend(14u32.to_be_bytes());
+            table.extend((document.len() as u32).to_be_bytes());
+            table.extend(document.as_bytes());
+            table
+        });
+
+        let content = render_svg(&path, "MM");
+        assert_eq!(content.matches("<svg xmlns").count(), 1);
+        let prefix = content
+            .split("<svg xmlns=\"http://www.w3.org/2000/svg\"><linearGradient id=\"")
+            .nth(1)
+            .unwrap();
+        let prefix = &prefix[..prefix.find("fill\"").unwrap()];
+        assert!(prefix.starts_with("svg"));
+        assert!(content.contains(&format!("fill=\"url(#{prefix}fill)\"")));
+        let href = format!("href=\"#{prefix}glyph{}\"", glyph_id.to_u32());
+        assert_eq!(content.matches(&href).count(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn render_png(path: &Path, text: &str) -> Pixmap {
+        let content = render_text(
+            text,
+            RenderOptions {
+                format: RenderFormat::Png,
+                pixel_ratio: 2.0,
+                ..render_options(path)
+            },
+        )
+        .unwrap()
+        .unwrap();
+        Pixmap::decode_png(&content).unwrap()
+    }
+
+    /// An sbix table with a red bitmap of M in a strike of 20 ppem.
+    fn sbix_table(glyph_id: GlyphId, num_glyphs: u16) -> Vec<u8> {
+        let mut bitmap = Pixmap::new(20, 20).unwrap();
+        bitmap.fill(tiny_skia::Color::from_rgba8(255, 0, 0, 255));
+        let png = bitmap.encode_png().unwrap();
+        let mut glyph_data = Vec::new();
+        glyph_data.extend(0i16.to_be_bytes());
+        glyph_data.extend(0i16.to_be_bytes());
+        glyph_data.extend(b"png ");
+        glyph_data.extend(&png);
+
+        let mut table = Vec::new();
+        table.extend(1u16.to_be_bytes());
+        table.extend(1u16.to_be_bytes());
+        table.extend(1u32.to_be_bytes());
+        table.extend(12u32.to_be_bytes());
+        // One strike at 20 ppem, with data for M only.
+        table.extend(20u16.to_be_bytes());
+        table.extend(72u16.to_be_bytes());
+        let data_offset = 4 + (num_glyphs as u32 + 1) * 4;
+        for id in 0..=num_glyphs as u32 {
+            let offset = match id.cmp(&glyph_id.to_u32()) {
+                std::cmp::Ordering::Greater => data_offset + glyph_data.len() as u32,
+                _ => data_offset,
+            };
+            table.extend(offset.to_be_bytes());
+        }
+        table.extend(&glyph_data);
+        table
+    }
+
+    #[test]
+    fn test_render_bitmap_glyphs() {
+        let (path, _) = build_font("sbix", Tag::new(b"sbix"), sbix_table);
+
+        let content = render_svg(&path, "MF");
+        assert_eq!(content.matches("<image").count(), 1);
+        assert!(content.contains("href=\"data:image/png;base64,"));
+        assert!(content.contains("<path"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_render_png_color_glyphs() {
+        let is_red = |pixel: tiny_skia::PremultipliedColorU8| {
+            pixel.alpha() == 255 && pixel.red() == 255 && pixel.green() == 0 && pixel.blue() == 0
+        };
+        let is_black = |pixel: tiny_skia::PremultipliedColorU8| {
+            pixel.alpha() == 255 && pixel.red() == 0 && pixel.green() == 0 && pixel.blue() == 0
+        };
+
+        // COLR layers and gradients are painted in their colors.
+        let path = fixture("colr_1.ttf");
+        let pixmap = render_png(&path, "\u{F0100}\u{F0200}");
+        let colors = pixmap
+            .pixels()
+            .iter()
+            .filter(|pixel| pixel.alpha() == 255)
+            .map(|pixel| (pixel.red(), pixel.green(), pixel.blue()))
+            .collect::<HashSet<_>>();
+        assert!(colors.len() > 10);
+        assert!(
+            colors
+                .iter()
+                .any(|&(red, green, blue)| red > 240 && green == 0 && blue < 16)
+        );
+
+        // Bitmaps are drawn over the outlines of the other glyphs.
+        let (path, _) = build_font("sbix-png", Tag::new(b"sbix"), sbix_table);
+        let pixmap = render_png(&path, "MF");
+        assert!(pixmap.pixels().iter().copied().any(is_red));
+        assert!(pixmap.pixels().iter().copied().any(is_black));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_namespace_ids() {
+        assert_eq!(
+            namespace_ids(
+                "<svg><g id=\"a\" grid=\"b\"><use xlink:href=\"#a\" fill=\"url(#c)\" \
+                 style=\"fill:url('#d')\"/></g></svg>",
+                "svg1-"
+            ),
+            "<svg><g id=\"svg1-a\" grid=\"b\"><use xlink:href=\"#svg1-a\" fill=\"url(#svg1-c)\" \
+             style=\"fill:url('#svg1-d')\"/></g></svg>"
+        );
+    }
+}
@@ -1,12 +1,18 @@
 use std::{
-    fs, io, iter,
+    env, fmt, fs, io, iter,
+    num::NonZeroU64,
     path::{Path, PathBuf},
 };
 
 use fontconfig_parser::FontConfig;
 use itertools::{Either, Itertools};
 
-use crate::path::expand_home;
+use crate::{
+    path::expand_home,
+    preview::PreviewFallback,
+    profile::{ClientProfile, Profile},
+    scanner::{FontDirectory, FontSource},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -18,8 +24,8 @@ pub enum ConfigError {
 
 #[derive(Debug, Clone, Eq, PartialEq, serde::Deserialize)]
 pub struct Config {
-    #[serde(default = "default_bind")]
-    pub bind: String,
+    #[serde(default = "default_bind", deserialize_with = "bind_addresses")]
+    pub bind: Vec<BindAddress>,
     #[serde(default = "default_bool::<true>")]
     pub use_system_fonts: bool,
     #[serde(default)]
@@ -28,13 +34,157 @@ pub struct Config {
     pub enable_font_rescan: bool,
     #[serde(default = "default_bool::<true>")]
     pub enable_font_preview: bool,
+    #[serde(default = "default_bool::<true>")]
+    pub enable_compression: bool,
+    #[serde(default)]
+    pub client_profile: ClientProfile,
+    #[serde(default)]
+    pub user_installed: Option<UserInstalled>,
+    #[serde(default)]
+    pub cache_control: CacheControl,
+    #[serde(default = "default_preview_fallbacks")]
+    pub preview_fallbacks: Vec<PreviewFallback>,
+    #[serde(default)]
+    pub fallback_font: Option<PathBuf>,
+    #[serde(default = "default_preview_cache_size")]
+    pub preview_cache_size: usize,
+    #[serde(default)]
+    pub svg_precision: Option<u8>,
+    /// Seconds without requests after which a socket-activated service exits.
+    #[serde(default)]
+    pub idle_timeout: Option<NonZeroU64>,
+}
+
+/// An address to listen on, either `host:port` or `unix:` followed by the path
+/// of a Unix domain socket.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum BindAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = String;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        let address = match address.strip_prefix("unix:") {
+            Some(path) => BindAddress::Unix(path.into()),
+            None => BindAddress::Tcp(address),
+        };
+        match &address {
+            BindAddress::Tcp(host) if host.is_empty() => {
+                Err("bind address must not be empty".into())
+            }
+            BindAddress::Unix(path) if path.as_os_str().is_empty() => {
+                Err("bind socket path must not be empty".into())
+            }
+            _ => Ok(address),
+        }
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(address) => f.write_str(address),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// `Cache-Control` header values of the font endpoints.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Deserialize)]
+pub struct CacheControl {
+    #[serde(default = "default_font_file_cache_control")]
+    pub font_file: String,
+    #[serde(default = "default_font_preview_cache_control")]
+    pub font_preview: String,
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        serde_json::from_value(serde_json::json!({})).unwrap()
+    }
+}
+
+/// How the `user_installed` flag of the font list is computed.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserInstalled {
+    /// Fonts from `font_directories` or from Fontconfig directories under the
+    /// home directory are user-installed; other Fontconfig fonts are not.
+    #[default]
+    Auto,
+    /// Every font is reported as user-installed.
+    Always,
+    /// No font is reported as user-installed.
+    Never,
 }
 
-fn default_bind() -> String {
-    "127.0.0.1:44950".into()
+impl UserInstalled {
+    pub fn resolve(&self, source: FontSource) -> bool {
+        match self {
+            UserInstalled::Auto => source != FontSource::System,
+            UserInstalled::Always => true,
+            UserInstalled::Never => false,
+        }
+    }
+}
+
+fn default_bind() -> Vec<BindAddress> {
+    vec![BindAddress::Tcp("127.0.0.1:44950".into())]
+}
+
+/// Accept a single address in place of a list, but not an empty list, which
+/// would leave nothing to listen on.
+fn bind_addresses<'de, D>(deserializer: D) -> Result<Vec<BindAddress>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let addresses = one_or_many(deserializer)?;
+    if addresses.is_empty() {
+        return Err(serde::de::Error::custom("bind must not be empty"));
+    }
+    Ok(addresses)
+}
+
+/// Accept a single value in place of a list.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+fn default_font_file_cache_control() -> String {
+    "no-cache".into()
+}
+
+fn default_font_preview_cache_control() -> String {
+    "max-age=300".into()
 }
 
-fn default_bool<const V: bool>() -> bool {
+fn default_preview_fallbacks() -> Vec<PreviewFallback> {
+    PreviewFallback::ALL.into()
+}
+
+fn default_preview_cache_size() -> usize {
+    1024
+}
+
+pub(crate) fn default_bool<const V: bool>() -> bool {
     V
 }
 
@@ -60,32 +210,66 @@ impl Config {
 }
 
 impl Config {
+    pub fn profile(&self) -> Profile {
+        let mut profile = self.client_profile.profile();
+        if let Some(user_installed) = self.user_installed {
+            profile.user_installed = user_installed;
+        }
+        profile
+    }
+
+    pub fn fallback_font(&self) -> Option<PathBuf> {
+        let path = self.fallback_font.as_ref()?;
+        expand_home(path)
+            .inspect_err(|error| {
+                tracing::debug!("Skipped fallback font: {path:?}, error: {error:?}");
+            })
+            .ok()
+    }
+
     pub fn effective_font_directories(
         &self,
         fontconfig: &FontConfig,
-    ) -> impl Iterator<Item = PathBuf> {
+    ) -> impl Iterator<Item = FontDirectory> {
+        let home_directory = env::home_dir().and_then(|directory| directory.canonicalize().ok());
+
         self.font_directories
             .iter()
             .filter_map(|directory| match expand_home(directory) {
-                Ok(directory) => Some(directory),
+                Ok(directory) => Some((directory, FontSource::Configured)),
                 Err(error) => {
                     tracing::debug!("Skipped font directory: {directory:?}, error: {error:?}");
                     None
                 }
             })
             .chain(if self.use_system_fonts {
-                Either::Left(fontconfig.dirs.iter().map(|dir| dir.path.clone()))
+                Either::Left(
+                    fontconfig
+                        .dirs
+                        .iter()
+                        .map(|dir| (dir.path.clone(), FontSource::System)),
+                )
             } else {
                 Either::Right(iter::empty())
             })
-            .filter_map(|directory| match directory.canonicalize() {
-                Ok(directory) => Some(directory),
+            .filter_map(move |(directory, source)| match directory.canonicalize() {
+                Ok(path) => Some(FontDirectory {
+                    source: match (source, &home_directory) {
+                        (FontSource::System, Some(home_directory))
+                            if path.starts_with(home_directory) =>
+                        {
+                            FontSource::User
+                        }
+                        _ => source,
+                    },
+                    path,
+                }),
                 Err(error) => {
                     tracing::debug!("Skipped font directory: {directory:?}, error: {error:?}");
                     None
                 }
             })
-            .unique()
+            .unique_by(|directory| directory.path.clone())
     }
 }
 
@@ -100,11 +284,28 @@ mod tests {
         assert_eq!(
             Config::default(),
             Config {
-                bind: "127.0.0.1:44950".into(),
+                bind: vec![BindAddress::Tcp("127.0.0.1:44950".into())],
                 use_system_fonts: true,
                 font_directories: vec![],
                 enable_font_rescan: true,
                 enable_font_preview: true,
+                enable_compression: true,
+                client_profile: ClientProfile::WindowsLatest,
+                user_installed: None,
+                cache_control: CacheControl {
+                    font_file: "no-cache".into(),
+                    font_preview: "max-age=300".into(),
+                },
+                preview_fallbacks: vec![
+                    PreviewFallback::SampleText,
+                    PreviewFallback::ScriptSample,
+                    PreviewFallback::LocalizedName,
+                    PreviewFallback::FallbackFont,
+                ],
+                fallback_font: None,
+                preview_cache_size: 1024,
+                svg_precision: None,
+                idle_timeout: None,
             },
         );
     }
@@ -115,16 +316,49 @@ mod tests {
         assert_eq!(Config::parse("{} // comment").unwrap(), Config::default());
         assert_eq!(
             Config::parse(
-                r#"{ "bind": "0.0.0.0:44950", "use_system_fonts": false, "font_directories": ["/usr/share/fonts"], "enable_font_rescan": false, "enable_font_preview": false }"#,
+                r#"{ "bind": "0.0.0.0:44950", "use_system_fonts": false, "font_directories": ["/usr/share/fonts"], "enable_font_rescan": false, "enable_font_preview": false, "enable_compression": false, "client_profile": { "custom": { "package": "126.0.0", "version": 24 } }, "user_installed": "never", "cache_control": { "font_preview": "no-store" }, "preview_fallbacks": ["fallback_font", "sample_text"], "fallback_font": "/usr/share/fonts/noto/NotoSans-Regular.ttf", "preview_cache_size": 0, "svg_precision": 1, "idle_timeout": 600 }"#,
             )
             .unwrap(),
             Config {
-                bind: "0.0.0.0:44950".into(),
+                bind: vec![BindAddress::Tcp("0.0.0.0:44950".into())],
                 use_system_fonts: false,
                 font_directories: vec![PathBuf::from("/usr/share/fonts")],
                 enable_font_rescan: false,
                 enable_font_preview: false,
+                enable_compression: false,
+                client_profile: ClientProfile::Custom(Profile {
+                    package: "126.0.0".into(),
+                    version: 24,
+                    variation_axes: true,
+                    user_installed: UserInstalled::Auto,
+                }),
+                user_installed: Some(UserInstalled::Never),
+                cache_control: CacheControl {
+                    font_file: "no-cache".into(),
+                    font_preview: "no-store".into(),
+                },
+                preview_fallbacks: vec![PreviewFallback::FallbackFont, PreviewFallback::SampleText],
+                fallback_font: Some(PathBuf::from("/usr/share/fonts/noto/NotoSans-Regular.ttf")),
+                preview_cache_size: 0,
+                svg_precision: Some(1),
+                idle_timeout: NonZeroU64::new(600),
             },
         );
+        assert_eq!(
+            Config::parse(
+                r#"{ "bind": ["127.0.0.1:44950", "[::1]:44950", "unix:/run/figma-agent.sock"] }"#
+            )
+            .unwrap()
+            .bind,
+            [
+                BindAddress::Tcp("127.0.0.1:44950".into()),
+                BindAddress::Tcp("[::1]:44950".into()),
+                BindAddress::Unix("/run/figma-agent.sock".into()),
+            ],
+        );
+        for bind in ["[]", "\"\"", "\"unix:\"", "[\"127.0.0.1:44950\", \"\"]"] {
+            assert!(Config::parse(format!(r#"{{ "bind": {bind} }}"#)).is_err());
+        }
+        assert!(Config::parse(r#"{ "idle_timeout": 0 }"#).is_err());
     }
 }
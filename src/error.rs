@@ -0,0 +1,188 @@
+use std::{path::PathBuf, time::Duration};
+
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    collection::ExtractError, font::FontError, instancer::InstanceError, payload::ErrorPayload,
+    renderer::RenderError, subsetter::SubsetError,
+};
+
+/// An error returned by the HTTP endpoints, rendered as a JSON problem-details body.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Font preview is disabled")]
+    PreviewDisabled,
+    #[error("Font file not found: {0:?}")]
+    FontNotFound(PathBuf),
+    #[error("Font face not found: {0}")]
+    FaceNotFound(String),
+    #[error("Named instance not found: {0:?}")]
+    InstanceNotFound(String),
+    #[error("Font is not a variable font")]
+    NotVariable,
+    #[error("Unknown variation axis: {0}")]
+    UnknownAxis(String),
+    #[error("Font has no glyphs for some characters of the text")]
+    GlyphsMissing,
+    #[error("Glyph not found: {0}")]
+    GlyphNotFound(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("Font preview is too large: {0}")]
+    PreviewTooLarge(String),
+    #[error("Too many font previews are being rendered")]
+    PreviewBusy,
+    #[error("Font preview timed out after {0:?}")]
+    PreviewTimeout(Duration),
+    #[error("Failed to load font")]
+    Font(#[from] FontError),
+    #[error("Failed to render font preview")]
+    Render(#[source] RenderError),
+    #[error("Failed to build font instance")]
+    Instance(#[source] InstanceError),
+    #[error("Failed to extract font face")]
+    Extract(#[from] ExtractError),
+    #[error("Failed to subset font")]
+    Subset(#[from] SubsetError),
+    #[error("Internal error")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<InstanceError> for ApiError {
+    fn from(error: InstanceError) -> Self {
+        match error {
+            InstanceError::NotVariable => ApiError::NotVariable,
+            InstanceError::UnknownAxis(tag) => ApiError::UnknownAxis(tag),
+            error => ApiError::Instance(error),
+        }
+    }
+}
+
+impl From<RenderError> for ApiError {
+    fn from(error: RenderError) -> Self {
+        match error {
+            RenderError::TooLarge(detail) => ApiError::PreviewTooLarge(detail),
+            error => ApiError::Render(error),
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for ApiError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        ApiError::Internal(error.into())
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::PreviewDisabled
+            | ApiError::FontNotFound(_)
+            | ApiError::FaceNotFound(_)
+            | ApiError::InstanceNotFound(_)
+            | ApiError::GlyphsMissing
+            | ApiError::GlyphNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::NotVariable | ApiError::UnknownAxis(_) | ApiError::InvalidQuery(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            ApiError::PreviewTooLarge(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::PreviewBusy | ApiError::PreviewTimeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Font(_)
+            | ApiError::Render(_)
+            | ApiError::Instance(_)
+            | ApiError::Extract(_)
+            | ApiError::Subset(_)
+            | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// A machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::PreviewDisabled => "preview_disabled",
+            ApiError::FontNotFound(_) => "font_not_found",
+            ApiError::FaceNotFound(_) => "face_not_found",
+            ApiError::InstanceNotFound(_) => "instance_not_found",
+            ApiError::NotVariable => "not_variable",
+            ApiError::UnknownAxis(_) => "unknown_axis",
+            ApiError::GlyphsMissing => "glyphs_missing",
+            ApiError::GlyphNotFound(_) => "glyph_not_found",
+            ApiError::InvalidQuery(_) => "invalid_query",
+            ApiError::PreviewTooLarge(_) => "preview_too_large",
+            ApiError::PreviewBusy => "preview_busy",
+            ApiError::PreviewTimeout(_) => "preview_timeout",
+            ApiError::Font(_) => "font_invalid",
+            ApiError::Render(_) => "render_failed",
+            ApiError::Instance(_) => "instance_failed",
+            ApiError::Extract(_) => "extract_failed",
+            ApiError::Subset(_) => "subset_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// The error message followed by the messages of its sources.
+    pub fn detail(&self) -> String {
+        let mut detail = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            detail.push_str(&format!(": {error}"));
+            source = error.source();
+        }
+        detail
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("{self}, error: {self:?}");
+        } else {
+            tracing::debug!("{self}");
+        }
+
+        let mut response = (
+            status,
+            Json(ErrorPayload {
+                type_: "about:blank".into(),
+                title: status.canonical_reason().unwrap_or_default().into(),
+                status: status.as_u16(),
+                detail: self.detail(),
+                code: self.code().into(),
+            }),
+        )
+            .into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_response() {
+        let response = ApiError::from(InstanceError::UnknownAxis("wdth".into())).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/problem+json"
+        );
+
+        let error = ApiError::GlyphsMissing;
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error.code(), "glyphs_missing");
+
+        let error = ApiError::from(RenderError::TooLarge("text exceeds 4096 glyphs".into()));
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code(), "preview_too_large");
+    }
+}
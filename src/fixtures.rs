@@ -0,0 +1,30 @@
+use std::path::{Path, PathBuf};
+
+use crate::renderer::{RenderFormat, RenderOptions};
+
+/// Directory of the fonts used by tests.
+pub fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fonts")
+}
+
+/// Path of a font in `tests/fixtures/fonts`.
+pub fn fixture(name: &str) -> PathBuf {
+    fixtures_dir().join(name)
+}
+
+/// Options that render the first face of the font as SVG at 32px, with no
+/// variations, features, language or fallback font.
+pub fn render_options(font: &Path) -> RenderOptions<'_> {
+    RenderOptions {
+        font: (font, 0),
+        size: 32.0,
+        named_instance_index: None,
+        variations: &[],
+        features: &[],
+        language: None,
+        format: RenderFormat::Svg,
+        pixel_ratio: 1.0,
+        svg_precision: None,
+        fallback_font: None,
+    }
+}
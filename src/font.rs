@@ -7,6 +7,8 @@ use std::{
 use interp::{InterpMode, interp};
 use skrifa::{MetadataProvider, string::StringId};
 
+use crate::scanner::FontSource;
+
 #[derive(Debug, thiserror::Error)]
 pub enum FontError {
     #[error("Failed to read font file")]
@@ -20,6 +22,7 @@ pub struct FontFile {
     pub path: PathBuf,
     pub fonts: Vec<Font>,
     pub modified_at: Option<SystemTime>,
+    pub source: FontSource,
 }
 
 impl FontFile {
@@ -45,6 +48,7 @@ impl FontFile {
             path: path.into(),
             fonts,
             modified_at: metadata.modified().ok(),
+            source: FontSource::default(),
         };
 
         if errors.is_empty() {
@@ -114,6 +118,7 @@ pub struct Font {
     pub family_name: Option<String>,
     pub subfamily_name: Option<String>,
     pub postscript_name: Option<String>,
+    pub version: Option<String>,
     pub weight: f32,
     pub width: f32,
     pub is_italic: bool,
@@ -135,6 +140,7 @@ impl Font {
                 .string(StringId::TYPOGRAPHIC_SUBFAMILY_NAME)
                 .or_else(|| font.string(StringId::SUBFAMILY_NAME)),
             postscript_name: font.string(StringId::POSTSCRIPT_NAME),
+            version: font.string(StringId::VERSION_STRING),
             weight: attributes.weight.value(),
             width: attributes.stretch.percentage(),
             is_italic: matches!(attributes.style, skrifa::attribute::Style::Italic),
@@ -0,0 +1,280 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Write},
+};
+
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl ContentEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+}
+
+/// Pick the preferred encoding accepted by the client, favoring Brotli over gzip.
+pub fn negotiate_encoding(headers: &HeaderMap) -> ContentEncoding {
+    let accepted = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let coding = parts.next()?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some(coding)
+        })
+        .collect::<Vec<_>>();
+
+    if accepted
+        .iter()
+        .any(|coding| coding.eq_ignore_ascii_case("br"))
+    {
+        ContentEncoding::Brotli
+    } else if accepted
+        .iter()
+        .any(|coding| coding.eq_ignore_ascii_case("gzip"))
+    {
+        ContentEncoding::Gzip
+    } else {
+        ContentEncoding::Identity
+    }
+}
+
+/// Check whether the `Accept` header explicitly lists the given media type.
+pub fn accepts_media_type(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let quality = parts
+                .clone()
+                .skip(1)
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            parts
+                .next()
+                .is_some_and(|item| item.eq_ignore_ascii_case(media_type))
+                && quality > 0.0
+        })
+}
+
+/// Build a strong ETag from the given identity.
+pub fn strong_etag(identity: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    identity.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Check whether the `If-None-Match` header matches the given ETag.
+///
+/// https://www.rfc-editor.org/rfc/rfc9110#section-13.1.2
+pub fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|item| item == "*" || item.strip_prefix("W/").unwrap_or(item) == etag)
+}
+
+/// Build the `ETag` and `Cache-Control` headers of a cacheable response.
+pub fn cache_headers(etag: &str, cache_control: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(etag) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    match HeaderValue::from_str(cache_control) {
+        Ok(cache_control) => {
+            headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+        Err(error) => {
+            tracing::warn!("Invalid Cache-Control value: {cache_control:?}, error: {error:?}");
+        }
+    }
+    headers
+}
+
+/// Brotli quality of pre-compressed bodies. The highest qualities take seconds on
+/// a large payload for a few percent smaller output.
+const BROTLI_QUALITY: i32 = 5;
+
+/// A response body serialized once, optionally pre-compressed, and served with a strong ETag.
+#[derive(Debug, Clone)]
+pub struct EncodedBody {
+    pub content_type: &'static str,
+    pub etag: String,
+    pub identity: Bytes,
+    pub gzip: Option<Bytes>,
+    pub brotli: Option<Bytes>,
+}
+
+impl EncodedBody {
+    pub fn new(content_type: &'static str, body: Vec<u8>, compress: bool) -> io::Result<Self> {
+        let etag = strong_etag(&body);
+
+        let (gzip, brotli) = if compress {
+            let mut gzip =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gzip.write_all(&body)?;
+
+            let mut brotli = Vec::new();
+            brotli::BrotliCompress(
+                &mut body.as_slice(),
+                &mut brotli,
+                &brotli::enc::BrotliEncoderParams {
+                    quality: BROTLI_QUALITY,
+                    ..Default::default()
+                },
+            )?;
+
+            (Some(gzip.finish()?.into()), Some(brotli.into()))
+        } else {
+            (None, None)
+        };
+
+        Ok(EncodedBody {
+            content_type,
+            etag,
+            identity: body.into(),
+            gzip,
+            brotli,
+        })
+    }
+
+    pub fn respond(&self, headers: &HeaderMap) -> Response {
+        let etag = HeaderValue::from_str(&self.etag).expect("ETag should be a valid header value");
+
+        let vary = (header::VARY, HeaderValue::from_static("accept-encoding"));
+        if etag_matches(headers, &self.etag) {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag), vary]).into_response();
+        }
+
+        let (encoding, body) = match negotiate_encoding(headers) {
+            ContentEncoding::Brotli if self.brotli.is_some() => {
+                (ContentEncoding::Brotli, self.brotli.clone())
+            }
+            ContentEncoding::Brotli | ContentEncoding::Gzip if self.gzip.is_some() => {
+                (ContentEncoding::Gzip, self.gzip.clone())
+            }
+            _ => (ContentEncoding::Identity, None),
+        };
+        let body = body.unwrap_or_else(|| self.identity.clone());
+
+        let mut response = (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.content_type),
+                ),
+                (header::ETAG, etag),
+                vary,
+            ],
+            body,
+        )
+            .into_response();
+        if encoding != ContentEncoding::Identity {
+            response.headers_mut().insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(name, HeaderValue::from_static(value))])
+    }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        assert_eq!(
+            negotiate_encoding(&HeaderMap::new()),
+            ContentEncoding::Identity
+        );
+        assert_eq!(
+            negotiate_encoding(&headers(header::ACCEPT_ENCODING, "gzip, deflate")),
+            ContentEncoding::Gzip
+        );
+        assert_eq!(
+            negotiate_encoding(&headers(header::ACCEPT_ENCODING, "gzip, deflate, br, zstd")),
+            ContentEncoding::Brotli
+        );
+        assert_eq!(
+            negotiate_encoding(&headers(header::ACCEPT_ENCODING, "br;q=0, gzip;q=0.5")),
+            ContentEncoding::Gzip
+        );
+    }
+
+    #[test]
+    fn test_accepts_media_type() {
+        let accept = headers(
+            header::ACCEPT,
+            "image/png, image/*;q=0.8, image/svg+xml;q=0",
+        );
+        assert!(accepts_media_type(&accept, "image/png"));
+        assert!(!accepts_media_type(&accept, "image/svg+xml"));
+        assert!(!accepts_media_type(&accept, "image/webp"));
+        assert!(!accepts_media_type(&HeaderMap::new(), "image/png"));
+    }
+
+    #[test]
+    fn test_encoded_body() {
+        let body = EncodedBody::new("application/json", b"{}".repeat(1000), true).unwrap();
+        assert!(body.brotli.as_ref().unwrap().len() < body.identity.len());
+
+        let response = body.respond(&headers(header::ACCEPT_ENCODING, "gzip"));
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        // Caches keep the encodings apart on revalidation too.
+        let response = body.respond(&headers(header::IF_NONE_MATCH, "*"));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches(
+            &headers(header::IF_NONE_MATCH, "\"a\", \"b\""),
+            "\"b\""
+        ));
+        assert!(etag_matches(
+            &headers(header::IF_NONE_MATCH, "W/\"a\""),
+            "\"a\""
+        ));
+        assert!(etag_matches(&headers(header::IF_NONE_MATCH, "*"), "\"a\""));
+        assert!(!etag_matches(
+            &headers(header::IF_NONE_MATCH, "\"a\""),
+            "\"b\""
+        ));
+        assert!(!etag_matches(&HeaderMap::new(), "\"a\""));
+    }
+}
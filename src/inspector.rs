@@ -0,0 +1,270 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use kurbo::Shape;
+use read_fonts::{
+    TableProvider,
+    tables::glyf::{Glyf, Glyph},
+    tables::loca::Loca,
+};
+use skrifa::{
+    FontRef, GlyphId, GlyphNameSource, GlyphNames, MetadataProvider, charmap::Charmap,
+    instance::Size, outline::DrawSettings,
+};
+use svg::{Document, node::element};
+
+use crate::{
+    payload::GlyphPayload,
+    renderer::{GlyphPen, OutlinePath, RenderError, RunFont, TextPath},
+};
+
+/// How a glyph is looked up in the font.
+#[derive(Debug, Clone)]
+pub enum GlyphSelector {
+    Id(u32),
+    Name(String),
+    Codepoint(u32),
+}
+
+impl fmt::Display for GlyphSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlyphSelector::Id(glyph_id) => write!(f, "glyph ID {glyph_id}"),
+            GlyphSelector::Name(name) => write!(f, "name {name:?}"),
+            GlyphSelector::Codepoint(codepoint) => write!(f, "U+{codepoint:04X}"),
+        }
+    }
+}
+
+/// Reads the data of individual glyphs of a face, in font units.
+pub struct GlyphInspector<'a> {
+    font: RunFont<'a>,
+    names: GlyphNames<'a>,
+    charmap: Charmap<'a>,
+    codepoints: HashMap<GlyphId, Vec<u32>>,
+    ascent: f32,
+    descent: f32,
+    truetype: Option<(Loca<'a>, Glyf<'a>)>,
+}
+
+impl<'a> GlyphInspector<'a> {
+    pub fn new(font: FontRef<'a>, named_instance_index: Option<usize>) -> Self {
+        let run_font = RunFont::new(font.clone(), named_instance_index, &[]);
+        let charmap = font.charmap();
+        let mut codepoints = HashMap::<GlyphId, Vec<u32>>::new();
+        for (codepoint, glyph_id) in charmap.mappings() {
+            codepoints.entry(glyph_id).or_default().push(codepoint);
+        }
+        let metrics = font.metrics(Size::unscaled(), &run_font.location);
+
+        GlyphInspector {
+            names: font.glyph_names(),
+            charmap,
+            codepoints,
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            truetype: font.loca(None).ok().zip(font.glyf().ok()),
+            font: run_font,
+        }
+    }
+
+    pub fn glyph_count(&self) -> u32 {
+        self.names.num_glyphs()
+    }
+
+    pub fn find(&self, selector: &GlyphSelector) -> Option<GlyphId> {
+        match selector {
+            GlyphSelector::Id(glyph_id) => {
+                (*glyph_id < self.glyph_count()).then_some(GlyphId::new(*glyph_id))
+            }
+            GlyphSelector::Name(name) => self
+                .names
+                .iter()
+                .find(|(_, glyph_name)| glyph_name.as_str() == name)
+                .map(|(glyph_id, _)| glyph_id),
+            GlyphSelector::Codepoint(codepoint) => self.charmap.map(*codepoint),
+        }
+    }
+
+    pub fn inspect(&self, glyph_id: GlyphId) -> Result<GlyphPayload, RenderError> {
+        // Glyphs of bitmap-only fonts have no outline, so they are inspected as empty.
+        let outline = self.font.outlines.get(glyph_id);
+        let settings = || DrawSettings::unhinted(Size::unscaled(), &self.font.location);
+
+        let mut outline_path = OutlinePath::default();
+        if let Some(outline) = &outline {
+            outline.draw(settings(), &mut outline_path)?;
+        }
+        // The outline path points down, so flip the bounds back into font units.
+        let bounds = Some(outline_path.into_path().bounding_box())
+            .filter(|bounds| !bounds.is_zero_area())
+            .map(|bounds| {
+                [
+                    bounds.x0 as f32,
+                    -bounds.y1 as f32,
+                    bounds.x1 as f32,
+                    -bounds.y0 as f32,
+                ]
+            });
+
+        let advance_width = self
+            .font
+            .font
+            .glyph_metrics(Size::unscaled(), &self.font.location)
+            .advance_width(glyph_id)
+            .unwrap_or_default();
+        let (left_side_bearing, right_side_bearing) = match bounds {
+            Some([x_min, _, x_max, _]) => (x_min, advance_width - x_max),
+            None => (0.0, advance_width),
+        };
+
+        // Frame the advance box from the ascent to the descent, grown to fit the
+        // outline where it extends beyond it.
+        let [x_min, y_min, x_max, y_max] =
+            bounds.unwrap_or([0.0, self.descent, advance_width, self.ascent]);
+        let (x0, x1) = (x_min.min(0.0), x_max.max(advance_width));
+        let (y0, y1) = (y_min.min(self.descent), y_max.max(self.ascent));
+        let mut text_path = TextPath::new();
+        text_path.set_origin(0.0, y1);
+        if let Some(outline) = &outline {
+            outline.draw(settings(), &mut text_path)?;
+        }
+        let svg = Document::new()
+            .set("viewBox", (x0, 0.0, x1 - x0, y1 - y0))
+            .add(element::Path::new().set("d", text_path.into_data()))
+            .to_string();
+
+        let name = match self.names.source() {
+            GlyphNameSource::Synthesized => None,
+            _ => self.names.get(glyph_id).map(|name| name.to_string()),
+        };
+
+        Ok(GlyphPayload {
+            id: glyph_id.to_u32(),
+            name,
+            codepoints: self.codepoints.get(&glyph_id).cloned().unwrap_or_default(),
+            advance_width,
+            left_side_bearing,
+            right_side_bearing,
+            bounds,
+            hinted: self.is_hinted(glyph_id),
+            svg,
+        })
+    }
+
+    /// Whether the glyph has TrueType instructions. Hints of CFF outlines aren't
+    /// inspected.
+    fn is_hinted(&self, glyph_id: GlyphId) -> Option<bool> {
+        let (loca, glyf) = self.truetype.as_ref()?;
+        Some(match loca.get_glyf(glyph_id, glyf).ok()? {
+            Some(Glyph::Simple(glyph)) => !glyph.instructions().is_empty(),
+            Some(Glyph::Composite(glyph)) => glyph
+                .instructions()
+                .is_some_and(|instructions| !instructions.is_empty()),
+            None => false,
+        })
+    }
+}
+
+/// Inspect the glyph of the face the selector points to, if any.
+pub fn inspect_glyph(
+    (font_path, font_index): (&Path, usize),
+    named_instance_index: Option<usize>,
+    selector: &GlyphSelector,
+) -> Result<Option<GlyphPayload>, RenderError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+    let inspector = GlyphInspector::new(font, named_instance_index);
+
+    inspector
+        .find(selector)
+        .map(|glyph_id| inspector.inspect(glyph_id))
+        .transpose()
+}
+
+/// Inspect up to `limit` glyphs of the face starting at the glyph `offset`,
+/// returning them along with the number of glyphs in the face.
+pub fn inspect_glyphs(
+    (font_path, font_index): (&Path, usize),
+    named_instance_index: Option<usize>,
+    offset: u32,
+    limit: u32,
+) -> Result<(u32, Vec<GlyphPayload>), RenderError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+    let inspector = GlyphInspector::new(font, named_instance_index);
+
+    let glyph_count = inspector.glyph_count();
+    let glyphs = (offset..offset.saturating_add(limit).min(glyph_count))
+        .map(|glyph_id| inspector.inspect(GlyphId::new(glyph_id)))
+        .collect::<Result<_, _>>()?;
+    Ok((glyph_count, glyphs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use skrifa::Tag;
+    use write_fonts::FontBuilder;
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn test_inspect_glyph() {
+        let path = fixture("FiraMono-Medium.ttf");
+
+        let glyph = inspect_glyph((&path, 0), None, &GlyphSelector::Codepoint('F' as u32))
+            .unwrap()
+            .unwrap();
+        assert_eq!(glyph.codepoints, ['F' as u32]);
+        assert_eq!(glyph.advance_width, 600.0);
+        let [x_min, _, x_max, _] = glyph.bounds.unwrap();
+        assert_eq!(glyph.left_side_bearing, x_min);
+        assert_eq!(glyph.right_side_bearing, 600.0 - x_max);
+        assert!(glyph.svg.starts_with("<svg"));
+
+        let by_id = inspect_glyph((&path, 0), None, &GlyphSelector::Id(glyph.id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(by_id.codepoints, glyph.codepoints);
+        assert!(
+            inspect_glyph((&path, 0), None, &GlyphSelector::Codepoint('Z' as u32))
+                .unwrap()
+                .is_none()
+        );
+
+        let (glyph_count, glyphs) = inspect_glyphs((&path, 0), None, 2, 10).unwrap();
+        assert_eq!(glyphs.len(), 10);
+        assert_eq!(glyphs[0].id, 2);
+        let (_, glyphs) = inspect_glyphs((&path, 0), None, glyph_count - 3, 10).unwrap();
+        assert_eq!(glyphs.len(), 3);
+    }
+
+    #[test]
+    fn test_inspect_glyphs_without_outlines() {
+        let path = fixture("FiraMono-Medium.ttf");
+        let data = fs::read(&path).unwrap();
+        let font = FontRef::new(&data).unwrap();
+
+        // A copy of the font without outlines, like a bitmap-only font.
+        let mut font_builder = FontBuilder::new();
+        for record in font.table_directory().table_records() {
+            let tag = record.tag();
+            if tag != Tag::new(b"glyf") && tag != Tag::new(b"loca") {
+                font_builder.add_raw(tag, font.data_for_tag(tag).unwrap());
+            }
+        }
+        let path = env::temp_dir().join(format!("figma-agent-inspector-{}.ttf", process::id()));
+        fs::write(&path, font_builder.build()).unwrap();
+
+        let (_, glyphs) = inspect_glyphs((&path, 0), None, 0, 100).unwrap();
+        assert_eq!(glyphs.len(), 100);
+        for glyph in &glyphs {
+            assert_eq!(glyph.bounds, None);
+            assert!(glyph.svg.starts_with("<svg"));
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1241 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use kurbo::{BezPath, CubicBez, PathEl, Point};
+use read_fonts::{
+    TableProvider,
+    tables::{
+        glyf::Glyf,
+        gvar::Gvar,
+        loca::Loca,
+        name::Encoding,
+        os2::SelectionFlags,
+        variations::{DeltaSetIndex, ItemVariationStore},
+        vmtx::Vmtx,
+        vvar::Vvar,
+    },
+    types::{F2Dot14, NameId},
+};
+use skrifa::{
+    FontRef, GlyphId, MetadataProvider, Tag,
+    instance::{LocationRef, Size},
+    outline::{DrawError, DrawSettings, OutlineGlyph, OutlinePen},
+    string::StringId,
+};
+use write_fonts::{
+    FontBuilder, NullableOffsetMarker, OffsetMarker, OtRound,
+    from_obj::ToOwnedTable,
+    tables::{
+        gdef::{CaretValue, Gdef},
+        glyf::{Bbox, GlyfLocaBuilder, Glyph, MalformedPath, SimpleGlyph},
+        gpos::{
+            AnchorTable, CursivePosFormat1, ExtensionSubtable, Gpos, MarkArray, MarkBasePosFormat1,
+            MarkLigPosFormat1, MarkMarkPosFormat1, PairPos, PositionLookup, SinglePos, ValueFormat,
+            ValueRecord,
+        },
+        gsub::Gsub,
+        head::{Head, MacStyle},
+        hhea::Hhea,
+        hmtx::{Hmtx, LongMetric},
+        layout::{Condition, DeviceOrVariationIndex, FeatureList, FeatureVariations, Lookup},
+        maxp::Maxp,
+        name::{Name, NameRecord},
+        os2::Os2,
+        vhea::Vhea,
+        vmtx::Vmtx as VmtxTable,
+    },
+};
+
+use crate::{
+    cache::cached_font_file,
+    font::{SkrifaFontRefExt, StringExt, to_us_weight_class, to_us_width_class},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InstanceError {
+    #[error("Failed to read or write font file")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse font file")]
+    Parse(#[from] read_fonts::ReadError),
+    #[error("Font is not a variable font")]
+    NotVariable,
+    #[error("Unknown variation axis: {0}")]
+    UnknownAxis(String),
+    #[error("Failed to draw glyph")]
+    Draw(#[from] DrawError),
+    #[error("Failed to convert glyph {0}")]
+    Glyph(GlyphId, MalformedPath),
+    #[error("Failed to build font table")]
+    Build(#[from] write_fonts::BuilderError),
+    #[error("Failed to build font table")]
+    Write(#[from] write_fonts::error::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct InstanceOptions<'a> {
+    pub font: (&'a Path, usize),
+    /// User-space coordinates keyed by axis tag. Axes not listed keep their default values.
+    pub coordinates: &'a [(String, f32)],
+    pub subfamily_name: Option<&'a str>,
+    pub postscript_name: Option<&'a str>,
+}
+
+/// Tables that only make sense for variable fonts, or that describe outlines and
+/// hinting we don't carry over to the static instance.
+const DROPPED_TABLES: [&[u8; 4]; 17] = [
+    b"fvar", b"gvar", b"avar", b"cvar", b"HVAR", b"VVAR", b"MVAR", b"STAT", b"CFF ", b"CFF2",
+    b"VORG", b"fpgm", b"prep", b"cvt ", b"hdmx", b"LTSH", b"VDMX",
+];
+
+/// Return the path of a static instance of a variable font, building it into the
+/// cache directory first if needed.
+pub fn cached_instance(options: InstanceOptions) -> Result<PathBuf, InstanceError> {
+    let (font_path, font_index) = options.font;
+    // The order of the axes in the query doesn't change the instance.
+    let mut coordinates = options
+        .coordinates
+        .iter()
+        .map(|(tag, value)| (tag, value.to_bits()))
+        .collect::<Vec<_>>();
+    coordinates.sort();
+    let key = (
+        font_index,
+        coordinates,
+        options.subfamily_name,
+        options.postscript_name,
+    );
+    cached_font_file("instances", font_path, key, || {
+        instantiate_font(options.clone()).map(|data| (data, "ttf"))
+    })
+}
+
+/// Build a static TrueType font from a variable font at the given coordinates.
+///
+/// Outlines are drawn at the requested location and stored as quadratic `glyf`
+/// glyphs, so CFF2 fonts are converted as well. Composite glyphs are flattened
+/// and hinting is dropped. Vertical metrics get the deltas of VVAR or of the
+/// phantom points, like the horizontal ones. GPOS values, anchors and ligature
+/// carets get their deltas at the location, and the feature variations that
+/// match it replace the features of GSUB and GPOS.
+pub fn instantiate_font(
+    InstanceOptions {
+        font: (font_path, font_index),
+        coordinates,
+        subfamily_name,
+        postscript_name,
+    }: InstanceOptions,
+) -> Result<Vec<u8>, InstanceError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+
+    let axes = font.axes();
+    if axes.is_empty() {
+        return Err(InstanceError::NotVariable);
+    }
+
+    let mut user_coordinates = axes
+        .iter()
+        .map(|axis| (axis.tag(), axis.default_value()))
+        .collect::<Vec<_>>();
+    for (tag, value) in coordinates {
+        let (axis, coordinate) = axes
+            .iter()
+            .zip(&mut user_coordinates)
+            .find(|(axis, _)| axis.tag().to_string() == *tag)
+            .ok_or_else(|| InstanceError::UnknownAxis(tag.clone()))?;
+        coordinate.1 = value.clamp(axis.min_value(), axis.max_value());
+    }
+    let location = axes.location(user_coordinates.iter().copied());
+    let user_coordinate = |tag: &[u8; 4]| {
+        user_coordinates
+            .iter()
+            .find(|(axis_tag, _)| axis_tag == &Tag::new(tag))
+            .map(|(_, value)| *value)
+    };
+
+    let num_glyphs = font.maxp()?.num_glyphs();
+    let outlines = font.outline_glyphs();
+    let glyph_metrics = font.glyph_metrics(Size::unscaled(), &location);
+    let vertical_metrics = VerticalMetrics::new(&font, location.coords());
+
+    let mut glyf_loca_builder = GlyfLocaBuilder::new();
+    let mut h_metrics = Vec::with_capacity(num_glyphs as usize);
+    let mut v_metrics = Vec::new();
+    let mut font_bbox: Option<Bbox> = None;
+    let (mut max_points, mut max_contours) = (0, 0);
+    let (mut min_right_side_bearing, mut x_max_extent) = (i16::MAX, i16::MIN);
+    let (mut min_bottom_side_bearing, mut y_max_extent) = (i16::MAX, i16::MIN);
+
+    for glyph_id in (0..num_glyphs).map(GlyphId::from) {
+        let outline = outlines.get(glyph_id);
+        let mut pen = BezPathPen::default();
+        if let Some(outline) = &outline {
+            outline.draw(
+                DrawSettings::unhinted(Size::unscaled(), &location),
+                &mut pen,
+            )?;
+        }
+
+        let glyph = if pen.path.elements().is_empty() {
+            Glyph::Empty
+        } else {
+            let glyph = SimpleGlyph::from_bezpath(&pen.path)
+                .map_err(|error| InstanceError::Glyph(glyph_id, error))?;
+            max_points = max_points.max(glyph.contours.iter().map(|c| c.len()).sum::<usize>());
+            max_contours = max_contours.max(glyph.contours.len());
+            Glyph::Simple(glyph)
+        };
+        glyf_loca_builder.add_glyph(&glyph)?;
+
+        let advance = glyph_metrics
+            .advance_width(glyph_id)
+            .unwrap_or_default()
+            .ot_round();
+        let bbox = glyph.bbox();
+        let left_side_bearing = bbox.map(|bbox| bbox.x_min).unwrap_or_default();
+        if let Some(bbox) = bbox {
+            min_right_side_bearing = min_right_side_bearing
+                .min((advance as i32 - bbox.x_max as i32).clamp(i16::MIN as _, i16::MAX as _) as _);
+            x_max_extent = x_max_extent.max(bbox.x_max);
+            font_bbox = Some(font_bbox.map_or(bbox, |font_bbox| font_bbox.union(bbox)));
+        }
+        h_metrics.push(LongMetric::new(advance, left_side_bearing));
+
+        if let Some(vertical_metrics) = &vertical_metrics {
+            let metric = vertical_metrics.metric(glyph_id, outline.as_ref(), bbox)?;
+            if let Some(bbox) = bbox {
+                let extent = metric.side_bearing as i32 + bbox.y_max as i32 - bbox.y_min as i32;
+                min_bottom_side_bearing = min_bottom_side_bearing
+                    .min((metric.advance as i32 - extent).clamp(i16::MIN as _, i16::MAX as _) as _);
+                y_max_extent = y_max_extent.max(extent.clamp(i16::MIN as _, i16::MAX as _) as _);
+            }
+            v_metrics.push(metric);
+        }
+    }
+
+    let (glyf, loca, loca_format) = glyf_loca_builder.build();
+    let font_bbox = font_bbox.unwrap_or_default();
+
+    // Deltas are applied in i32 and clamped, as they may push a metric out of
+    // the range of its field.
+    let metric_delta = |tag: &[u8; 4]| -> i32 {
+        font.mvar()
+            .ok()
+            .and_then(|mvar| mvar.metric_delta(Tag::new(tag), location.coords()).ok())
+            .map(|delta| (delta.to_f32() + 0.5).floor() as i32)
+            .unwrap_or_default()
+    };
+    let add_delta = |value: i16, tag: &[u8; 4]| -> i16 {
+        (value as i32 + metric_delta(tag)).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    };
+    let add_unsigned_delta = |value: u16, tag: &[u8; 4]| -> u16 {
+        (value as i32 + metric_delta(tag)).clamp(0, u16::MAX as i32) as u16
+    };
+
+    // The style bits follow the instance, or the default one without the axis.
+    let default_style = font.head()?.mac_style();
+    let is_bold = user_coordinate(b"wght")
+        .map_or(default_style.contains(MacStyle::BOLD), |value| {
+            value >= 700.0
+        });
+    let is_italic = user_coordinate(b"ital")
+        .map_or(default_style.contains(MacStyle::ITALIC), |value| {
+            value != 0.0
+        });
+
+    let mut head: Head = font.head()?.to_owned_table();
+    head.index_to_loc_format = loca_format as i16;
+    (head.x_min, head.y_min, head.x_max, head.y_max) = (
+        font_bbox.x_min,
+        font_bbox.y_min,
+        font_bbox.x_max,
+        font_bbox.y_max,
+    );
+    head.mac_style.remove(MacStyle::BOLD | MacStyle::ITALIC);
+    if is_bold {
+        head.mac_style.insert(MacStyle::BOLD);
+    }
+    if is_italic {
+        head.mac_style.insert(MacStyle::ITALIC);
+    }
+
+    let mut hhea: Hhea = font.hhea()?.to_owned_table();
+    hhea.ascender = add_delta(hhea.ascender.to_i16(), b"hasc").into();
+    hhea.descender = add_delta(hhea.descender.to_i16(), b"hdsc").into();
+    hhea.line_gap = add_delta(hhea.line_gap.to_i16(), b"hlgp").into();
+    hhea.advance_width_max = h_metrics
+        .iter()
+        .map(|metric| metric.advance)
+        .max()
+        .unwrap_or_default()
+        .into();
+    hhea.min_left_side_bearing = h_metrics
+        .iter()
+        .map(|metric| metric.side_bearing)
+        .min()
+        .unwrap_or_default()
+        .into();
+    hhea.min_right_side_bearing = min_right_side_bearing.min(0).into();
+    hhea.x_max_extent = x_max_extent.max(0).into();
+    hhea.number_of_h_metrics = num_glyphs;
+
+    let mut maxp = Maxp::new(num_glyphs);
+    maxp.max_points = Some(max_points as u16);
+    maxp.max_contours = Some(max_contours as u16);
+    maxp.max_composite_points = Some(0);
+    maxp.max_composite_contours = Some(0);
+    maxp.max_zones = Some(1);
+    maxp.max_twilight_points = Some(0);
+    maxp.max_storage = Some(0);
+    maxp.max_function_defs = Some(0);
+    maxp.max_instruction_defs = Some(0);
+    maxp.max_stack_elements = Some(0);
+    maxp.max_size_of_instructions = Some(0);
+    maxp.max_component_elements = Some(0);
+    maxp.max_component_depth = Some(0);
+
+    let mut font_builder = FontBuilder::new();
+    font_builder
+        .add_table(&glyf)?
+        .add_table(&loca)?
+        .add_table(&head)?
+        .add_table(&hhea)?
+        .add_table(&Hmtx::new(h_metrics, vec![]))?
+        .add_table(&maxp)?;
+
+    if vertical_metrics.is_some()
+        && let Ok(vhea) = font.vhea()
+    {
+        let mut vhea: Vhea = vhea.to_owned_table();
+        vhea.ascender = add_delta(vhea.ascender.to_i16(), b"vasc").into();
+        vhea.descender = add_delta(vhea.descender.to_i16(), b"vdsc").into();
+        vhea.line_gap = add_delta(vhea.line_gap.to_i16(), b"vlgp").into();
+        vhea.advance_height_max = v_metrics
+            .iter()
+            .map(|metric| metric.advance)
+            .max()
+            .unwrap_or_default()
+            .into();
+        vhea.min_top_side_bearing = v_metrics
+            .iter()
+            .map(|metric| metric.side_bearing)
+            .min()
+            .unwrap_or_default()
+            .into();
+        vhea.min_bottom_side_bearing = min_bottom_side_bearing.min(0).into();
+        vhea.y_max_extent = y_max_extent.max(0).into();
+        vhea.number_of_long_ver_metrics = num_glyphs;
+        font_builder
+            .add_table(&vhea)?
+            .add_table(&VmtxTable::new(v_metrics, vec![]))?;
+    }
+
+    if let Ok(os2) = font.os2() {
+        let mut os2: Os2 = os2.to_owned_table();
+        if let Some(weight) = user_coordinate(b"wght") {
+            os2.us_weight_class = to_us_weight_class(weight);
+        }
+        if let Some(width) = user_coordinate(b"wdth") {
+            os2.us_width_class = to_us_width_class(width);
+        }
+        os2.fs_selection
+            .remove(SelectionFlags::BOLD | SelectionFlags::ITALIC | SelectionFlags::REGULAR);
+        if is_bold {
+            os2.fs_selection.insert(SelectionFlags::BOLD);
+        }
+        if is_italic {
+            os2.fs_selection.insert(SelectionFlags::ITALIC);
+        }
+        if !is_bold && !is_italic {
+            os2.fs_selection.insert(SelectionFlags::REGULAR);
+        }
+        os2.s_typo_ascender = add_delta(os2.s_typo_ascender, b"hasc");
+        os2.s_typo_descender = add_delta(os2.s_typo_descender, b"hdsc");
+        os2.s_typo_line_gap = add_delta(os2.s_typo_line_gap, b"hlgp");
+        os2.us_win_ascent = add_unsigned_delta(os2.us_win_ascent, b"hcla");
+        os2.us_win_descent = add_unsigned_delta(os2.us_win_descent, b"hcld");
+        os2.sx_height = os2.sx_height.map(|value| add_delta(value, b"xhgt"));
+        os2.s_cap_height = os2.s_cap_height.map(|value| add_delta(value, b"cpht"));
+        font_builder.add_table(&os2)?;
+    }
+
+    let family_name = font
+        .string(StringId::TYPOGRAPHIC_FAMILY_NAME)
+        .or_else(|| font.string(StringId::FAMILY_NAME))
+        .unwrap_or_default();
+    let subfamily_name = subfamily_name.map(String::from).unwrap_or_else(|| {
+        axes.iter()
+            .zip(&user_coordinates)
+            .filter(|(axis, (_, value))| *value != axis.default_value())
+            .map(|(_, (tag, value))| format!("{tag}{value}"))
+            .reduce(|name, item| format!("{name} {item}"))
+            .unwrap_or_else(|| "Regular".into())
+    });
+    let postscript_name = postscript_name.map(String::from).unwrap_or_else(|| {
+        format!(
+            "{}-{}",
+            family_name.postscript(),
+            subfamily_name.postscript()
+        )
+    });
+    font_builder.add_table(&instance_name_table(
+        &font,
+        &family_name,
+        &subfamily_name,
+        &postscript_name,
+        (is_bold, is_italic),
+    )?)?;
+
+    let deltas = Deltas {
+        store: font
+            .gdef()
+            .ok()
+            .and_then(|gdef| gdef.item_var_store())
+            .transpose()?,
+        coords: location.coords(),
+    };
+    if let Ok(gdef) = font.gdef()
+        && gdef.item_var_store().is_some()
+    {
+        let mut gdef: Gdef = gdef.to_owned_table();
+        instance_gdef(&mut gdef, &deltas);
+        font_builder.add_table(&gdef)?;
+    }
+    if let Ok(gpos) = font.gpos()
+        && (deltas.store.is_some() || gpos.feature_variations().is_some())
+    {
+        let mut gpos: Gpos = gpos.to_owned_table();
+        apply_feature_variations(
+            &mut gpos.feature_list,
+            &mut gpos.feature_variations,
+            &deltas,
+        );
+        for lookup in gpos.lookup_list.lookups.iter_mut() {
+            instance_lookup(lookup, &deltas);
+        }
+        font_builder.add_table(&gpos)?;
+    }
+    if let Ok(gsub) = font.gsub()
+        && gsub.feature_variations().is_some()
+    {
+        let mut gsub: Gsub = gsub.to_owned_table();
+        apply_feature_variations(
+            &mut gsub.feature_list,
+            &mut gsub.feature_variations,
+            &deltas,
+        );
+        font_builder.add_table(&gsub)?;
+    }
+
+    let dropped_tables = DROPPED_TABLES.map(Tag::new);
+    for record in font.table_directory().table_records() {
+        let tag = record.tag();
+        if !font_builder.contains(tag)
+            && !dropped_tables.contains(&tag)
+            && let Some(data) = font.data_for_tag(tag)
+        {
+            font_builder.add_raw(tag, data);
+        }
+    }
+
+    Ok(font_builder.build())
+}
+
+/// The vertical metrics of a variable font at a location, with the deltas of VVAR,
+/// or of the phantom points of gvar without it.
+struct VerticalMetrics<'a> {
+    vmtx: Vmtx<'a>,
+    vvar: Option<Vvar<'a>>,
+    gvar: Option<(Gvar<'a>, Glyf<'a>, Loca<'a>)>,
+    coords: &'a [F2Dot14],
+}
+
+impl<'a> VerticalMetrics<'a> {
+    fn new(font: &FontRef<'a>, coords: &'a [F2Dot14]) -> Option<Self> {
+        font.vhea().ok()?;
+        Some(VerticalMetrics {
+            vmtx: font.vmtx().ok()?,
+            vvar: font.vvar().ok(),
+            gvar: font
+                .gvar()
+                .and_then(|gvar| Ok((gvar, font.glyf()?, font.loca(None)?)))
+                .ok(),
+            coords,
+        })
+    }
+
+    /// The advance height and top side bearing of the glyph with the bounding box
+    /// at the location. The vertical origin sits at the top side bearing above the
+    /// glyph at the default location, and moves with the deltas.
+    fn metric(
+        &self,
+        glyph_id: GlyphId,
+        outline: Option<&OutlineGlyph>,
+        bbox: Option<Bbox>,
+    ) -> Result<LongMetric, InstanceError> {
+        let phantom_deltas = match &self.gvar {
+            Some((gvar, glyf, loca)) => {
+                gvar.phantom_point_deltas(glyf, loca, self.coords, glyph_id)?
+            }
+            None => None,
+        };
+        // The phantom points are left, right, top and bottom.
+        let (phantom_advance_delta, phantom_origin_delta) = phantom_deltas
+            .map_or((0.0, 0.0), |[_, _, top, bottom]| {
+                ((top.y - bottom.y).to_f32(), top.y.to_f32())
+            });
+        let advance_delta = match &self.vvar {
+            Some(vvar) => vvar.advance_height_delta(glyph_id, self.coords)?.to_f32(),
+            None => phantom_advance_delta,
+        };
+        let origin_delta = match &self.vvar {
+            Some(vvar) if vvar.v_org_mapping().is_some() => {
+                vvar.v_org_delta(glyph_id, self.coords)?.to_f32()
+            }
+            _ => phantom_origin_delta,
+        };
+
+        let advance = self.vmtx.advance(glyph_id).unwrap_or_default() as f32 + advance_delta;
+        let advance = advance.max(0.0).ot_round();
+        let top_side_bearing = match (outline, bbox) {
+            (Some(outline), Some(bbox)) => {
+                let mut pen = BezPathPen::default();
+                outline.draw(
+                    DrawSettings::unhinted(Size::unscaled(), LocationRef::default()),
+                    &mut pen,
+                )?;
+                let origin = self.vmtx.side_bearing(glyph_id).unwrap_or_default() as f32
+                    + pen.path.control_box().max_y() as f32
+                    + origin_delta;
+                (origin - bbox.y_max as f32)
+                    .clamp(i16::MIN as f32, i16::MAX as f32)
+                    .ot_round()
+            }
+            _ => 0,
+        };
+        Ok(LongMetric::new(advance, top_side_bearing))
+    }
+}
+
+/// Rewrite the naming records of the static instance; other records are kept.
+/// The legacy subfamily name matches the bold and italic style bits, and the
+/// legacy family name takes the rest of the subfamily name.
+fn instance_name_table(
+    font: &FontRef,
+    family_name: &str,
+    subfamily_name: &str,
+    postscript_name: &str,
+    (is_bold, is_italic): (bool, bool),
+) -> Result<Name, InstanceError> {
+    let legacy_subfamily_name = match (is_bold, is_italic) {
+        (false, false) => "Regular",
+        (true, false) => "Bold",
+        (false, true) => "Italic",
+        (true, true) => "Bold Italic",
+    };
+    let names = [
+        (
+            NameId::FAMILY_NAME,
+            if subfamily_name == legacy_subfamily_name {
+                family_name.to_string()
+            } else {
+                format!("{family_name} {subfamily_name}")
+            },
+        ),
+        (NameId::SUBFAMILY_NAME, legacy_subfamily_name.to_string()),
+        (NameId::UNIQUE_ID, postscript_name.to_string()),
+        (NameId::FULL_NAME, format!("{family_name} {subfamily_name}")),
+        (NameId::POSTSCRIPT_NAME, postscript_name.to_string()),
+        (NameId::TYPOGRAPHIC_FAMILY_NAME, family_name.to_string()),
+        (
+            NameId::TYPOGRAPHIC_SUBFAMILY_NAME,
+            subfamily_name.to_string(),
+        ),
+    ];
+    let removed_names = names
+        .iter()
+        .map(|(name_id, _)| *name_id)
+        .chain([
+            NameId::WWS_FAMILY_NAME,
+            NameId::WWS_SUBFAMILY_NAME,
+            NameId::VARIATIONS_POSTSCRIPT_NAME_PREFIX,
+        ])
+        .collect::<BTreeSet<_>>();
+
+    let mut name: Name = font.name()?.to_owned_table();
+    name.name_record.retain(|record| {
+        !removed_names.contains(&record.name_id)
+            && !matches!(
+                Encoding::new(record.platform_id, record.encoding_id),
+                Encoding::Unknown
+            )
+    });
+    name.name_record.extend(
+        names.into_iter().map(|(name_id, string)| {
+            NameRecord::new(3, 1, 0x409, name_id, OffsetMarker::new(string))
+        }),
+    );
+    name.name_record.sort();
+
+    Ok(name)
+}
+
+/// Deltas of the GDEF variation store at the location of the instance.
+struct Deltas<'a> {
+    store: Option<ItemVariationStore<'a>>,
+    coords: &'a [F2Dot14],
+}
+
+impl Deltas<'_> {
+    fn get(&self, index: DeltaSetIndex) -> i32 {
+        self.store
+            .as_ref()
+            .and_then(|store| store.compute_delta(index, self.coords).ok())
+            .unwrap_or_default()
+    }
+
+    /// The delta of a value with a variation index; device tables only hint and
+    /// are dropped along with the hinting.
+    fn device(&self, device: Option<&DeviceOrVariationIndex>) -> i32 {
+        match device {
+            Some(DeviceOrVariationIndex::VariationIndex(index)) => self.get(DeltaSetIndex {
+                outer: index.delta_set_outer_index,
+                inner: index.delta_set_inner_index,
+            }),
+            _ => 0,
+        }
+    }
+
+    /// The value with the delta added, clamped to its field.
+    fn add(&self, value: i16, device: Option<&DeviceOrVariationIndex>) -> i16 {
+        (value as i32 + self.device(device)).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+/// Apply the deltas to the ligature carets and drop the variation store.
+fn instance_gdef(gdef: &mut Gdef, deltas: &Deltas) {
+    if let Some(lig_caret_list) = gdef.lig_caret_list.as_mut() {
+        for lig_glyph in lig_caret_list.lig_glyphs.iter_mut() {
+            for caret_value in lig_glyph.caret_values.iter_mut() {
+                if let CaretValue::Format3(caret) = &**caret_value {
+                    let coordinate = deltas.add(caret.coordinate, Some(&caret.device));
+                    **caret_value = CaretValue::format_1(coordinate);
+                }
+            }
+        }
+    }
+    gdef.item_var_store = NullableOffsetMarker::new(None);
+}
+
+/// Replace the features with the alternates of the first feature variation whose
+/// conditions match the location, and drop the feature variations.
+fn apply_feature_variations(
+    feature_list: &mut FeatureList,
+    feature_variations: &mut NullableOffsetMarker<FeatureVariations, 4>,
+    deltas: &Deltas,
+) {
+    let Some(feature_variations) = feature_variations.take() else {
+        return;
+    };
+    let Some(substitution) = feature_variations
+        .feature_variation_records
+        .iter()
+        .find(|record| {
+            record.condition_set.as_ref().is_none_or(|condition_set| {
+                condition_set
+                    .conditions
+                    .iter()
+                    .all(|condition| condition_matches(condition, deltas))
+            })
+        })
+        .and_then(|record| record.feature_table_substitution.as_ref())
+    else {
+        return;
+    };
+    for record in &substitution.substitutions {
+        if let Some(feature_record) = feature_list
+            .feature_records
+            .get_mut(record.feature_index as usize)
+        {
+            feature_record.feature = OffsetMarker::new((*record.alternate_feature).clone());
+        }
+    }
+}
+
+fn condition_matches(condition: &Condition, deltas: &Deltas) -> bool {
+    match condition {
+        Condition::Format1AxisRange(condition) => {
+            let coord = deltas
+                .coords
+                .get(condition.axis_index as usize)
+                .copied()
+                .unwrap_or_default();
+            condition.filter_range_min_value <= coord && coord <= condition.filter_range_max_value
+        }
+        Condition::Format2VariableValue(condition) => {
+            let index = DeltaSetIndex {
+                outer: (condition.var_index >> 16) as u16,
+                inner: condition.var_index as u16,
+            };
+            condition.default_value as i32 + deltas.get(index) > 0
+        }
+        Condition::Format3And(condition) => condition
+            .conditions
+            .iter()
+            .all(|condition| condition_matches(condition, deltas)),
+        Condition::Format4Or(condition) => condition
+            .conditions
+            .iter()
+            .any(|condition| condition_matches(condition, deltas)),
+        Condition::Format5Negate(condition) => !condition_matches(&condition.condition, deltas),
+    }
+}
+
+/// Positioning data that can vary, which gets its deltas at the instance.
+trait Instance {
+    fn instance(&mut self, deltas: &Deltas);
+}
+
+fn instance_lookup(lookup: &mut PositionLookup, deltas: &Deltas) {
+    fn instance_subtables<T: Instance>(lookup: &mut Lookup<T>, deltas: &Deltas) {
+        for subtable in lookup.subtables.iter_mut() {
+            subtable.instance(deltas);
+        }
+    }
+
+    match lookup {
+        PositionLookup::Single(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::Pair(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::Cursive(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::MarkToBase(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::MarkToLig(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::MarkToMark(lookup) => instance_subtables(lookup, deltas),
+        PositionLookup::Extension(lookup) => {
+            for subtable in lookup.subtables.iter_mut() {
+                match &mut **subtable {
+                    ExtensionSubtable::Single(extension) => extension.extension.instance(deltas),
+                    ExtensionSubtable::Pair(extension) => extension.extension.instance(deltas),
+                    ExtensionSubtable::Cursive(extension) => extension.extension.instance(deltas),
+                    ExtensionSubtable::MarkToBase(extension) => {
+                        extension.extension.instance(deltas)
+                    }
+                    ExtensionSubtable::MarkToLig(extension) => extension.extension.instance(deltas),
+                    ExtensionSubtable::MarkToMark(extension) => {
+                        extension.extension.instance(deltas)
+                    }
+                    ExtensionSubtable::Contextual(_) | ExtensionSubtable::ChainContextual(_) => {}
+                }
+            }
+        }
+        // Contextual lookups only refer to other lookups.
+        PositionLookup::Contextual(_) | PositionLookup::ChainContextual(_) => {}
+    }
+}
+
+impl<T: Instance> Instance for OffsetMarker<T> {
+    fn instance(&mut self, deltas: &Deltas) {
+        (**self).instance(deltas);
+    }
+}
+
+impl<T: Instance> Instance for OffsetMarker<T, 4> {
+    fn instance(&mut self, deltas: &Deltas) {
+        (**self).instance(deltas);
+    }
+}
+
+impl<T: Instance> Instance for NullableOffsetMarker<T> {
+    fn instance(&mut self, deltas: &Deltas) {
+        if let Some(table) = self.as_mut() {
+            table.instance(deltas);
+        }
+    }
+}
+
+impl<T: Instance> Instance for Vec<T> {
+    fn instance(&mut self, deltas: &Deltas) {
+        for item in self {
+            item.instance(deltas);
+        }
+    }
+}
+
+/// Values with a device or variation index get the delta added and lose the
+/// device. Every record of a subtable has the same format, so they all end up
+/// with the same format again.
+impl Instance for ValueRecord {
+    fn instance(&mut self, deltas: &Deltas) {
+        let format = self.format();
+        let mut instance_format = format
+            - (ValueFormat::X_PLACEMENT_DEVICE
+                | ValueFormat::Y_PLACEMENT_DEVICE
+                | ValueFormat::X_ADVANCE_DEVICE
+                | ValueFormat::Y_ADVANCE_DEVICE);
+        for (value, device, value_flag, device_flag) in [
+            (
+                &mut self.x_placement,
+                &mut self.x_placement_device,
+                ValueFormat::X_PLACEMENT,
+                ValueFormat::X_PLACEMENT_DEVICE,
+            ),
+            (
+                &mut self.y_placement,
+                &mut self.y_placement_device,
+                ValueFormat::Y_PLACEMENT,
+                ValueFormat::Y_PLACEMENT_DEVICE,
+            ),
+            (
+                &mut self.x_advance,
+                &mut self.x_advance_device,
+                ValueFormat::X_ADVANCE,
+                ValueFormat::X_ADVANCE_DEVICE,
+            ),
+            (
+                &mut self.y_advance,
+                &mut self.y_advance_device,
+                ValueFormat::Y_ADVANCE,
+                ValueFormat::Y_ADVANCE_DEVICE,
+            ),
+        ] {
+            if format.contains(device_flag) {
+                *value = Some(deltas.add(value.unwrap_or_default(), device.as_ref()));
+                *device = NullableOffsetMarker::new(None);
+                instance_format |= value_flag;
+            }
+        }
+        self.set_explicit_value_format(instance_format);
+    }
+}
+
+/// Anchors with devices or variation indices become plain coordinates.
+impl Instance for AnchorTable {
+    fn instance(&mut self, deltas: &Deltas) {
+        if let AnchorTable::Format3(anchor) = self {
+            *self = AnchorTable::format_1(
+                deltas.add(anchor.x_coordinate, anchor.x_device.as_ref()),
+                deltas.add(anchor.y_coordinate, anchor.y_device.as_ref()),
+            );
+        }
+    }
+}
+
+impl Instance for SinglePos {
+    fn instance(&mut self, deltas: &Deltas) {
+        match self {
+            SinglePos::Format1(subtable) => subtable.value_record.instance(deltas),
+            SinglePos::Format2(subtable) => subtable.value_records.instance(deltas),
+        }
+    }
+}
+
+impl Instance for PairPos {
+    fn instance(&mut self, deltas: &Deltas) {
+        match self {
+            PairPos::Format1(subtable) => {
+                for pair_set in subtable.pair_sets.iter_mut() {
+                    for record in pair_set.pair_value_records.iter_mut() {
+                        record.value_record1.instance(deltas);
+                        record.value_record2.instance(deltas);
+                    }
+                }
+            }
+            PairPos::Format2(subtable) => {
+                for class1_record in subtable.class1_records.iter_mut() {
+                    for record in class1_record.class2_records.iter_mut() {
+                        record.value_record1.instance(deltas);
+                        record.value_record2.instance(deltas);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Instance for CursivePosFormat1 {
+    fn instance(&mut self, deltas: &Deltas) {
+        for record in self.entry_exit_record.iter_mut() {
+            record.entry_anchor.instance(deltas);
+            record.exit_anchor.instance(deltas);
+        }
+    }
+}
+
+impl Instance for MarkArray {
+    fn instance(&mut self, deltas: &Deltas) {
+        for record in self.mark_records.iter_mut() {
+            record.mark_anchor.instance(deltas);
+        }
+    }
+}
+
+impl Instance for MarkBasePosFormat1 {
+    fn instance(&mut self, deltas: &Deltas) {
+        self.mark_array.instance(deltas);
+        for record in self.base_array.base_records.iter_mut() {
+            record.base_anchors.instance(deltas);
+        }
+    }
+}
+
+impl Instance for MarkLigPosFormat1 {
+    fn instance(&mut self, deltas: &Deltas) {
+        self.mark_array.instance(deltas);
+        for ligature_attach in self.ligature_array.ligature_attaches.iter_mut() {
+            for record in ligature_attach.component_records.iter_mut() {
+                record.ligature_anchors.instance(deltas);
+            }
+        }
+    }
+}
+
+impl Instance for MarkMarkPosFormat1 {
+    fn instance(&mut self, deltas: &Deltas) {
+        self.mark1_array.instance(deltas);
+        for record in self.mark2_array.mark2_records.iter_mut() {
+            record.mark2_anchors.instance(deltas);
+        }
+    }
+}
+
+/// An outline pen that collects a glyph as quadratic curves, converting any
+/// cubic curves along the way.
+#[derive(Debug, Default)]
+struct BezPathPen {
+    path: BezPath,
+}
+
+impl OutlinePen for BezPathPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.path.move_to((x as f64, y as f64));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to((x as f64, y as f64));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.path
+            .quad_to((x1 as f64, y1 as f64), (x as f64, y as f64));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let start = match self.path.elements().last() {
+            Some(PathEl::MoveTo(point) | PathEl::LineTo(point)) => *point,
+            Some(PathEl::QuadTo(_, point) | PathEl::CurveTo(_, _, point)) => *point,
+            Some(PathEl::ClosePath) | None => Point::ZERO,
+        };
+        let cubic = CubicBez::new(
+            start,
+            Point::new(x1 as f64, y1 as f64),
+            Point::new(x2 as f64, y2 as f64),
+            Point::new(x as f64, y as f64),
+        );
+        for (_, _, quad) in cubic.to_quads(0.5) {
+            self.path.quad_to(quad.p1, quad.p2);
+        }
+    }
+
+    fn close(&mut self) {
+        self.path.close_path();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::fixture;
+
+    fn instantiate(
+        name: &str,
+        coordinates: &[(&str, f32)],
+        subfamily_name: Option<&str>,
+    ) -> Result<Vec<u8>, InstanceError> {
+        let path = fixture(name);
+        let coordinates = coordinates
+            .iter()
+            .map(|(tag, value)| (tag.to_string(), *value))
+            .collect::<Vec<_>>();
+        instantiate_font(InstanceOptions {
+            font: (&path, 0),
+            coordinates: &coordinates,
+            subfamily_name,
+            postscript_name: None,
+        })
+    }
+
+    /// Check that the instance has static outlines and the advances of the variable
+    /// font at the given location.
+    fn check_instance(name: &str, instance: &FontRef, coordinates: &[(&str, f32)]) {
+        for tag in [b"fvar", b"gvar", b"HVAR", b"CFF2"] {
+            assert!(instance.data_for_tag(Tag::new(tag)).is_none());
+        }
+        assert!(instance.glyf().is_ok());
+        assert!(instance.axes().is_empty());
+
+        let data = fs::read(fixture(name)).unwrap();
+        let font = FontRef::new(&data).unwrap();
+        let location = font.axes().location(
+            coordinates
+                .iter()
+                .map(|(tag, value)| (Tag::new_checked(tag.as_bytes()).unwrap(), *value)),
+        );
+        let glyph_id = font.charmap().map('a').unwrap();
+        assert_eq!(
+            instance
+                .glyph_metrics(Size::unscaled(), &[] as &[_])
+                .advance_width(glyph_id),
+            font.glyph_metrics(Size::unscaled(), &location)
+                .advance_width(glyph_id)
+                .map(|advance| OtRound::<u16>::ot_round(advance) as f32)
+        );
+    }
+
+    #[test]
+    fn test_instantiate_glyf() {
+        let data = instantiate(
+            "NotoSansHebrew-VF.ttf",
+            &[("wght", 700.0), ("wdth", 80.0)],
+            None,
+        )
+        .unwrap();
+        let instance = FontRef::new(&data).unwrap();
+        check_instance(
+            "NotoSansHebrew-VF.ttf",
+            &instance,
+            &[("wght", 700.0), ("wdth", 80.0)],
+        );
+
+        let os2 = instance.os2().unwrap();
+        assert_eq!(os2.us_weight_class(), 700);
+        assert_eq!(os2.us_width_class(), to_us_width_class(80.0));
+        assert_eq!(
+            os2.fs_selection() & (SelectionFlags::BOLD | SelectionFlags::REGULAR),
+            SelectionFlags::BOLD
+        );
+        assert_eq!(instance.head().unwrap().mac_style(), MacStyle::BOLD);
+        assert_eq!(
+            instance.string(StringId::SUBFAMILY_NAME).as_deref(),
+            Some("Bold")
+        );
+        assert_eq!(
+            instance
+                .string(StringId::TYPOGRAPHIC_SUBFAMILY_NAME)
+                .as_deref(),
+            Some("wght700 wdth80")
+        );
+    }
+
+    #[test]
+    fn test_instantiate_cff2() {
+        let data = instantiate("Cantarell-VF.otf", &[("wght", 800.0)], Some("Extra Bold")).unwrap();
+        let instance = FontRef::new(&data).unwrap();
+        check_instance("Cantarell-VF.otf", &instance, &[("wght", 800.0)]);
+
+        assert_eq!(instance.os2().unwrap().us_weight_class(), 800);
+        assert!(
+            instance
+                .os2()
+                .unwrap()
+                .fs_selection()
+                .contains(SelectionFlags::BOLD)
+        );
+        assert_eq!(
+            instance
+                .string(StringId::TYPOGRAPHIC_SUBFAMILY_NAME)
+                .as_deref(),
+            Some("Extra Bold")
+        );
+        assert_eq!(
+            instance.string(StringId::POSTSCRIPT_NAME).as_deref(),
+            Some("Cantarell-ExtraBold")
+        );
+    }
+
+    #[test]
+    fn test_instantiate_regular() {
+        let data = instantiate("NotoSansHebrew-VF.ttf", &[("wght", 400.0)], None).unwrap();
+        let instance = FontRef::new(&data).unwrap();
+        assert_eq!(
+            instance.os2().unwrap().fs_selection()
+                & (SelectionFlags::BOLD | SelectionFlags::REGULAR),
+            SelectionFlags::REGULAR
+        );
+        assert_eq!(instance.head().unwrap().mac_style(), MacStyle::empty());
+        assert_eq!(
+            instance.string(StringId::SUBFAMILY_NAME).as_deref(),
+            Some("Regular")
+        );
+    }
+
+    #[test]
+    fn test_instance_deltas() {
+        use read_fonts::{FontData, FontRead};
+        use write_fonts::tables::{
+            layout::VariationIndex,
+            variations::{
+                ItemVariationData, ItemVariationStore as ItemVariationStoreTable,
+                RegionAxisCoordinates, VariationRegion, VariationRegionList,
+            },
+        };
+
+        // A single delta of 50 at the maximum of the only axis.
+        let store = write_fonts::dump_table(&ItemVariationStoreTable::new(
+            VariationRegionList::new(
+                1,
+                vec![VariationRegion::new(vec![RegionAxisCoordinates::new(
+                    F2Dot14::from_f32(0.0),
+                    F2Dot14::from_f32(1.0),
+                    F2Dot14::from_f32(1.0),
+                )])],
+            ),
+            vec![Some(ItemVariationData::new(1, 0, vec![0], vec![50]))],
+        ))
+        .unwrap();
+        let coords = [F2Dot14::from_f32(0.5)];
+        let deltas = Deltas {
+            store: Some(ItemVariationStore::read(FontData::new(&store)).unwrap()),
+            coords: &coords,
+        };
+        let variation_index = || DeviceOrVariationIndex::VariationIndex(VariationIndex::new(0, 0));
+
+        let mut value_record = ValueRecord::new()
+            .with_x_advance(100)
+            .with_x_advance_device(variation_index());
+        value_record.instance(&deltas);
+        assert_eq!(value_record.x_advance, Some(125));
+        assert!(value_record.x_advance_device.is_none());
+        assert_eq!(value_record.format(), ValueFormat::X_ADVANCE);
+
+        let mut anchor = AnchorTable::format_3(10, 20, Some(variation_index()), None);
+        anchor.instance(&deltas);
+        assert_eq!(anchor, AnchorTable::format_1(35, 20));
+    }
+
+    /// The vertical origins stay above the glyphs, and the advances follow VVAR.
+    #[test]
+    fn test_instantiate_vertical_metrics() {
+        use std::{env, process};
+
+        use write_fonts::tables::{
+            variations::{
+                ItemVariationData, ItemVariationStore as ItemVariationStoreTable,
+                RegionAxisCoordinates, VariationRegion, VariationRegionList,
+            },
+            vvar::Vvar as VvarTable,
+        };
+
+        let y_max = |font: &FontRef, glyph_id: GlyphId| {
+            let mut pen = BezPathPen::default();
+            let settings = DrawSettings::unhinted(Size::unscaled(), LocationRef::default());
+            let glyph = font.outline_glyphs().get(glyph_id).unwrap();
+            glyph.draw(settings, &mut pen).unwrap();
+            pen.path.control_box().max_y().round() as i32
+        };
+        let vertical_metrics = |name: &str, coordinates: &[(&str, f32)], with_vvar: bool| {
+            let data = fs::read(fixture(name)).unwrap();
+            let font = FontRef::new(&data).unwrap();
+            let num_glyphs = font.maxp().unwrap().num_glyphs();
+            let mut font_builder = FontBuilder::new();
+            font_builder
+                .add_table(&Vhea::new(
+                    500.into(),
+                    (-500).into(),
+                    0.into(),
+                    1000.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    0,
+                    1,
+                    0,
+                    num_glyphs,
+                ))
+                .unwrap()
+                .add_table(&VmtxTable::new(
+                    vec![LongMetric::new(1000, 100); num_glyphs as usize],
+                    vec![],
+                ))
+                .unwrap();
+            if with_vvar {
+                // An advance delta of 100 for every glyph at the maximum of the first axis.
+                let axis_count = font.axes().len();
+                let region = (0..axis_count)
+                    .map(|index| {
+                        let peak = F2Dot14::from_f32(if index == 0 { 1.0 } else { 0.0 });
+                        RegionAxisCoordinates::new(peak.min(F2Dot14::ZERO), peak, peak)
+                    })
+                    .collect();
+                let store = ItemVariationStoreTable::new(
+                    VariationRegionList::new(axis_count as u16, vec![VariationRegion::new(region)]),
+                    vec![Some(ItemVariationData::new(
+                        num_glyphs,
+                        0,
+                        vec![0],
+                        vec![100; num_glyphs as usize],
+                    ))],
+                );
+                font_builder
+                    .add_table(&VvarTable::new(store, None, None, None, None))
+                    .unwrap();
+            }
+            font_builder.copy_missing_tables(font.clone());
+            let path =
+                env::temp_dir().join(format!("figma-agent-vertical-{}-{name}", process::id()));
+            fs::write(&path, font_builder.build()).unwrap();
+
+            let coordinates = coordinates
+                .iter()
+                .map(|(tag, value)| (tag.to_string(), *value))
+                .collect::<Vec<_>>();
+            let instance = instantiate_font(InstanceOptions {
+                font: (&path, 0),
+                coordinates: &coordinates,
+                subfamily_name: None,
+                postscript_name: None,
+            })
+            .unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let instance = FontRef::new(&instance).unwrap();
+            assert!(instance.data_for_tag(Tag::new(b"VVAR")).is_none());
+            let glyph_id = font.charmap().map('a').unwrap();
+            let vmtx = instance.vmtx().unwrap();
+            let (advance, top_side_bearing) = (
+                vmtx.advance(glyph_id).unwrap(),
+                vmtx.side_bearing(glyph_id).unwrap(),
+            );
+            let origin = top_side_bearing as i32 + y_max(&instance, glyph_id);
+            assert_eq!(origin, 100 + y_max(&font, glyph_id), "{name}");
+            assert_ne!(y_max(&instance, glyph_id), y_max(&font, glyph_id), "{name}");
+            assert_eq!(
+                instance.vhea().unwrap().number_of_long_ver_metrics(),
+                num_glyphs
+            );
+            advance
+        };
+
+        assert_eq!(
+            vertical_metrics("NotoSansHebrew-VF.ttf", &[("wght", 900.0)], false),
+            1000
+        );
+        assert_eq!(
+            vertical_metrics("Cantarell-VF.otf", &[("wght", 800.0)], true),
+            1100
+        );
+    }
+
+    #[test]
+    fn test_instantiate_errors() {
+        assert!(matches!(
+            instantiate("NotoSansHebrew-VF.ttf", &[("slnt", -10.0)], None),
+            Err(InstanceError::UnknownAxis(tag)) if tag == "slnt"
+        ));
+        assert!(matches!(
+            instantiate("FiraMono-Medium.ttf", &[], None),
+            Err(InstanceError::NotVariable)
+        ));
+    }
+}
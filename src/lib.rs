@@ -1,26 +1,54 @@
 use std::{
     collections::{HashMap, HashSet},
     fs,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+    thread,
+    time::Duration,
 };
 
+use arc_swap::ArcSwap;
 use fontconfig_parser::FontConfig;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 
 use crate::{
     config::Config,
     font::{FontError, FontFile},
-    scanner::scan_font_paths,
+    http::EncodedBody,
+    notify::Notifier,
+    preview::PreviewCache,
+    scanner::{FontDirectory, FontSource, scan_font_paths},
+    shutdown::Activity,
 };
 
+pub mod cache;
+pub mod collection;
+pub mod color;
 pub mod config;
+pub mod error;
+#[cfg(test)]
+mod fixtures;
 pub mod font;
+pub mod http;
+pub mod inspector;
+pub mod instancer;
+pub mod listener;
+pub mod metrics;
+pub mod notify;
+pub mod paragraph;
 pub mod path;
 pub mod payload;
+pub mod preview;
+pub mod profile;
+pub mod reload;
 pub mod renderer;
 pub mod routes;
 pub mod scanner;
+pub mod shaping;
+pub mod shutdown;
+pub mod specimen;
+pub mod subsetter;
 
 pub static XDG_DIRECTORIES: LazyLock<xdg::BaseDirectories> =
     LazyLock::new(|| xdg::BaseDirectories::with_prefix("figma-agent"));
@@ -35,68 +63,147 @@ pub static FONTCONFIG: LazyLock<FontConfig> = LazyLock::new(|| {
     font_config
 });
 
-pub static CONFIG: LazyLock<Config> = LazyLock::new(|| {
-    XDG_DIRECTORIES
-        .find_config_file("config.json")
-        .and_then(|path| {
-            tracing::info!("Use config file: {path:?}");
-            match Config::from_path(&path) {
-                Ok(config) => {
-                    tracing::info!("Use config: {config:?}");
-                    Some(config)
-                }
-                Err(error) => {
-                    tracing::error!("Failed to load config file: {path:?}, error: {error:?}");
-                    None
-                }
-            }
-        })
-        .unwrap_or_else(|| {
-            let config = Config::default();
-            tracing::info!("Use default config: {config:?}");
-            config
-        })
+// The current config, replaced when the config file is reloaded.
+pub static CONFIG: LazyLock<ArcSwap<Config>> = LazyLock::new(|| {
+    let config = load_config().unwrap_or_else(|| {
+        let config = Config::default();
+        tracing::info!("Use default config: {config:?}");
+        config
+    });
+    ArcSwap::from_pointee(config)
 });
 
-pub static EFFECTIVE_FONT_DIRECTORIES: LazyLock<Vec<PathBuf>> = LazyLock::new(|| {
-    let directories = CONFIG.effective_font_directories(&FONTCONFIG).collect();
-    tracing::info!("Use effective font directories: {directories:?}");
-    directories
-});
+pub static EFFECTIVE_FONT_DIRECTORIES: LazyLock<ArcSwap<Vec<FontDirectory>>> =
+    LazyLock::new(|| ArcSwap::from_pointee(effective_font_directories(&CONFIG.load())));
 
 pub static FONT_FILES: LazyLock<RwLock<HashMap<PathBuf, FontFile>>> =
     LazyLock::new(|| RwLock::new(HashMap::new()));
 
+// The serialized `/figma/font-files` payload, rebuilt lazily after the font index changes.
+pub static FONT_FILES_PAYLOAD: LazyLock<RwLock<Option<Arc<EncodedBody>>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+// Rendered font previews, invalidated when their font files change.
+pub static PREVIEW_CACHE: LazyLock<Mutex<PreviewCache>> =
+    LazyLock::new(|| Mutex::new(PreviewCache::new(CONFIG.load().preview_cache_size)));
+
+// Permits of the previews, specimens, subsets, shaping and glyph reads running at
+// once, so that slow requests can't take up all the blocking threads.
+pub static PREVIEW_PERMITS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(thread::available_parallelism().map_or(4, NonZeroUsize::get)));
+
+// Requests in flight and when the last one finished.
+pub static ACTIVITY: LazyLock<Activity> = LazyLock::new(Activity::new);
+
+// The systemd notify socket, if the service was started with one.
+pub static NOTIFIER: LazyLock<Option<Notifier>> = LazyLock::new(Notifier::from_env);
+
+// Number of loaded font files between progress reports to systemd.
+const SCAN_PROGRESS_INTERVAL: usize = 500;
+
+// How much more time to ask systemd for with each progress report, well over
+// what loading `SCAN_PROGRESS_INTERVAL` font files takes.
+const SCAN_PROGRESS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Report the status of the service to systemd, if it's listening.
+pub fn notify_status(status: &str) {
+    if let Some(notifier) = &*NOTIFIER
+        && let Err(error) = notifier.status(status)
+    {
+        tracing::debug!("Failed to notify status, error: {error:?}");
+    }
+}
+
+/// Report the progress of a long task to systemd, extending the startup timeout
+/// while it runs before the service is ready.
+pub fn notify_progress(status: &str) {
+    notify_status(status);
+    if let Some(notifier) = &*NOTIFIER
+        && let Err(error) = notifier.extend_timeout(SCAN_PROGRESS_TIMEOUT)
+    {
+        tracing::debug!("Failed to extend timeout, error: {error:?}");
+    }
+}
+
+/// Load the config file, or the default config without one.
+/// Returns `None` when the config file is invalid.
+pub fn load_config() -> Option<Config> {
+    let Some(path) = XDG_DIRECTORIES.find_config_file("config.json") else {
+        let config = Config::default();
+        tracing::info!("Use default config: {config:?}");
+        return Some(config);
+    };
+    load_config_file(&path)
+}
+
+/// Load the config file at the path, or `None` when it is invalid.
+pub fn load_config_file(path: &Path) -> Option<Config> {
+    tracing::info!("Use config file: {path:?}");
+    match Config::from_path(path) {
+        Ok(config) => {
+            tracing::info!("Use config: {config:?}");
+            Some(config)
+        }
+        Err(error) => {
+            tracing::error!("Failed to load config file: {path:?}, error: {error:?}");
+            None
+        }
+    }
+}
+
+pub fn effective_font_directories(config: &Config) -> Vec<FontDirectory> {
+    let directories = config.effective_font_directories(&FONTCONFIG).collect();
+    tracing::info!("Use effective font directories: {directories:?}");
+    directories
+}
+
 #[tracing::instrument]
 pub async fn scan_font_files() {
     tracing::debug!("Scanning font files...");
+    notify_progress("Scanning font files...");
 
     let mut font_files = FONT_FILES.write().await;
 
     let (mut added_count, mut updated_count, mut removed_count) = (0, 0, 0);
-    let mut font_paths = scan_font_paths(&*EFFECTIVE_FONT_DIRECTORIES).collect::<HashSet<_>>();
+    let mut changed_paths = HashSet::new();
+    let mut font_paths =
+        scan_font_paths(&**EFFECTIVE_FONT_DIRECTORIES.load()).collect::<HashMap<_, _>>();
 
     font_files.retain(|path, _| {
-        let contains = font_paths.contains(path);
+        let contains = font_paths.contains_key(path);
         if !contains {
             removed_count += 1;
+            changed_paths.insert(path.clone());
         }
         contains
     });
 
-    font_paths.retain(|path| {
-        if let Some(font_file) = font_files.get(path) {
+    font_paths.retain(|path, source| {
+        if let Some(font_file) = font_files.get_mut(path) {
             let modified_at = fs::metadata(path)
                 .and_then(|metadata| metadata.modified())
                 .ok();
-            modified_at > font_file.modified_at
+            if modified_at > font_file.modified_at {
+                true
+            } else {
+                if font_file.source != *source {
+                    font_file.source = *source;
+                    updated_count += 1;
+                }
+                false
+            }
         } else {
             true
         }
     });
 
-    for path in font_paths {
-        if let Some(font_file) = load_font_file(&path) {
+    let load_count = font_paths.len();
+    for (index, (path, source)) in font_paths.into_iter().enumerate() {
+        if index % SCAN_PROGRESS_INTERVAL == 0 && index > 0 {
+            notify_progress(&format!("Loading font files ({index}/{load_count})..."));
+        }
+        changed_paths.insert(path.clone());
+        if let Some(font_file) = load_font_file(&path, source) {
             if font_files.insert(path, font_file).is_none() {
                 added_count += 1;
             } else {
@@ -105,13 +212,25 @@ pub async fn scan_font_files() {
         }
     }
 
-    tracing::debug!(
+    if added_count > 0 || updated_count > 0 || removed_count > 0 {
+        FONT_FILES_PAYLOAD.write().await.take();
+    }
+    if !changed_paths.is_empty() {
+        PREVIEW_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .invalidate(&changed_paths);
+    }
+
+    let status = format!(
         "{count} font files loaded ({added_count} added, {updated_count} updated, {removed_count} removed)",
         count = font_files.len(),
     );
+    tracing::debug!("{status}");
+    notify_status(&status);
 }
 
-pub fn load_font_file(path: impl AsRef<Path>) -> Option<FontFile> {
+pub fn load_font_file(path: impl AsRef<Path>, source: FontSource) -> Option<FontFile> {
     let path = path.as_ref();
 
     match FontFile::from_path(path) {
@@ -127,4 +246,8 @@ pub fn load_font_file(path: impl AsRef<Path>) -> Option<FontFile> {
             font_file
         }
     }
+    .map(|font_file| FontFile {
+        source,
+        ..font_file
+    })
 }
@@ -0,0 +1,189 @@
+use std::{fmt, fs, io, os::unix::fs::FileTypeExt, path::Path};
+
+use anyhow::Context;
+use listenfd::ListenFd;
+use tokio::net::{TcpListener, UnixListener, UnixStream};
+
+use crate::{config::BindAddress, path::expand_home};
+
+/// A socket the service accepts connections on.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => write!(f, "{address}"),
+                Err(_) => f.write_str("TCP socket"),
+            },
+            Listener::Unix(listener) => match listener
+                .local_addr()
+                .ok()
+                .and_then(|address| address.as_pathname().map(Path::to_path_buf))
+            {
+                Some(path) => write!(f, "unix:{}", path.display()),
+                None => f.write_str("Unix socket"),
+            },
+        }
+    }
+}
+
+/// The sockets the service accepts connections on.
+#[derive(Debug)]
+pub struct Listeners {
+    pub listeners: Vec<Listener>,
+    /// Whether the sockets were passed by the service manager.
+    pub socket_activated: bool,
+}
+
+/// Take every socket passed by the service manager, or bind the addresses when
+/// there are none.
+pub async fn listen(bind: &[BindAddress]) -> Result<Listeners, anyhow::Error> {
+    let mut listenfd = ListenFd::from_env();
+    let mut listeners = Vec::new();
+    for index in 0..listenfd.len() {
+        listeners.push(take_listener(&mut listenfd, index).with_context(|| {
+            format!("Failed to take socket {index} passed by the service manager")
+        })?);
+    }
+    if !listeners.is_empty() {
+        return Ok(Listeners {
+            listeners,
+            socket_activated: true,
+        });
+    }
+
+    for address in bind {
+        listeners.push(
+            bind_listener(address)
+                .await
+                .with_context(|| format!("Failed to bind {address}"))?,
+        );
+    }
+    Ok(Listeners {
+        listeners,
+        socket_activated: false,
+    })
+}
+
+fn take_listener(listenfd: &mut ListenFd, index: usize) -> io::Result<Listener> {
+    // Taking a socket of the wrong type leaves it in place for the next attempt.
+    if let Ok(Some(listener)) = listenfd.take_tcp_listener(index) {
+        listener.set_nonblocking(true)?;
+        return Ok(Listener::Tcp(TcpListener::from_std(listener)?));
+    }
+    match listenfd.take_unix_listener(index)? {
+        Some(listener) => {
+            listener.set_nonblocking(true)?;
+            Ok(Listener::Unix(UnixListener::from_std(listener)?))
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "socket was already taken",
+        )),
+    }
+}
+
+async fn bind_listener(address: &BindAddress) -> Result<Listener, anyhow::Error> {
+    match address {
+        BindAddress::Tcp(address) => Ok(Listener::Tcp(TcpListener::bind(address).await?)),
+        BindAddress::Unix(path) => {
+            let path = expand_home(path)?;
+            if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                remove_stale_socket(&path).await?;
+            }
+            Ok(Listener::Unix(UnixListener::bind(&path)?))
+        }
+    }
+}
+
+/// Remove a socket left behind by a previous run, which would fail the bind, but
+/// not one that still accepts connections, as it belongs to a running instance.
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "socket is in use by another process",
+        )),
+        Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+            match fs::remove_file(path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+                _ => Ok(()),
+            }
+        }
+        // Removed in the meantime.
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, os::unix::net, process};
+
+    use axum::{Router, routing::get};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_listen_unix() {
+        let path = env::temp_dir().join(format!("figma-agent-listener-{}.sock", process::id()));
+        // A stale socket, as left behind by a previous run that was killed.
+        drop(net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let Listeners {
+            mut listeners,
+            socket_activated,
+        } = listen(&[BindAddress::Unix(path.clone())]).await.unwrap();
+        assert!(!socket_activated);
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].to_string(), format!("unix:{}", path.display()));
+        let Some(Listener::Unix(listener)) = listeners.pop() else {
+            panic!("not a Unix socket");
+        };
+        let app = Router::new().route("/figma/version", get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(
+                b"GET /figma/version HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("ok"));
+
+        // The socket of a running instance is left alone.
+        let error = listen(&[BindAddress::Unix(path.clone())])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error
+                .root_cause()
+                .downcast_ref::<io::Error>()
+                .map(io::Error::kind),
+            Some(io::ErrorKind::AddrInUse)
+        );
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(
+                b"GET /figma/version HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
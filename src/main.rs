@@ -1,17 +1,41 @@
-use std::sync::LazyLock;
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::LazyLock,
+    time::Duration,
+};
 
-use axum::{Router, http::HeaderValue, routing::get};
-use figma_agent::{CONFIG, EFFECTIVE_FONT_DIRECTORIES, routes, scan_font_files};
-use listenfd::ListenFd;
-use tokio::net::TcpListener;
+use anyhow::{Context, bail};
+use axum::{Router, http::HeaderValue, middleware, routing::get};
+use figma_agent::{
+    CONFIG, EFFECTIVE_FONT_DIRECTORIES, NOTIFIER,
+    font::FontFile,
+    listener::{Listener, Listeners, listen},
+    notify::watchdog_interval,
+    reload::watch_config,
+    routes, scan_font_files,
+    shutdown::{shutdown_signal, track_activity},
+    specimen::{SpecimenFormat, render_specimen},
+};
+use tokio::{sync::watch, task::JoinSet};
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    tracing_subscriber::fmt::init();
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        None => tracing_subscriber::fmt::init(),
+        Some("specimen") => {
+            // The specimen may be written to stdout, so logs go to stderr.
+            tracing_subscriber::fmt().with_writer(io::stderr).init();
+            return specimen(args);
+        }
+        Some(command) => bail!("Unknown command: {command}"),
+    }
 
-    LazyLock::force(&CONFIG);
+    let config = CONFIG.load_full();
     LazyLock::force(&EFFECTIVE_FONT_DIRECTORIES);
 
     scan_font_files().await;
@@ -20,9 +44,18 @@ async fn main() -> Result<(), anyhow::Error> {
         .route("/figma/version", get(routes::version))
         .route("/figma/font-files", get(routes::font_files))
         .route("/figma/font-file", get(routes::font_file))
+        .route("/figma/font-subset", get(routes::font_subset))
         .route("/figma/font-preview", get(routes::font_preview))
+        .route("/figma/font-render", get(routes::font_render))
+        .route("/figma/font-paragraph", get(routes::font_paragraph))
+        .route("/figma/font-shape", get(routes::font_shape))
+        .route("/figma/font-metrics", get(routes::font_metrics))
+        .route("/figma/font-specimen", get(routes::font_specimen))
+        .route("/figma/font-glyph", get(routes::font_glyph))
+        .route("/figma/font-glyphs", get(routes::font_glyphs))
         .layer(
             ServiceBuilder::new()
+                .layer(middleware::from_fn(track_activity))
                 .layer(TraceLayer::new_for_http())
                 .layer(
                     CorsLayer::new()
@@ -31,16 +64,143 @@ async fn main() -> Result<(), anyhow::Error> {
                 ),
         );
 
-    let listener = match ListenFd::from_env().take_tcp_listener(0)? {
-        Some(listener) => {
-            listener.set_nonblocking(true)?;
-            TcpListener::from_std(listener)?
+    // Every server stops accepting connections and drains the requests in flight
+    // once the shutdown signal is sent.
+    let (shutdown_sender, shutdown_receiver) = watch::channel(());
+    let Listeners {
+        listeners,
+        socket_activated,
+    } = listen(&config.bind).await?;
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        tracing::info!("Listening on {listener}");
+        let app = app.clone();
+        let mut shutdown_receiver = shutdown_receiver.clone();
+        let shutdown = async move {
+            let _ = shutdown_receiver.changed().await;
+        };
+        match listener {
+            Listener::Tcp(listener) => servers.spawn(async move {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }),
+            Listener::Unix(listener) => servers.spawn(async move {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }),
+        };
+    }
+    if let Some(notifier) = &*NOTIFIER {
+        if let Err(error) = notifier.ready() {
+            tracing::warn!("Failed to notify readiness, error: {error:?}");
+        }
+        if let Some(interval) = watchdog_interval() {
+            tracing::info!("Ping watchdog every {interval:?}");
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    if let Err(error) = notifier.watchdog() {
+                        tracing::warn!("Failed to ping watchdog, error: {error:?}");
+                    }
+                }
+            });
         }
-        None => TcpListener::bind(&CONFIG.bind).await?,
+    }
+
+    tokio::spawn(watch_config(config.clone()));
+
+    // Exiting when idle only makes sense when systemd starts the service again on
+    // the next connection.
+    let idle_timeout = config
+        .idle_timeout
+        .map(|seconds| Duration::from_secs(seconds.get()));
+    let idle_timeout = match idle_timeout {
+        Some(_) if !socket_activated => {
+            tracing::warn!("Ignored idle_timeout without socket activation");
+            None
+        }
+        idle_timeout => idle_timeout,
     };
-    tracing::info!("Listening on {}", listener.local_addr()?);
+    tokio::spawn(async move {
+        let reason = shutdown_signal(idle_timeout).await;
+        tracing::info!("Shutting down ({reason:?})...");
+        if let Some(notifier) = &*NOTIFIER
+            && let Err(error) = notifier.stopping()
+        {
+            tracing::warn!("Failed to notify stopping, error: {error:?}");
+        }
+        let _ = shutdown_sender.send(());
+    });
+
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+    tracing::info!("Stopped");
+
+    Ok(())
+}
+
+const SPECIMEN_USAGE: &str =
+    "Usage: figma-agent specimen [--index <index>] [--format svg|pdf] [--output <file>] <font>";
+
+/// Write a type specimen of a font file to a file or stdout.
+fn specimen(mut args: impl Iterator<Item = String>) -> Result<(), anyhow::Error> {
+    let mut font_path = None;
+    let mut index = 0;
+    let mut format = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--index" => {
+                let value = args.next().context(SPECIMEN_USAGE)?;
+                index = value
+                    .parse()
+                    .with_context(|| format!("Invalid index: {value}"))?;
+            }
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("svg") => Some(SpecimenFormat::Svg),
+                    Some("pdf") => Some(SpecimenFormat::Pdf),
+                    Some(value) => bail!("Invalid format: {value}"),
+                    None => bail!(SPECIMEN_USAGE),
+                }
+            }
+            "--output" => output = Some(PathBuf::from(args.next().context(SPECIMEN_USAGE)?)),
+            _ if font_path.is_none() && !arg.starts_with("--") => {
+                font_path = Some(PathBuf::from(arg))
+            }
+            _ => bail!(SPECIMEN_USAGE),
+        }
+    }
+    let font_path = font_path.context(SPECIMEN_USAGE)?;
+    let format = format.unwrap_or_else(|| {
+        match output
+            .as_ref()
+            .and_then(|output| output.extension())
+            .and_then(|extension| extension.to_str())
+        {
+            Some("pdf") => SpecimenFormat::Pdf,
+            _ => SpecimenFormat::Svg,
+        }
+    });
 
-    axum::serve(listener, app).await?;
+    let font_file = FontFile::from_path(&font_path)
+        .with_context(|| format!("Failed to load font file: {}", font_path.display()))?;
+    let font = font_file
+        .fonts
+        .iter()
+        .find(|font| font.index == index)
+        .with_context(|| format!("No font at index {index}"))?;
+    let content =
+        render_specimen(&font_file.path, font, format).context("Failed to render specimen")?;
 
+    match output {
+        Some(output) => fs::write(&output, content)
+            .with_context(|| format!("Failed to write specimen: {}", output.display()))?,
+        None => io::stdout().write_all(&content)?,
+    }
     Ok(())
 }
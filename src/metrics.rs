@@ -0,0 +1,138 @@
+use std::{fs, path::Path};
+
+use harfrust::Variation;
+use read_fonts::{
+    TableProvider,
+    tables::{mvar::tags, os2::SelectionFlags},
+    types::Tag,
+};
+use skrifa::{
+    FontRef, MetadataProvider,
+    instance::Size,
+    metrics::{Decoration, Metrics},
+};
+
+use crate::{
+    payload::{DecorationPayload, FontMetricsPayload, LineMetricsPayload},
+    renderer::{RenderError, RunFont},
+};
+
+/// Compute the metrics of the face at the named instance and axis coordinates,
+/// in font units.
+pub fn font_metrics(
+    (font_path, font_index): (&Path, usize),
+    named_instance_index: Option<usize>,
+    variations: &[Variation],
+) -> Result<FontMetricsPayload, RenderError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+    let run_font = RunFont::new(font.clone(), named_instance_index, variations);
+    let metrics = font.metrics(Size::unscaled(), &run_font.location);
+
+    // Vary the table values the same way `font.metrics` varies those it picks.
+    let coords = run_font.location.coords();
+    let mvar = font.mvar().ok().filter(|_| !coords.is_empty());
+    let delta = |tag: Tag| {
+        mvar.as_ref()
+            .and_then(|mvar| mvar.metric_delta(tag, coords).ok())
+            .map_or(0.0, |delta| delta.to_f64() as f32)
+    };
+
+    let hhea = font.hhea().ok().map(|hhea| LineMetricsPayload {
+        ascent: hhea.ascender().to_i16() as f32 + delta(tags::HASC),
+        descent: hhea.descender().to_i16() as f32 + delta(tags::HDSC),
+        line_gap: Some(hhea.line_gap().to_i16() as f32 + delta(tags::HLGP)),
+    });
+    let os2 = font.os2().ok();
+    let typo = os2.as_ref().map(|os2| LineMetricsPayload {
+        ascent: os2.s_typo_ascender() as f32 + delta(tags::HASC),
+        descent: os2.s_typo_descender() as f32 + delta(tags::HDSC),
+        line_gap: Some(os2.s_typo_line_gap() as f32 + delta(tags::HLGP)),
+    });
+    let win = os2.as_ref().map(|os2| LineMetricsPayload {
+        ascent: os2.us_win_ascent() as f32 + delta(tags::HCLA),
+        descent: os2.us_win_descent() as f32 + delta(tags::HCLD),
+        line_gap: None,
+    });
+    let use_typo_metrics = os2.as_ref().is_some_and(|os2| {
+        os2.fs_selection()
+            .contains(SelectionFlags::USE_TYPO_METRICS)
+    });
+    let (line_ascent, line_descent) = line_metrics(&font, &metrics, 1.0);
+
+    let decoration = |decoration: Decoration| DecorationPayload {
+        offset: decoration.offset,
+        thickness: decoration.thickness,
+    };
+    Ok(FontMetricsPayload {
+        units_per_em: metrics.units_per_em,
+        ascent: metrics.ascent,
+        descent: metrics.descent,
+        line_gap: metrics.leading,
+        hhea,
+        typo,
+        win,
+        use_typo_metrics,
+        line_ascent,
+        line_descent,
+        x_height: metrics.x_height,
+        cap_height: metrics.cap_height,
+        underline: metrics.underline.map(decoration),
+        strikeout: metrics.strikeout.map(decoration),
+        italic_angle: metrics.italic_angle,
+    })
+}
+
+/// Ascent and descent of the line the Windows client lays out, which uses the
+/// typographic metrics only when the font asks for them and the Windows metrics
+/// otherwise. The descent is positive downwards.
+fn line_metrics(font: &FontRef, metrics: &Metrics, scale: f32) -> (f32, f32) {
+    if let Ok(os2) = font.os2()
+        && !os2
+            .fs_selection()
+            .contains(SelectionFlags::USE_TYPO_METRICS)
+        && os2.us_win_ascent() as u32 + os2.us_win_descent() as u32 > 0
+    {
+        (
+            os2.us_win_ascent() as f32 * scale,
+            os2.us_win_descent() as f32 * scale,
+        )
+    } else {
+        (metrics.ascent, -metrics.descent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn test_font_metrics() {
+        let path = fixture("Inter-Regular.ttf");
+        let metrics = font_metrics((&path, 0), None, &[]).unwrap();
+
+        let hhea = metrics.hhea.unwrap();
+        let typo = metrics.typo.unwrap();
+        let win = metrics.win.unwrap();
+        let picked = if metrics.use_typo_metrics {
+            &typo
+        } else {
+            &hhea
+        };
+        assert_eq!(metrics.ascent, picked.ascent);
+        assert_eq!(metrics.descent, picked.descent);
+        if metrics.use_typo_metrics {
+            assert_eq!(
+                (metrics.line_ascent, metrics.line_descent),
+                (typo.ascent, -typo.descent)
+            );
+        } else {
+            assert_eq!(
+                (metrics.line_ascent, metrics.line_descent),
+                (win.ascent, win.descent)
+            );
+        }
+        assert!(metrics.cap_height.unwrap() > metrics.x_height.unwrap());
+    }
+}
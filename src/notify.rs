@@ -0,0 +1,119 @@
+use std::{
+    env, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    path::Path,
+    process,
+    time::Duration,
+};
+
+/// Reports the state of the service to systemd over the `NOTIFY_SOCKET`
+/// datagram socket.
+///
+/// https://www.freedesktop.org/software/systemd/man/latest/sd_notify.html
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+
+impl Notifier {
+    /// The notifier of the socket in `NOTIFY_SOCKET`, if the service manager
+    /// passed one.
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        Notifier::new(&path)
+            .inspect_err(|error| {
+                tracing::warn!("Failed to open notify socket: {path:?}, error: {error:?}");
+            })
+            .ok()
+    }
+
+    /// The notifier of a socket path, or of an abstract socket when the path
+    /// starts with `@`.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let address = match path.as_os_str().as_encoded_bytes() {
+            [b'@', name @ ..] => SocketAddr::from_abstract_name(name)?,
+            _ => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Notifier {
+            socket: UnixDatagram::unbound()?,
+            address,
+        })
+    }
+
+    /// Send newline-separated `KEY=VALUE` assignments.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.address)?;
+        Ok(())
+    }
+
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Describe the state of the service in one line, shown by `systemctl status`.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+
+    /// Ask for more time to finish starting up, counted from now, so that a long
+    /// startup isn't killed by `TimeoutStartSec`.
+    pub fn extend_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.notify(&format!("EXTEND_TIMEOUT_USEC={}", timeout.as_micros()))
+    }
+
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+}
+
+/// How often to ping the watchdog, which is half of the timeout systemd sets in
+/// `WATCHDOG_USEC` for this process, if any.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = env::var_os("WATCHDOG_PID")
+        && pid.to_str() != Some(&process::id().to_string())
+    {
+        return None;
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify() {
+        let path = env::temp_dir().join(format!("figma-agent-notify-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::new(&path).unwrap();
+
+        let mut buffer = [0; 256];
+        let mut receive = || {
+            let length = systemd.recv(&mut buffer).unwrap();
+            String::from_utf8(buffer[..length].to_vec()).unwrap()
+        };
+        notifier.status("Loading\nfonts").unwrap();
+        assert_eq!(receive(), "STATUS=Loading fonts");
+        notifier.extend_timeout(Duration::from_secs(90)).unwrap();
+        assert_eq!(receive(), "EXTEND_TIMEOUT_USEC=90000000");
+        notifier.ready().unwrap();
+        assert_eq!(receive(), "READY=1");
+        notifier.watchdog().unwrap();
+        assert_eq!(receive(), "WATCHDOG=1");
+        notifier.stopping().unwrap();
+        assert_eq!(receive(), "STOPPING=1");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,534 @@
+use std::{fs, iter, ops::Range, path::Path};
+
+use harfrust::{Direction, Feature, Language, Script, Tag, Variation};
+use skrifa::{FontRef, GlyphId, MetadataProvider, instance::Size};
+use unicode_bidi::{Level, ParagraphBidiInfo};
+use unicode_linebreak::{BreakOpportunity, linebreaks};
+use unicode_script::UnicodeScript;
+
+use crate::{
+    color::ColorGlyphs,
+    renderer::{
+        GlyphDefs, GlyphPen, RenderError, RunFont, SvgPen, TextPath, check_output_size, draw_glyphs,
+    },
+    shaping::{ShapedRun, TextRun, shape_run},
+};
+
+/// Alignment of lines along the inline axis.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    /// Left for left-to-right paragraphs and right for right-to-left ones.
+    #[default]
+    Start,
+    End,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritingMode {
+    /// Horizontal lines stacked from top to bottom.
+    #[default]
+    HorizontalTb,
+    /// Vertical lines stacked from right to left, with `vert` and `vrt2` glyphs.
+    VerticalRl,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParagraphOptions<'a> {
+    pub font: (&'a Path, usize),
+    pub size: f32,
+    pub named_instance_index: Option<usize>,
+    pub variations: &'a [Variation],
+    pub features: &'a [Feature],
+    pub language: Option<&'a Language>,
+    /// Maximum length of a line in pixels. Lines only break at newlines without it.
+    pub max_width: Option<f32>,
+    /// Distance between lines as a multiple of the font size. The line metrics of
+    /// the font are used without it.
+    pub line_height: Option<f32>,
+    pub align: TextAlign,
+    pub writing_mode: WritingMode,
+    /// Write compact SVG, with coordinates rounded to this many decimals, relative
+    /// commands, and each distinct glyph defined once and placed with `<use>`.
+    pub svg_precision: Option<u8>,
+}
+
+/// A glyph positioned relative to the pen, in pixels. Offsets point up like in
+/// font units, and the advance is along the line.
+#[derive(Debug, Clone, Copy)]
+struct ShapedGlyph {
+    glyph_id: GlyphId,
+    cluster: usize,
+    advance: f32,
+    x_offset: f32,
+    y_offset: f32,
+}
+
+/// A line of a paragraph, with its glyphs in visual order.
+#[derive(Debug, Clone)]
+struct Line {
+    glyphs: Vec<ShapedGlyph>,
+    length: f32,
+    rtl: bool,
+}
+
+/// Lay out text as a paragraph and render it to SVG, drawing color glyphs in color.
+pub fn render_paragraph(text: &str, options: ParagraphOptions) -> Result<Vec<u8>, RenderError> {
+    match options.svg_precision {
+        Some(precision) => render_svg(text, options, GlyphDefs::new(precision)),
+        None => render_svg(text, options, TextPath::new()),
+    }
+}
+
+fn render_svg(
+    text: &str,
+    options: ParagraphOptions,
+    pen: impl SvgPen,
+) -> Result<Vec<u8>, RenderError> {
+    let mut color_glyphs = ColorGlyphs::new(pen);
+    let (width, height) = draw_paragraph(text, options, &mut color_glyphs)?;
+
+    let document = color_glyphs.into_document(width, height);
+    check_output_size(document.to_string().into_bytes())
+}
+
+/// Lay out text as a paragraph and draw it into the pen, returning the size of the
+/// block it fits in.
+///
+/// Each newline starts a new paragraph. Paragraphs are split into runs of the same
+/// BiDi level and script, which are shaped separately, broken into lines at UAX #14
+/// opportunities and reordered visually per line. `svg_precision` is ignored.
+pub fn draw_paragraph(
+    text: &str,
+    ParagraphOptions {
+        font: (font_path, font_index),
+        size,
+        named_instance_index,
+        variations,
+        features,
+        language,
+        max_width,
+        line_height,
+        align,
+        writing_mode,
+        ..
+    }: ParagraphOptions,
+    pen: &mut impl GlyphPen,
+) -> Result<(f32, f32), RenderError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+    let run_font = RunFont::new(font.clone(), named_instance_index, variations);
+
+    let vertical = writing_mode == WritingMode::VerticalRl;
+    let size = Size::new(size);
+    let metrics = font.metrics(size, &run_font.location);
+    let scale = size.linear_scale(metrics.units_per_em);
+    let line_height = line_height
+        .map(|line_height| line_height * size.ppem().unwrap_or_default())
+        .unwrap_or(metrics.ascent - metrics.descent + metrics.leading);
+
+    let mut features = features.to_vec();
+    if vertical {
+        features.extend([b"vert", b"vrt2"].map(|tag| Feature::new(Tag::new(tag), 1, ..)));
+    }
+
+    // Shape part of a paragraph with the rest of it as context.
+    let shape = |paragraph: &str, range: Range<usize>, rtl: bool, script: Option<Script>| {
+        let run = TextRun {
+            text: &paragraph[range.clone()],
+            pre_context: &paragraph[..range.start],
+            post_context: &paragraph[range.end..],
+            direction: Some(match (vertical, rtl) {
+                (true, _) => Direction::TopToBottom,
+                (false, true) => Direction::RightToLeft,
+                (false, false) => Direction::LeftToRight,
+            }),
+            script,
+        };
+        let ShapedRun {
+            glyphs: glyph_buffer,
+            ..
+        } = shape_run(&run_font, run, &features, language, size.ppem());
+        iter::zip(glyph_buffer.glyph_infos(), glyph_buffer.glyph_positions())
+            .map(|(info, position)| ShapedGlyph {
+                glyph_id: GlyphId::new(info.glyph_id),
+                cluster: range.start + info.cluster as usize,
+                advance: if vertical {
+                    -position.y_advance as f32 * scale
+                } else {
+                    position.x_advance as f32 * scale
+                },
+                x_offset: position.x_offset as f32 * scale,
+                y_offset: position.y_offset as f32 * scale,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+        let bidi_info = ParagraphBidiInfo::new(paragraph, None);
+        let items = itemize(paragraph, &bidi_info.levels);
+
+        // Measure the paragraph as a whole to find where lines break.
+        let mut offsets = vec![0.0; paragraph.len() + 1];
+        for (range, level, script) in &items {
+            for glyph in shape(paragraph, range.clone(), level.is_rtl(), *script) {
+                offsets[glyph.cluster + 1] += glyph.advance;
+            }
+        }
+        for index in 1..offsets.len() {
+            offsets[index] += offsets[index - 1];
+        }
+        let length = |range: Range<usize>| offsets[range.end] - offsets[range.start];
+
+        // Vertical lines have no right-to-left direction, so start means top.
+        let rtl = !vertical && bidi_info.paragraph_level.is_rtl();
+        for range in break_lines(paragraph, max_width, length) {
+            if range.is_empty() {
+                lines.push(Line {
+                    glyphs: Vec::new(),
+                    length: 0.0,
+                    rtl,
+                });
+                continue;
+            }
+
+            let (levels, runs) = bidi_info.visual_runs(range.clone());
+
+            // Shape each line again so glyphs at the edges are shaped in isolation.
+            let mut glyphs = Vec::new();
+            for run in runs {
+                let level = levels[run.start];
+                let mut pieces = items
+                    .iter()
+                    .filter_map(|(item, _, script)| {
+                        let start = item.start.max(run.start);
+                        let end = item.end.min(run.end);
+                        (start < end).then_some((start..end, *script))
+                    })
+                    .collect::<Vec<_>>();
+                if level.is_rtl() {
+                    pieces.reverse();
+                }
+                for (piece, script) in pieces {
+                    glyphs.extend(shape(paragraph, piece, level.is_rtl(), script));
+                }
+            }
+
+            lines.push(Line {
+                length: glyphs.iter().map(|glyph| glyph.advance).sum(),
+                glyphs,
+                rtl,
+            });
+        }
+    }
+
+    let max_length = lines.iter().map(|line| line.length).fold(0.0, f32::max);
+    let container_length = max_width.unwrap_or(max_length).max(max_length);
+    let block_length = lines.len() as f32 * line_height;
+    let (width, height) = if vertical {
+        (block_length, container_length)
+    } else {
+        (container_length, block_length)
+    };
+
+    let mut positioned_glyphs = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let free_length = container_length - line.length;
+        let start = match (align, line.rtl) {
+            (TextAlign::Left, _) | (TextAlign::Start, false) | (TextAlign::End, true) => 0.0,
+            (TextAlign::Right, _) | (TextAlign::Start, true) | (TextAlign::End, false) => {
+                free_length
+            }
+            (TextAlign::Center, _) => free_length / 2.0,
+        };
+
+        let mut pen = start;
+        for glyph in &line.glyphs {
+            let (x, y) = if vertical {
+                let center = width - (index as f32 + 0.5) * line_height;
+                (center + glyph.x_offset, pen - glyph.y_offset)
+            } else {
+                let baseline = index as f32 * line_height
+                    + (line_height - (metrics.ascent - metrics.descent)) / 2.0
+                    + metrics.ascent;
+                (pen + glyph.x_offset, baseline - glyph.y_offset)
+            };
+            positioned_glyphs.push((0, glyph.glyph_id, x, y));
+            pen += glyph.advance;
+        }
+    }
+
+    draw_glyphs(pen, &[run_font], &positioned_glyphs, size)?;
+
+    Ok((width, height))
+}
+
+/// Split a paragraph into runs of the same BiDi level and script. Characters of
+/// common or inherited script join the run around them.
+fn itemize(paragraph: &str, levels: &[Level]) -> Vec<(Range<usize>, Level, Option<Script>)> {
+    let mut items = Vec::<(Range<usize>, Level, Option<Script>)>::new();
+    for (index, char) in paragraph.char_indices() {
+        let level = levels[index];
+        let script = match char.script() {
+            unicode_script::Script::Common
+            | unicode_script::Script::Inherited
+            | unicode_script::Script::Unknown => None,
+            script => {
+                let tag = script.short_name().as_bytes();
+                Script::from_iso15924_tag(Tag::new(&[tag[0], tag[1], tag[2], tag[3]]))
+            }
+        };
+        let end = index + char.len_utf8();
+
+        match items.last_mut() {
+            Some((range, item_level, item_script))
+                if *item_level == level
+                    && (script.is_none() || item_script.is_none() || *item_script == script) =>
+            {
+                range.end = end;
+                if item_script.is_none() {
+                    *item_script = script;
+                }
+            }
+            _ => items.push((index..end, level, script)),
+        }
+    }
+    items
+}
+
+/// Break a paragraph into lines no longer than the maximum length where possible,
+/// without the trailing whitespace of each line.
+fn break_lines(
+    paragraph: &str,
+    max_length: Option<f32>,
+    length: impl Fn(Range<usize>) -> f32,
+) -> Vec<Range<usize>> {
+    let trim_end =
+        |range: Range<usize>| range.start..range.start + paragraph[range].trim_end().len();
+
+    let mut lines = Vec::new();
+    let (mut start, mut last_fit) = (0, None);
+    for (index, opportunity) in linebreaks(paragraph) {
+        if let Some(max_length) = max_length
+            && let Some(fit) = last_fit
+            && length(trim_end(start..index)) > max_length
+        {
+            lines.push(trim_end(start..fit));
+            start = fit;
+        }
+        last_fit = Some(index);
+        if opportunity == BreakOpportunity::Mandatory {
+            lines.push(trim_end(start..index));
+            start = index;
+            last_fit = None;
+        }
+    }
+    if lines.is_empty() {
+        lines.push(0..0);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use skrifa::outline::OutlinePen;
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    /// A pen that records glyphs in the order they are drawn, with their origins.
+    #[derive(Default)]
+    struct Glyphs {
+        origin: (f32, f32),
+        glyphs: Vec<(GlyphId, f32)>,
+    }
+
+    impl OutlinePen for Glyphs {
+        fn move_to(&mut self, _x: f32, _y: f32) {}
+        fn line_to(&mut self, _x: f32, _y: f32) {}
+        fn quad_to(&mut self, _cx0: f32, _cy0: f32, _x: f32, _y: f32) {}
+        fn curve_to(&mut self, _cx0: f32, _cy0: f32, _cx1: f32, _cy1: f32, _x: f32, _y: f32) {}
+        fn close(&mut self) {}
+    }
+
+    impl GlyphPen for Glyphs {
+        fn set_origin(&mut self, x: f32, y: f32) {
+            self.origin = (x, y);
+        }
+
+        fn draw_color_glyph(
+            &mut self,
+            _font: &RunFont,
+            glyph_id: GlyphId,
+            _size: Size,
+        ) -> Result<bool, RenderError> {
+            self.glyphs.push((glyph_id, self.origin.0));
+            Ok(false)
+        }
+    }
+
+    fn size_of(svg: &[u8]) -> (f32, f32) {
+        let svg = str::from_utf8(svg).unwrap();
+        let attribute = |name: &str| {
+            let start = svg.find(&format!(" {name}=\"")).unwrap() + name.len() + 3;
+            let end = start + svg[start..].find('"').unwrap();
+            svg[start..end].parse::<f32>().unwrap()
+        };
+        (attribute("width"), attribute("height"))
+    }
+
+    #[test]
+    fn test_render_paragraph() {
+        let font_path = fixture("FiraMono-Medium.ttf");
+        let options = ParagraphOptions {
+            font: (&font_path, 0),
+            size: 20.0,
+            named_instance_index: None,
+            variations: &[],
+            features: &[],
+            language: None,
+            max_width: None,
+            line_height: Some(1.5),
+            align: TextAlign::Start,
+            writing_mode: WritingMode::HorizontalTb,
+            svg_precision: None,
+        };
+
+        let (width, height) = size_of(&render_paragraph("Fira Mono", options.clone()).unwrap());
+        assert_eq!(height, 30.0);
+
+        // Lines break at spaces, which don't count towards the line length.
+        let wrapped = render_paragraph(
+            "Fira Mono Fira Mono",
+            ParagraphOptions {
+                max_width: Some(width),
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(size_of(&wrapped), (width, 60.0));
+
+        // Newlines always break.
+        let (_, height) = size_of(&render_paragraph("Fira\nMono\n", options.clone()).unwrap());
+        assert_eq!(height, 90.0);
+
+        // Vertical lines are stacked along the x axis.
+        let (vertical_width, _) = size_of(
+            &render_paragraph(
+                "Fira\nMono",
+                ParagraphOptions {
+                    writing_mode: WritingMode::VerticalRl,
+                    ..options
+                },
+            )
+            .unwrap(),
+        );
+        assert_eq!(vertical_width, 60.0);
+    }
+
+    #[test]
+    fn test_render_paragraph_svg() {
+        let font_path = fixture("colr_1.ttf");
+        let options = ParagraphOptions {
+            font: (&font_path, 0),
+            size: 20.0,
+            named_instance_index: None,
+            variations: &[],
+            features: &[],
+            language: None,
+            max_width: None,
+            line_height: None,
+            align: TextAlign::Start,
+            writing_mode: WritingMode::HorizontalTb,
+            svg_precision: None,
+        };
+
+        // Color glyphs are painted like in previews.
+        let content =
+            String::from_utf8(render_paragraph("\u{F0100}\n\u{F0101}", options.clone()).unwrap())
+                .unwrap();
+        assert_eq!(content.matches("<linearGradient").count(), 2);
+
+        // Compact output defines each outline once.
+        let font_path = fixture("FiraMono-Medium.ttf");
+        let content = String::from_utf8(
+            render_paragraph(
+                "Mono\nMono",
+                ParagraphOptions {
+                    font: (&font_path, 0),
+                    svg_precision: Some(1),
+                    ..options
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(content.matches("<use").count(), 8);
+        assert_eq!(content.matches("<path").count(), 3);
+    }
+
+    #[test]
+    fn test_draw_paragraph_bidi() {
+        let font_path = fixture("DejaVuSans.ttf");
+        let options = ParagraphOptions {
+            font: (&font_path, 0),
+            size: 20.0,
+            named_instance_index: None,
+            variations: &[],
+            features: &[],
+            language: None,
+            max_width: Some(400.0),
+            line_height: None,
+            align: TextAlign::Start,
+            writing_mode: WritingMode::HorizontalTb,
+            svg_precision: None,
+        };
+        let draw = |text: &str| {
+            let mut pen = Glyphs::default();
+            draw_paragraph(text, options.clone(), &mut pen).unwrap();
+            pen.glyphs
+        };
+
+        let data = fs::read(&font_path).unwrap();
+        let charmap = FontRef::new(&data).unwrap().charmap();
+        let latin = "Hello"
+            .chars()
+            .map(|char| charmap.map(char).unwrap())
+            .collect::<Vec<_>>();
+
+        // The Arabic word is shaped from right to left, with its joining forms
+        // rather than the isolated forms of the cmap.
+        let arabic = draw("مرحبا")
+            .into_iter()
+            .map(|(glyph_id, _)| glyph_id)
+            .collect::<Vec<_>>();
+        assert_eq!(arabic.len(), 5);
+        let isolated = "مرحبا"
+            .chars()
+            .rev()
+            .map(|char| charmap.map(char).unwrap())
+            .collect::<Vec<_>>();
+        assert_ne!(arabic, isolated);
+
+        for (text, rtl) in [("Hello مرحبا", false), ("مرحبا Hello", true)] {
+            let glyphs = draw(text);
+            let glyph_ids = glyphs
+                .iter()
+                .map(|(glyph_id, _)| *glyph_id)
+                .collect::<Vec<_>>();
+            // Glyphs are drawn from left to right in visual order, with the Latin
+            // word on the left in both paragraph directions.
+            assert!(glyphs.is_sorted_by(|(_, a), (_, b)| a <= b), "{text}");
+            assert_eq!(glyph_ids[..5], latin, "{text}");
+            assert_eq!(glyph_ids[6..], arabic, "{text}");
+            // Right-to-left paragraphs start on the right.
+            assert_eq!(glyphs[0].1 > 0.0, rtl, "{text}");
+        }
+    }
+}
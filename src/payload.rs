@@ -40,3 +40,92 @@ pub struct VariationAxisPayload {
     pub default: f32,
     pub hidden: bool,
 }
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GlyphsEndpointPayload {
+    pub glyph_count: u32,
+    pub offset: u32,
+    pub limit: u32,
+    pub glyphs: Vec<GlyphPayload>,
+}
+
+// A glyph, measured in font units with y pointing up.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GlyphPayload {
+    pub id: u32,
+    pub name: Option<String>, // From the post or CFF table
+    pub codepoints: Vec<u32>,
+    pub advance_width: f32,
+    pub left_side_bearing: f32,
+    pub right_side_bearing: f32,
+    pub bounds: Option<[f32; 4]>, // [x_min, y_min, x_max, y_max]
+    pub hinted: Option<bool>,     // Unknown for CFF outlines
+    pub svg: String,
+}
+
+// Glyphs of shaped text in visual order, measured in font units with y
+// pointing up.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ShapingPayload {
+    pub units_per_em: u16,
+    pub direction: &'static str, // ltr, rtl, ttb or btt
+    pub script: String,          // ISO 15924 tag, such as Latn
+    pub glyphs: Vec<ShapedGlyphPayload>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ShapedGlyphPayload {
+    pub id: u32,
+    pub name: Option<String>, // From the post or CFF table
+    pub cluster: u32,         // UTF-8 byte offset of the first character in the text
+    pub x_advance: i32,
+    pub y_advance: i32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+}
+
+// Metrics of a face or instance in font units with y pointing up, except for
+// the Windows metrics, whose descent is positive downwards as in the table.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FontMetricsPayload {
+    pub units_per_em: u16,
+    pub ascent: f32, // Picked from the tables below like FreeType does
+    pub descent: f32,
+    pub line_gap: f32,
+    pub hhea: Option<LineMetricsPayload>,
+    pub typo: Option<LineMetricsPayload>,
+    pub win: Option<LineMetricsPayload>,
+    pub use_typo_metrics: bool,
+    pub line_ascent: f32, // Line laid out by the Windows client
+    pub line_descent: f32,
+    pub x_height: Option<f32>,
+    pub cap_height: Option<f32>,
+    pub underline: Option<DecorationPayload>,
+    pub strikeout: Option<DecorationPayload>,
+    pub italic_angle: f32, // Counter-clockwise degrees from the vertical
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LineMetricsPayload {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: Option<f32>, // Not in the Windows metrics
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DecorationPayload {
+    pub offset: f32, // To the top of the decoration
+    pub thickness: f32,
+}
+
+// Problem details of a failed request.
+// https://www.rfc-editor.org/rfc/rfc9457
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorPayload {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+}
@@ -0,0 +1,397 @@
+use std::{collections::HashSet, fs, num::NonZeroUsize, path::PathBuf, time::SystemTime};
+
+use axum::body::Bytes;
+use itertools::Itertools;
+use lru::LruCache;
+use read_fonts::TableProvider;
+use skrifa::{FontRef, MetadataProvider, string::StringId};
+
+use crate::{
+    font::SkrifaFontRefExt,
+    renderer::{RenderError, RenderFormat, RenderOptions, render_text},
+};
+
+/// What to render when the family name can't be rendered with the font itself.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewFallback {
+    /// The sample text provided by the font (name ID 19).
+    SampleText,
+    /// A sample of a script the font supports, based on the OS/2 Unicode ranges
+    /// and the cmap.
+    ScriptSample,
+    /// The family name in another language provided by the font.
+    LocalizedName,
+    /// The family name with missing characters taken from the fallback font.
+    FallbackFont,
+}
+
+impl PreviewFallback {
+    pub const ALL: [PreviewFallback; 4] = [
+        PreviewFallback::SampleText,
+        PreviewFallback::ScriptSample,
+        PreviewFallback::LocalizedName,
+        PreviewFallback::FallbackFont,
+    ];
+}
+
+/// Samples of scripts, keyed by their OS/2 Unicode range bit.
+///
+/// https://learn.microsoft.com/en-us/typography/opentype/spec/os2#ulunicoderange1-bits-031ulunicoderange2-bits-3263ulunicoderange3-bits-6495ulunicoderange4-bits-96127
+const SCRIPT_SAMPLES: [(u32, &str); 14] = [
+    (0, "Aa Bb Cc"),
+    (7, "Αα Ββ Γγ"),
+    (9, "Аа Бб Вв"),
+    (10, "Աա Բբ Գգ"),
+    (11, "אבגד"),
+    (13, "ابجد"),
+    (15, "अआइई"),
+    (16, "অআইঈ"),
+    (24, "กขคง"),
+    (26, "აბგდ"),
+    (49, "あいうえ"),
+    (50, "アイウエ"),
+    (56, "가나다라"),
+    (59, "永字八法"),
+];
+
+/// Number of characters of the sample taken from the cmap.
+const CMAP_SAMPLE_LENGTH: usize = 8;
+
+/// Render the family name, trying the fallbacks in order if the font can't render it.
+pub fn render_preview(
+    family_name: &str,
+    options: RenderOptions,
+    fallbacks: &[PreviewFallback],
+) -> Result<Option<Vec<u8>>, RenderError> {
+    let own_options = RenderOptions {
+        fallback_font: None,
+        ..options.clone()
+    };
+    if let Some(content) = render_text(family_name, own_options.clone())? {
+        return Ok(Some(content));
+    }
+
+    let data = fs::read(options.font.0)?;
+    let font = FontRef::from_index(&data, options.font.1 as u32)?;
+
+    for fallback in fallbacks {
+        let candidates = match fallback {
+            PreviewFallback::SampleText => font.string(StringId::SAMPLE_TEXT).into_iter().collect(),
+            PreviewFallback::ScriptSample => script_samples(&font),
+            PreviewFallback::LocalizedName => localized_names(&font, family_name),
+            PreviewFallback::FallbackFont => {
+                if options.fallback_font.is_some()
+                    && let Some(content) = render_text(family_name, options.clone())?
+                {
+                    tracing::debug!("Rendered preview with fallback font");
+                    return Ok(Some(content));
+                }
+                continue;
+            }
+        };
+
+        for text in candidates {
+            if let Some(content) = render_text(&text, own_options.clone())? {
+                tracing::debug!("Rendered preview with {fallback:?}: {text:?}");
+                return Ok(Some(content));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Samples of the scripts declared in the OS/2 table, followed by the first
+/// characters of the cmap.
+pub(crate) fn script_samples(font: &FontRef) -> Vec<String> {
+    let unicode_ranges = font
+        .os2()
+        .map(|os2| {
+            [
+                os2.ul_unicode_range_1(),
+                os2.ul_unicode_range_2(),
+                os2.ul_unicode_range_3(),
+                os2.ul_unicode_range_4(),
+            ]
+        })
+        .unwrap_or_default();
+
+    let cmap_sample = font
+        .charmap()
+        .mappings()
+        .filter_map(|(codepoint, _)| char::from_u32(codepoint))
+        .filter(|char| !char.is_control() && !char.is_whitespace())
+        .take(CMAP_SAMPLE_LENGTH)
+        .collect::<String>();
+
+    SCRIPT_SAMPLES
+        .iter()
+        .filter(|(bit, _)| unicode_ranges[*bit as usize / 32] & (1 << (bit % 32)) != 0)
+        .map(|(_, sample)| sample.to_string())
+        .chain(Some(cmap_sample).filter(|sample| !sample.is_empty()))
+        .collect()
+}
+
+/// Family names in all languages, other than the one that failed to render.
+fn localized_names(font: &FontRef, family_name: &str) -> Vec<String> {
+    [StringId::TYPOGRAPHIC_FAMILY_NAME, StringId::FAMILY_NAME]
+        .into_iter()
+        .flat_map(|id| font.localized_strings(id))
+        .map(|localized_string| localized_string.to_string())
+        .filter(|name| name != family_name)
+        .unique()
+        .collect()
+}
+
+/// Everything a rendered preview depends on, including the settings of the
+/// config that change it, so that its ETag changes with them.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PreviewKey {
+    pub path: PathBuf,
+    pub modified_at: Option<SystemTime>,
+    pub index: usize,
+    pub named_instance_index: Option<usize>,
+    pub text: String,
+    /// Bits of the font size, since floats aren't hashable.
+    pub size: u32,
+    pub format: RenderFormat,
+    /// Bits of the device pixel ratio.
+    pub pixel_ratio: u32,
+    /// Variation coordinates, OpenType features and language, as given in the query.
+    pub variations: String,
+    pub features: String,
+    pub language: String,
+    pub svg_precision: Option<u8>,
+    /// Fallbacks tried if the text can't be rendered, none for an exact render.
+    pub fallbacks: Vec<PreviewFallback>,
+    /// Font file for characters missing from the font.
+    pub fallback_font: Option<PathBuf>,
+    pub fallback_font_modified_at: Option<SystemTime>,
+}
+
+/// A bounded cache of rendered previews, including fonts that have no preview.
+#[derive(Debug)]
+pub struct PreviewCache {
+    entries: Option<LruCache<PreviewKey, Option<Bytes>>>,
+}
+
+impl PreviewCache {
+    /// Create a cache holding up to `capacity` previews. A capacity of 0 disables it.
+    pub fn new(capacity: usize) -> Self {
+        PreviewCache {
+            entries: NonZeroUsize::new(capacity).map(LruCache::new),
+        }
+    }
+
+    /// Return the cached preview, where `Some(None)` means the font has no preview.
+    pub fn get(&mut self, key: &PreviewKey) -> Option<Option<Bytes>> {
+        self.entries.as_mut()?.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: PreviewKey, content: Option<Bytes>) {
+        if let Some(entries) = &mut self.entries {
+            entries.put(key, content);
+        }
+    }
+
+    /// Drop the previews of the given font files.
+    pub fn invalidate(&mut self, paths: &HashSet<PathBuf>) {
+        let Some(entries) = &mut self.entries else {
+            return;
+        };
+        let keys = entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| paths.contains(&key.path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            entries.pop(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use skrifa::{GlyphId, instance::Size, outline::OutlinePen};
+
+    use super::*;
+    use crate::{
+        fixtures::{fixture, render_options},
+        renderer::{GlyphPen, RunFont, draw_text},
+    };
+
+    fn options<'a>(font: &'a Path, fallback_font: Option<&'a Path>) -> RenderOptions<'a> {
+        RenderOptions {
+            fallback_font: fallback_font.map(|path| (path, 0)),
+            ..render_options(font)
+        }
+    }
+
+    /// A pen that records whether each glyph is taken from a font with Hebrew letters.
+    #[derive(Default)]
+    struct HebrewGlyphs(Vec<bool>);
+
+    impl OutlinePen for HebrewGlyphs {
+        fn move_to(&mut self, _x: f32, _y: f32) {}
+        fn line_to(&mut self, _x: f32, _y: f32) {}
+        fn quad_to(&mut self, _cx0: f32, _cy0: f32, _x: f32, _y: f32) {}
+        fn curve_to(&mut self, _cx0: f32, _cy0: f32, _cx1: f32, _cy1: f32, _x: f32, _y: f32) {}
+        fn close(&mut self) {}
+    }
+
+    impl GlyphPen for HebrewGlyphs {
+        fn set_origin(&mut self, _x: f32, _y: f32) {}
+
+        fn draw_color_glyph(
+            &mut self,
+            font: &RunFont,
+            _glyph_id: GlyphId,
+            _size: Size,
+        ) -> Result<bool, RenderError> {
+            self.0.push(font.font.charmap().map('א').is_some());
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn test_render_preview_fallback_font() {
+        let (font, fallback_font) = (
+            fixture("FiraMono-Medium.ttf"),
+            fixture("NotoSansHebrew-VF.ttf"),
+        );
+        let text = "Mono אבג";
+
+        // The characters missing from the font are taken from the fallback font.
+        let mut pen = HebrewGlyphs::default();
+        draw_text(text, options(&font, Some(&fallback_font)), &mut pen)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pen.0, [false, false, false, false, false, true, true, true]);
+
+        assert_eq!(
+            render_preview(
+                text,
+                options(&font, Some(&fallback_font)),
+                &[PreviewFallback::FallbackFont]
+            )
+            .unwrap(),
+            render_text(text, options(&font, Some(&fallback_font))).unwrap()
+        );
+        assert_eq!(
+            render_preview(text, options(&font, None), &[PreviewFallback::FallbackFont]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_preview_script_sample() {
+        let font = fixture("NotoSansHebrew-VF.ttf");
+        let data = fs::read(&font).unwrap();
+        let samples = script_samples(&FontRef::new(&data).unwrap());
+        assert_eq!(&samples[..2], ["Aa Bb Cc", "אבגד"]);
+
+        // A name with a character the font doesn't have falls back to the first
+        // sample it can render, as the subset lacks the letters of the Latin one.
+        assert_eq!(
+            render_preview(
+                "Noto Sans Hebrew ✓",
+                options(&font, None),
+                &[PreviewFallback::ScriptSample]
+            )
+            .unwrap(),
+            render_text("אבגד", options(&font, None)).unwrap()
+        );
+
+        // Icon fonts declare no script, so the sample is taken from the cmap.
+        let font = fixture("FontAwesome.otf");
+        let data = fs::read(&font).unwrap();
+        let samples = script_samples(&FontRef::new(&data).unwrap());
+        assert_eq!(
+            samples,
+            ["\u{f000}\u{f001}\u{f002}\u{f003}\u{f004}\u{f005}\u{f006}\u{f007}"]
+        );
+        assert_eq!(
+            render_preview("FontAwesome", options(&font, None), &PreviewFallback::ALL).unwrap(),
+            render_text(&samples[0], options(&font, None)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_preview_nothing() {
+        let font = fixture("FontAwesome.otf");
+        assert_eq!(
+            render_preview("FontAwesome", options(&font, None), &[]).unwrap(),
+            None
+        );
+        assert_eq!(
+            render_preview(
+                "FontAwesome",
+                options(&font, None),
+                &[
+                    PreviewFallback::SampleText,
+                    PreviewFallback::LocalizedName,
+                    PreviewFallback::FallbackFont
+                ]
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    fn key(path: &str, text: &str) -> PreviewKey {
+        PreviewKey {
+            path: path.into(),
+            modified_at: None,
+            index: 0,
+            named_instance_index: None,
+            text: text.into(),
+            size: 16f32.to_bits(),
+            format: RenderFormat::Svg,
+            pixel_ratio: 1f32.to_bits(),
+            variations: String::new(),
+            features: String::new(),
+            language: String::new(),
+            svg_precision: None,
+            fallbacks: PreviewFallback::ALL.into(),
+            fallback_font: None,
+            fallback_font_modified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_preview_cache() {
+        let mut cache = PreviewCache::new(2);
+        cache.insert(key("/a.ttf", "A"), Some(Bytes::from_static(b"a")));
+        cache.insert(key("/b.ttf", "B"), None);
+        assert_eq!(
+            cache.get(&key("/a.ttf", "A")),
+            Some(Some(Bytes::from_static(b"a")))
+        );
+        assert_eq!(cache.get(&key("/b.ttf", "B")), Some(None));
+
+        // The least recently used entry is evicted.
+        cache.insert(key("/c.ttf", "C"), None);
+        assert_eq!(cache.get(&key("/a.ttf", "A")), None);
+
+        cache.invalidate(&HashSet::from([PathBuf::from("/b.ttf")]));
+        assert_eq!(cache.get(&key("/b.ttf", "B")), None);
+        assert_eq!(cache.get(&key("/c.ttf", "C")), Some(None));
+
+        // An exact render of the text is cached apart from the preview with fallbacks.
+        let exact_key = PreviewKey {
+            fallbacks: Vec::new(),
+            ..key("/c.ttf", "C")
+        };
+        assert_eq!(cache.get(&exact_key), None);
+        cache.insert(exact_key.clone(), Some(Bytes::from_static(b"c")));
+        assert_eq!(cache.get(&key("/c.ttf", "C")), Some(None));
+
+        let mut cache = PreviewCache::new(0);
+        cache.insert(key("/a.ttf", "A"), None);
+        assert_eq!(cache.get(&key("/a.ttf", "A")), None);
+    }
+}
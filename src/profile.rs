@@ -0,0 +1,73 @@
+use crate::config::{UserInstalled, default_bool};
+
+/// The official client that the agent pretends to be.
+#[derive(Debug, Clone, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientProfile {
+    #[default]
+    WindowsLatest,
+    Macos,
+    Custom(Profile),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, serde::Deserialize)]
+pub struct Profile {
+    pub package: String,
+    pub version: u32,
+    #[serde(default = "default_bool::<true>")]
+    pub variation_axes: bool,
+    #[serde(default)]
+    pub user_installed: UserInstalled,
+}
+
+impl ClientProfile {
+    pub fn profile(&self) -> Profile {
+        match self {
+            // These values are taken from the official Windows client as of 2025-11-09.
+            ClientProfile::WindowsLatest => Profile {
+                package: "125.9.10".into(),
+                version: 23,
+                variation_axes: true,
+                user_installed: UserInstalled::Auto,
+            },
+            // The macOS client hasn't been captured yet, so this reports the same
+            // payload as the Windows client. Use a custom profile to report another
+            // version.
+            ClientProfile::Macos => ClientProfile::WindowsLatest.profile(),
+            ClientProfile::Custom(profile) => profile.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(
+            serde_json::from_str::<ClientProfile>(r#""windows-latest""#).unwrap(),
+            ClientProfile::WindowsLatest,
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientProfile>(r#""macos""#).unwrap(),
+            ClientProfile::Macos,
+        );
+        assert_eq!(
+            ClientProfile::Macos.profile(),
+            ClientProfile::WindowsLatest.profile(),
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientProfile>(
+                r#"{ "custom": { "package": "126.0.0", "version": 24, "variation_axes": false } }"#,
+            )
+            .unwrap(),
+            ClientProfile::Custom(Profile {
+                package: "126.0.0".into(),
+                version: 24,
+                variation_axes: false,
+                user_installed: UserInstalled::Auto,
+            }),
+        );
+    }
+}
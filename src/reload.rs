@@ -0,0 +1,300 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use ::notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use arc_swap::ArcSwap;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::{RwLock, mpsc},
+};
+
+use crate::{
+    CONFIG, EFFECTIVE_FONT_DIRECTORIES, FONT_FILES_PAYLOAD, PREVIEW_CACHE, XDG_DIRECTORIES,
+    config::Config, effective_font_directories, http::EncodedBody, load_config, notify_status,
+    preview::PreviewCache, scan_font_files, scanner::FontDirectory,
+};
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// How long to wait for more changes after the config file changed, since
+/// editors can save a file in several steps.
+const CONFIG_SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// The state that depends on the config, which a reloaded config is applied to.
+struct ConfigState<'a> {
+    config: &'a ArcSwap<Config>,
+    font_directories: &'a ArcSwap<Vec<FontDirectory>>,
+    font_files_payload: &'a RwLock<Option<Arc<EncodedBody>>>,
+    preview_cache: &'a Mutex<PreviewCache>,
+}
+
+impl ConfigState<'static> {
+    /// The state of the service.
+    fn global() -> Self {
+        ConfigState {
+            config: &CONFIG,
+            font_directories: &EFFECTIVE_FONT_DIRECTORIES,
+            font_files_payload: &FONT_FILES_PAYLOAD,
+            preview_cache: &PREVIEW_CACHE,
+        }
+    }
+}
+
+/// Reload the config file and apply it, keeping the current config if the new
+/// one is invalid, and rescan the font files. Settings read at startup only
+/// take effect after a restart, so they're compared with `started`, the config the
+/// service started with.
+#[tracing::instrument(skip(started))]
+pub async fn reload_config(started: &Config) {
+    tracing::info!("Reloading config...");
+    notify_status("Reloading config...");
+    if update_config(&ConfigState::global(), load_config(), started).await {
+        scan_font_files().await;
+    }
+}
+
+/// Apply the reloaded config, or keep the current one if it's `None` because the
+/// config file is invalid. Returns whether the config was reloaded.
+async fn update_config(state: &ConfigState<'_>, config: Option<Config>, started: &Config) -> bool {
+    let Some(config) = config else {
+        tracing::warn!("Kept the current config");
+        notify_status("Failed to reload config, kept the current one");
+        return false;
+    };
+
+    if config == **state.config.load() {
+        tracing::info!("Config unchanged");
+    } else {
+        apply_config(state, config, started).await;
+    }
+    true
+}
+
+async fn apply_config(state: &ConfigState<'_>, config: Config, started: &Config) {
+    if config.bind != started.bind {
+        tracing::warn!("Changed bind requires a restart to take effect");
+    }
+    if config.idle_timeout != started.idle_timeout {
+        tracing::warn!("Changed idle_timeout requires a restart to take effect");
+    }
+
+    let preview_cache_size = config.preview_cache_size;
+    let directories = effective_font_directories(&config);
+    state.config.store(Arc::new(config));
+    state.font_directories.store(Arc::new(directories));
+
+    // The profile, fallbacks and compression change the payload and previews of
+    // fonts that didn't change.
+    state.font_files_payload.write().await.take();
+    *state
+        .preview_cache
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = PreviewCache::new(preview_cache_size);
+}
+
+/// Reload the config on SIGHUP and whenever the config file changes.
+pub async fn watch_config(started: Arc<Config>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(error) => {
+            tracing::warn!("Failed to listen for SIGHUP, error: {error:?}");
+            None
+        }
+    };
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    let mut watcher = ConfigWatcher::new(sender)
+        .inspect_err(|error| {
+            tracing::warn!("Failed to watch the config file, reload with SIGHUP, error: {error:?}");
+        })
+        .ok();
+    let mut config_file = config_file_version();
+    loop {
+        let hangup = async {
+            match &mut hangup {
+                Some(hangup) => hangup.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = hangup => {}
+            Some(()) = changes.recv() => {
+                tokio::time::sleep(CONFIG_SETTLE_DELAY).await;
+                while changes.try_recv().is_ok() {}
+                if let Some(watcher) = &mut watcher {
+                    watcher.watch_directories();
+                }
+                if config_file_version() == config_file {
+                    continue;
+                }
+            }
+        }
+        config_file = config_file_version();
+        reload_config(&started).await;
+    }
+}
+
+/// Watches the directories the config file is looked up in, or the parents of
+/// the ones that don't exist yet to see them created.
+struct ConfigWatcher {
+    watcher: RecommendedWatcher,
+    watched: HashSet<PathBuf>,
+}
+
+impl ConfigWatcher {
+    /// Send to `changes` whenever a config file or one of its directories changes.
+    fn new(changes: mpsc::UnboundedSender<()>) -> ::notify::Result<Self> {
+        let directories = config_directories();
+        let watcher =
+            ::notify::recommended_watcher(move |event: ::notify::Result<Event>| match event {
+                Ok(event) => {
+                    if event.paths.iter().any(|path| {
+                        directories.iter().any(|directory| {
+                            path == directory || *path == directory.join(CONFIG_FILE_NAME)
+                        })
+                    }) {
+                        let _ = changes.send(());
+                    }
+                }
+                Err(error) => tracing::warn!("Failed to watch the config file, error: {error:?}"),
+            })?;
+        let mut config_watcher = ConfigWatcher {
+            watcher,
+            watched: HashSet::new(),
+        };
+        config_watcher.watch_directories();
+        Ok(config_watcher)
+    }
+
+    /// Watch the directories that were created since, and forget the removed ones.
+    fn watch_directories(&mut self) {
+        self.watched.retain(|path| path.is_dir());
+        for directory in config_directories() {
+            let path = if directory.is_dir() {
+                directory
+            } else {
+                match directory.parent() {
+                    Some(parent) if parent.is_dir() => parent.to_path_buf(),
+                    _ => continue,
+                }
+            };
+            if self.watched.contains(&path) {
+                continue;
+            }
+            match self.watcher.watch(&path, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    tracing::debug!("Watching {path:?} for the config file");
+                    self.watched.insert(path);
+                }
+                Err(error) => tracing::warn!("Failed to watch {path:?}, error: {error:?}"),
+            }
+        }
+    }
+}
+
+/// The directories the config file is looked up in, the user's first.
+fn config_directories() -> Vec<PathBuf> {
+    XDG_DIRECTORIES
+        .get_config_home()
+        .into_iter()
+        .chain(XDG_DIRECTORIES.get_config_dirs())
+        .collect()
+}
+
+/// The path and modification time of the config file, which tell it changed,
+/// including when it's created, removed or shadowed by another one.
+fn config_file_version() -> Option<(PathBuf, Option<SystemTime>)> {
+    let path = XDG_DIRECTORIES.find_config_file(CONFIG_FILE_NAME)?;
+    let modified_at = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    Some((path, modified_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use axum::body::Bytes;
+
+    use super::*;
+    use crate::{
+        fixtures::fixtures_dir, load_config_file, preview::PreviewKey, renderer::RenderFormat,
+    };
+
+    #[tokio::test]
+    async fn test_update_config() {
+        let path = env::temp_dir().join(format!("figma-agent-config-{}.json", process::id()));
+        let started = Arc::new(Config::default());
+        let (config, font_directories, font_files_payload, preview_cache) = (
+            ArcSwap::new(started.clone()),
+            ArcSwap::from_pointee(Vec::new()),
+            RwLock::new(None),
+            Mutex::new(PreviewCache::new(started.preview_cache_size)),
+        );
+        let state = ConfigState {
+            config: &config,
+            font_directories: &font_directories,
+            font_files_payload: &font_files_payload,
+            preview_cache: &preview_cache,
+        };
+        let preview_key = PreviewKey {
+            path: "/a.ttf".into(),
+            modified_at: None,
+            index: 0,
+            named_instance_index: None,
+            text: "A".into(),
+            size: 16f32.to_bits(),
+            format: RenderFormat::Svg,
+            pixel_ratio: 1f32.to_bits(),
+            variations: String::new(),
+            features: String::new(),
+            language: String::new(),
+            svg_precision: None,
+            fallbacks: Vec::new(),
+            fallback_font: None,
+            fallback_font_modified_at: None,
+        };
+
+        // An invalid config keeps the current one.
+        fs::write(&path, r#"{ "bind": [] }"#).unwrap();
+        assert!(!update_config(&state, load_config_file(&path), &started).await);
+        assert!(Arc::ptr_eq(&config.load_full(), &started));
+
+        let body = EncodedBody::new("application/json", b"{}".to_vec(), false).unwrap();
+        *font_files_payload.write().await = Some(Arc::new(body));
+        preview_cache
+            .lock()
+            .unwrap()
+            .insert(preview_key.clone(), Some(Bytes::from_static(b"a")));
+
+        let font_directory = fixtures_dir();
+        fs::write(
+            &path,
+            format!(
+                r#"{{ "use_system_fonts": false, "font_directories": [{:?}], "preview_cache_size": 8 }}"#,
+                font_directory
+            ),
+        )
+        .unwrap();
+        assert!(update_config(&state, load_config_file(&path), &started).await);
+        let reloaded = config.load_full();
+        assert_eq!(reloaded.preview_cache_size, 8);
+        assert_eq!(reloaded.font_directories, vec![font_directory.clone()]);
+        let directories = font_directories.load_full();
+        assert_eq!(directories.len(), 1);
+        assert_eq!(directories[0].path, font_directory);
+        assert!(font_files_payload.read().await.is_none());
+        assert_eq!(preview_cache.lock().unwrap().get(&preview_key), None);
+
+        // The same config again leaves the current one in place.
+        assert!(update_config(&state, load_config_file(&path), &started).await);
+        assert!(Arc::ptr_eq(&config.load_full(), &reloaded));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,10 +1,13 @@
-use std::{fs, iter, path::Path};
+use std::{collections::HashMap, fs, iter, path::Path};
 
-use harfrust::{ShapeOptions, ShaperData, ShaperInstance, UnicodeBuffer};
+use harfrust::{Feature, Language, Variation};
+use kurbo::{BezPath, Point};
+use read_fonts::TableProvider;
 use skrifa::{
     FontRef, GlyphId, MetadataProvider,
-    instance::Size,
-    outline::{DrawError, DrawSettings, OutlinePen},
+    color::PaintError,
+    instance::{Location, Size},
+    outline::{DrawError, DrawSettings, OutlineGlyphCollection, OutlinePen},
 };
 use svg::{
     Document,
@@ -13,6 +16,12 @@ use svg::{
         path::{Command, Position},
     },
 };
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Transform};
+
+use crate::{
+    color::{ColorGlyphs, RasterColorGlyphs},
+    shaping::{ShapedRun, shape_run},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum RenderError {
@@ -22,6 +31,37 @@ pub enum RenderError {
     Parse(#[from] read_fonts::ReadError),
     #[error("Failed to draw glyph")]
     Draw(#[from] DrawError),
+    #[error("Failed to paint color glyph")]
+    Paint(#[from] PaintError),
+    #[error("Failed to encode image")]
+    Encode(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Render limit exceeded: {0}")]
+    TooLarge(String),
+}
+
+/// Upper bound of the glyphs of a line of text, which shaping can make many more
+/// than its characters.
+pub const MAX_GLYPHS: usize = 4096;
+/// Upper bound of the pixels of raster output.
+pub const MAX_PIXELS: u64 = 1 << 24;
+/// Upper bound of the size of encoded output in bytes.
+pub const MAX_OUTPUT_SIZE: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl RenderFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "image/svg+xml",
+            RenderFormat::Png => "image/png",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -29,84 +69,272 @@ pub struct RenderOptions<'a> {
     pub font: (&'a Path, usize),
     pub size: f32,
     pub named_instance_index: Option<usize>,
+    /// Axis coordinates in user space, overriding those of the named instance.
+    pub variations: &'a [Variation],
+    /// OpenType features applied during shaping, such as `ss01` or `-liga`.
+    pub features: &'a [Feature],
+    /// Language of the text, selecting language-specific forms with `locl`.
+    pub language: Option<&'a Language>,
+    pub format: RenderFormat,
+    /// Device pixel ratio of raster output. SVG output is always in CSS pixels.
+    pub pixel_ratio: f32,
+    /// Write compact SVG, with coordinates rounded to this many decimals, relative
+    /// commands, and each distinct glyph defined once and placed with `<use>`.
+    pub svg_precision: Option<u8>,
+    /// A font for the characters missing from `font`. Without one, nothing is
+    /// rendered if any character is missing.
+    pub fallback_font: Option<(&'a Path, usize)>,
+}
+
+/// A font that glyphs of the text are taken from.
+pub struct RunFont<'a> {
+    pub font: FontRef<'a>,
+    pub location: Location,
+    pub variations: Vec<Variation>,
+    pub outlines: OutlineGlyphCollection<'a>,
+}
+
+impl<'a> RunFont<'a> {
+    pub fn new(
+        font: FontRef<'a>,
+        named_instance_index: Option<usize>,
+        variations: &[Variation],
+    ) -> Self {
+        let axes = font.axes();
+        let mut settings = named_instance_index
+            .and_then(|index| font.named_instances().get(index))
+            .map(|named_instance| {
+                iter::zip(axes.iter(), named_instance.user_coords())
+                    .map(|(axis, value)| Variation {
+                        tag: axis.tag(),
+                        value,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for variation in variations {
+            settings.retain(|setting| setting.tag != variation.tag);
+            settings.push(*variation);
+        }
+
+        let location = axes.location(
+            settings
+                .iter()
+                .map(|variation| (variation.tag, variation.value)),
+        );
+        let outlines = font.outline_glyphs();
+        RunFont {
+            font,
+            location,
+            variations: settings,
+            outlines,
+        }
+    }
 }
 
-// TODO: The official implementation seems to use some other methods to calculate
-// the height of the generated image and the position of the text. Our logic based
-// on baseline and ascent/descent may cause some fonts to not be vertically centered
-// properly in the font picker. Needs further investigation.
 pub fn render_text(
+    text: impl AsRef<str>,
+    options: RenderOptions,
+) -> Result<Option<Vec<u8>>, RenderError> {
+    match options.format {
+        RenderFormat::Svg => match options.svg_precision {
+            Some(precision) => render_svg(text, options, GlyphDefs::new(precision)),
+            None => render_svg(text, options, TextPath::new()),
+        },
+        RenderFormat::Png => {
+            let pixel_ratio = options.pixel_ratio;
+            let mut color_glyphs = RasterColorGlyphs::new(pixel_ratio);
+            let Some((width, height)) = draw_text(text, options, &mut color_glyphs)? else {
+                return Ok(None);
+            };
+
+            let pixmap_width = (width * pixel_ratio).ceil().max(1.0) as u32;
+            let pixmap_height = (height * pixel_ratio).ceil().max(1.0) as u32;
+            if pixmap_width as u64 * pixmap_height as u64 > MAX_PIXELS {
+                return Err(RenderError::TooLarge(format!(
+                    "image of {pixmap_width}x{pixmap_height} pixels exceeds {MAX_PIXELS} pixels"
+                )));
+            }
+            let mut pixmap = Pixmap::new(pixmap_width, pixmap_height).ok_or_else(|| {
+                RenderError::Encode(
+                    format!("invalid image size: {pixmap_width}x{pixmap_height}").into(),
+                )
+            })?;
+
+            color_glyphs.draw(&mut pixmap);
+
+            let content = pixmap
+                .encode_png()
+                .map_err(|error| RenderError::Encode(error.into()))?;
+            check_output_size(content).map(Some)
+        }
+    }
+}
+
+/// Render SVG with the pen, drawing color glyphs in color.
+fn render_svg(
+    text: impl AsRef<str>,
+    options: RenderOptions,
+    pen: impl SvgPen,
+) -> Result<Option<Vec<u8>>, RenderError> {
+    let mut color_glyphs = ColorGlyphs::new(pen);
+    let Some((width, height)) = draw_text(text, options, &mut color_glyphs)? else {
+        return Ok(None);
+    };
+
+    let document = color_glyphs.into_document(width, height);
+    check_output_size(document.to_string().into_bytes()).map(Some)
+}
+
+pub(crate) fn check_output_size(content: Vec<u8>) -> Result<Vec<u8>, RenderError> {
+    if content.len() > MAX_OUTPUT_SIZE {
+        return Err(RenderError::TooLarge(format!(
+            "output of {} bytes exceeds {MAX_OUTPUT_SIZE} bytes",
+            content.len()
+        )));
+    }
+    Ok(content)
+}
+
+/// Lay out a line of text and draw it into the pen, returning the size of the
+/// image it fits in. `format` and `pixel_ratio` are ignored.
+pub fn draw_text(
     text: impl AsRef<str>,
     RenderOptions {
         font: (font_path, font_index),
         size,
         named_instance_index,
+        variations,
+        features,
+        language,
+        fallback_font,
+        ..
     }: RenderOptions,
-) -> Result<Option<String>, RenderError> {
+    pen: &mut impl GlyphPen,
+) -> Result<Option<(f32, f32)>, RenderError> {
     let data = fs::read(font_path)?;
     let font = FontRef::from_index(&data, font_index as u32)?;
+    let fallback_data = fallback_font
+        .map(|(path, index)| fs::read(path).map(|data| (data, index)))
+        .transpose()?;
 
-    let size = Size::new(size);
-    let location = named_instance_index
-        .and_then(|index| font.named_instances().get(index))
-        .map(|named_instance| named_instance.location())
-        .unwrap_or_default();
+    let mut fonts = vec![RunFont::new(font.clone(), named_instance_index, variations)];
+    if let Some((data, index)) = &fallback_data {
+        fonts.push(RunFont::new(
+            FontRef::from_index(data, *index as u32)?,
+            None,
+            variations,
+        ));
+    }
 
-    let metrics = font.metrics(size, &location);
-    let charmap = font.charmap();
-    let outlines = font.outline_glyphs();
+    let size = Size::new(size);
+    let metrics = font.metrics(size, &fonts[0].location);
 
-    let text = text.as_ref();
-    if text.chars().any(|char| charmap.map(char).is_none()) {
-        return Ok(None);
+    // Split the text into runs of characters taken from the same font.
+    let charmaps = fonts
+        .iter()
+        .map(|run_font| run_font.font.charmap())
+        .collect::<Vec<_>>();
+    let mut runs = Vec::<(usize, String)>::new();
+    for char in text.as_ref().chars() {
+        let Some(font_index) = charmaps
+            .iter()
+            .position(|charmap| charmap.map(char).is_some())
+        else {
+            return Ok(None);
+        };
+        match runs.last_mut() {
+            Some((run_font_index, run)) if *run_font_index == font_index => run.push(char),
+            _ => runs.push((font_index, char.into())),
+        }
     }
 
-    let shaper_data = ShaperData::new(&font);
-    let shaper_instance = named_instance_index
-        .map(|index| ShaperInstance::from_named_instance(&font, index))
-        .unwrap_or_default();
-    let shaper = shaper_data
-        .shaper(&font)
-        .instance(Some(&shaper_instance))
-        .build();
+    let (mut cursor_x, mut cursor_y) = (0.0, metrics.ascent);
+    let mut glyphs = Vec::new();
 
-    let mut buffer = UnicodeBuffer::new();
-    buffer.push_str(text);
-    buffer.guess_segment_properties();
+    for (font_index, run) in runs {
+        let run_font = &fonts[font_index];
+        let ShapedRun {
+            glyphs: glyph_buffer,
+            ..
+        } = shape_run(run_font, run.as_str(), features, language, size.ppem());
 
-    let glyph_buffer = shaper.shape(buffer, ShapeOptions::new().point_size(size.ppem()));
+        if glyphs.len() + glyph_buffer.len() > MAX_GLYPHS {
+            return Err(RenderError::TooLarge(format!(
+                "text exceeds {MAX_GLYPHS} glyphs"
+            )));
+        }
 
-    let mut text_path = TextPath::new();
+        let scale = size.linear_scale(run_font.font.head()?.units_per_em());
+        let scale_unit = |unit: i32| unit as f32 * scale;
 
-    let scale = size.linear_scale(metrics.units_per_em);
-    let scale_unit = |unit: i32| unit as f32 * scale;
+        for (info, position) in
+            iter::zip(glyph_buffer.glyph_infos(), glyph_buffer.glyph_positions())
+        {
+            glyphs.push((
+                font_index,
+                GlyphId::new(info.glyph_id),
+                cursor_x + scale_unit(position.x_offset),
+                // Offsets point up in font units but down in the image.
+                cursor_y - scale_unit(position.y_offset),
+            ));
 
-    let (mut cursor_x, mut cursor_y) = (0.0, metrics.ascent);
+            cursor_x += scale_unit(position.x_advance);
+            cursor_y -= scale_unit(position.y_advance);
+        }
+    }
 
-    for (info, position) in iter::zip(glyph_buffer.glyph_infos(), glyph_buffer.glyph_positions()) {
-        let glyph = outlines
-            .get(GlyphId::new(info.glyph_id))
-            .ok_or_else(|| DrawError::GlyphNotFound(GlyphId::new(info.glyph_id)))?;
+    // TODO: The official implementation seems to use some other methods to calculate
+    // the height of the generated image and the position of the text. Our logic based
+    // on baseline and ascent/descent may cause some fonts to not be vertically centered
+    // properly in the font picker. Needs further investigation.
+    let width = cursor_x;
+    let height = metrics.ascent - metrics.descent;
+    draw_glyphs(pen, &fonts, &glyphs, size)?;
 
-        text_path.origin_x = cursor_x + scale_unit(position.x_offset);
-        text_path.origin_y = cursor_y + scale_unit(position.y_offset);
+    Ok(Some((width, height)))
+}
 
-        glyph.draw(DrawSettings::unhinted(size, &location), &mut text_path)?;
+/// An outline pen that places glyphs at an origin in text coordinates.
+pub trait GlyphPen: OutlinePen {
+    fn set_origin(&mut self, x: f32, y: f32);
 
-        cursor_x += scale_unit(position.x_advance);
-        cursor_y += scale_unit(position.y_advance);
+    /// Draw the glyph in color at the origin, returning whether the font has a
+    /// color representation of it. Its outline is drawn otherwise.
+    fn draw_color_glyph(
+        &mut self,
+        _font: &RunFont,
+        _glyph_id: GlyphId,
+        _size: Size,
+    ) -> Result<bool, RenderError> {
+        Ok(false)
     }
+}
 
-    let width = cursor_x;
-    let height = metrics.ascent - metrics.descent;
-
-    let document = Document::new()
-        .set("width", width)
-        .set("height", height)
-        .set("viewBox", (0.0, 0.0, width, height))
-        .add(element::Path::new().set("d", text_path.data));
+/// A glyph pen that writes what it has drawn as an SVG document.
+pub trait SvgPen: GlyphPen {
+    fn into_document(self, width: f32, height: f32) -> Document;
+}
 
-    Ok(Some(document.to_string()))
+/// Draw positioned glyphs into the pen.
+pub(crate) fn draw_glyphs(
+    pen: &mut impl GlyphPen,
+    fonts: &[RunFont],
+    glyphs: &[(usize, GlyphId, f32, f32)],
+    size: Size,
+) -> Result<(), RenderError> {
+    for (font_index, glyph_id, origin_x, origin_y) in glyphs {
+        let run_font = &fonts[*font_index];
+        pen.set_origin(*origin_x, *origin_y);
+        if pen.draw_color_glyph(run_font, *glyph_id, size)? {
+            continue;
+        }
+        // Glyphs of bitmap-only fonts have no outline to draw.
+        if let Some(glyph) = run_font.outlines.get(*glyph_id) {
+            glyph.draw(DrawSettings::unhinted(size, &run_font.location), &mut *pen)?;
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Default)]
@@ -120,6 +348,27 @@ impl TextPath {
     pub fn new() -> Self {
         TextPath::default()
     }
+
+    pub fn into_data(self) -> element::path::Data {
+        self.data
+    }
+}
+
+impl GlyphPen for TextPath {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin_x = x;
+        self.origin_y = y;
+    }
+}
+
+impl SvgPen for TextPath {
+    fn into_document(self, width: f32, height: f32) -> Document {
+        Document::new()
+            .set("width", width)
+            .set("height", height)
+            .set("viewBox", (0.0, 0.0, width, height))
+            .add(element::Path::new().set("d", self.data))
+    }
 }
 
 // Because the Y-axis in text rendering is opposite to SVG, we need to invert the Y values.
@@ -170,3 +419,390 @@ impl OutlinePen for TextPath {
         self.data.append(Command::Close);
     }
 }
+
+/// Collects the outline of each distinct glyph once, along with where it's placed,
+/// for compact SVG output.
+#[derive(Debug, Clone)]
+pub struct GlyphDefs {
+    precision: u8,
+    origin_x: f32,
+    origin_y: f32,
+    data: CompactData,
+    defs: Vec<String>,
+    ids: HashMap<String, usize>,
+    uses: Vec<(usize, f32, f32)>,
+}
+
+/// Decimals beyond this are noise in f32 coordinates.
+const MAX_SVG_PRECISION: u8 = 6;
+
+impl GlyphDefs {
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.min(MAX_SVG_PRECISION);
+        GlyphDefs {
+            precision,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            data: CompactData::new(precision),
+            defs: Vec::new(),
+            ids: HashMap::new(),
+            uses: Vec::new(),
+        }
+    }
+
+    /// Place the glyph drawn since the last origin, defining it if it's new.
+    fn finish_glyph(&mut self) {
+        let data = std::mem::replace(&mut self.data, CompactData::new(self.precision)).text;
+        if data.is_empty() {
+            return;
+        }
+        let id = match self.ids.get(&data) {
+            Some(id) => *id,
+            None => {
+                self.defs.push(data.clone());
+                self.ids.insert(data, self.defs.len() - 1);
+                self.defs.len() - 1
+            }
+        };
+        self.uses.push((id, self.origin_x, self.origin_y));
+    }
+}
+
+impl SvgPen for GlyphDefs {
+    fn into_document(mut self, width: f32, height: f32) -> Document {
+        self.finish_glyph();
+
+        let number = |value: f32| format_number(value, self.precision);
+        let mut defs = element::Definitions::new();
+        for (id, data) in self.defs.iter().enumerate() {
+            defs = defs.add(
+                element::Path::new()
+                    .set("id", format!("g{id}"))
+                    .set("d", &**data),
+            );
+        }
+        let mut document = Document::new()
+            .set("width", width)
+            .set("height", height)
+            .set("viewBox", (0.0, 0.0, width, height))
+            .add(defs);
+        for (id, x, y) in &self.uses {
+            document = document.add(
+                element::Use::new()
+                    .set("href", format!("#g{id}"))
+                    .set("x", number(*x))
+                    .set("y", number(*y)),
+            );
+        }
+        document
+    }
+}
+
+impl GlyphPen for GlyphDefs {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.finish_glyph();
+        self.origin_x = x;
+        self.origin_y = y;
+    }
+}
+
+// Glyphs are defined relative to their origin, with the Y-axis inverted like in `TextPath`.
+impl OutlinePen for GlyphDefs {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.data.push('m', &[(x, -y)]);
+        self.data.start = self.data.current;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.data.push('l', &[(x, -y)]);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.data.push('q', &[(x1, -y1), (x, -y)]);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.data.push('c', &[(x1, -y1), (x2, -y2), (x, -y)]);
+    }
+
+    fn close(&mut self) {
+        self.data.text.push('z');
+        self.data.command = Some('z');
+        self.data.current = self.data.start;
+    }
+}
+
+/// SVG path data with relative commands and rounded coordinates.
+#[derive(Debug, Clone)]
+struct CompactData {
+    precision: u8,
+    text: String,
+    command: Option<char>,
+    /// The current point and the start of the subpath, as rounded in the output,
+    /// so that rounding errors don't add up along the path.
+    current: (f32, f32),
+    start: (f32, f32),
+}
+
+impl CompactData {
+    fn new(precision: u8) -> Self {
+        CompactData {
+            precision,
+            text: String::new(),
+            command: None,
+            current: (0.0, 0.0),
+            start: (0.0, 0.0),
+        }
+    }
+
+    /// Append a command with points relative to the current point, which moves
+    /// to the last of them.
+    fn push(&mut self, command: char, points: &[(f32, f32)]) {
+        // Repeated commands can be left out, except for moves, which would
+        // turn into lines.
+        let repeated = self.command == Some(command) && command != 'm';
+        if !repeated {
+            self.text.push(command);
+        }
+        self.command = Some(command);
+
+        let (current_x, current_y) = self.current;
+        let mut needs_separator = repeated;
+        for (x, y) in points {
+            let dx = round(x - current_x, self.precision);
+            let dy = round(y - current_y, self.precision);
+            for value in [dx, dy] {
+                let number = format_number(value, self.precision);
+                if needs_separator && !number.starts_with('-') {
+                    self.text.push(' ');
+                }
+                self.text.push_str(&number);
+                needs_separator = true;
+            }
+            self.current = (current_x + dx, current_y + dy);
+        }
+    }
+}
+
+fn round(value: f32, precision: u8) -> f32 {
+    let scale = 10f32.powi(precision as i32);
+    (value * scale).round() / scale
+}
+
+/// Format a number rounded to the precision in its shortest form, such as `.5`
+/// instead of `0.5`.
+fn format_number(value: f32, precision: u8) -> String {
+    let value = round(value, precision);
+    let text = if value == 0.0 {
+        "0".to_string()
+    } else {
+        value.to_string()
+    };
+    if let Some(fraction) = text.strip_prefix("0.") {
+        format!(".{fraction}")
+    } else if let Some(fraction) = text.strip_prefix("-0.") {
+        format!("-.{fraction}")
+    } else {
+        text
+    }
+}
+
+/// Collects glyph outlines into a kurbo path, to measure their exact bounding box
+/// or place them in a larger document.
+#[derive(Debug, Clone, Default)]
+pub struct OutlinePath {
+    origin_x: f32,
+    origin_y: f32,
+    path: BezPath,
+}
+
+impl OutlinePath {
+    pub fn into_path(self) -> BezPath {
+        self.path
+    }
+
+    fn point(&self, x: f32, y: f32) -> Point {
+        Point::new((self.origin_x + x) as f64, (self.origin_y - y) as f64)
+    }
+}
+
+impl GlyphPen for OutlinePath {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin_x = x;
+        self.origin_y = y;
+    }
+}
+
+impl OutlinePen for OutlinePath {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.path.move_to(self.point(x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.path.line_to(self.point(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.path.quad_to(self.point(x1, y1), self.point(x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.path
+            .curve_to(self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+    }
+
+    fn close(&mut self) {
+        self.path.close_path();
+    }
+}
+
+/// Collects glyph outlines into a path in device pixels for rasterization.
+#[derive(Debug, Clone)]
+pub struct RasterPath {
+    origin_x: f32,
+    origin_y: f32,
+    pixel_ratio: f32,
+    builder: PathBuilder,
+}
+
+impl RasterPath {
+    pub fn new(pixel_ratio: f32) -> Self {
+        RasterPath {
+            origin_x: 0.0,
+            origin_y: 0.0,
+            pixel_ratio,
+            builder: PathBuilder::new(),
+        }
+    }
+
+    /// Fill the outlines in black.
+    pub fn draw(self, pixmap: &mut Pixmap) {
+        if let Some(path) = self.builder.finish() {
+            let mut paint = Paint::default();
+            paint.set_color(Color::BLACK);
+            paint.anti_alias = true;
+            pixmap.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (self.origin_x + x) * self.pixel_ratio,
+            (self.origin_y - y) * self.pixel_ratio,
+        )
+    }
+}
+
+impl GlyphPen for RasterPath {
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin_x = x;
+        self.origin_y = y;
+    }
+}
+
+impl OutlinePen for RasterPath {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x, y) = self.point(x, y);
+        self.builder.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x2, y2) = self.point(x2, y2);
+        let (x, y) = self.point(x, y);
+        self.builder.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.builder.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{fixture, render_options};
+
+    /// A pen that records the origin of each glyph.
+    #[derive(Default)]
+    struct GlyphOrigins(Vec<(f32, f32)>);
+
+    impl OutlinePen for GlyphOrigins {
+        fn move_to(&mut self, _x: f32, _y: f32) {}
+        fn line_to(&mut self, _x: f32, _y: f32) {}
+        fn quad_to(&mut self, _cx0: f32, _cy0: f32, _x: f32, _y: f32) {}
+        fn curve_to(&mut self, _cx0: f32, _cy0: f32, _cx1: f32, _cy1: f32, _x: f32, _y: f32) {}
+        fn close(&mut self) {}
+    }
+
+    impl GlyphPen for GlyphOrigins {
+        fn set_origin(&mut self, x: f32, y: f32) {
+            self.0.push((x, y));
+        }
+    }
+
+    #[test]
+    fn test_draw_text_offsets() {
+        // The acute accent is attached above the capital with a positive y offset
+        // in font units, which points up in the image.
+        let mut pen = GlyphOrigins::default();
+        draw_text(
+            "A\u{301}",
+            render_options(&fixture("DejaVuSans.ttf")),
+            &mut pen,
+        )
+        .unwrap()
+        .unwrap();
+        let [(_, base_y), (_, mark_y)] = pen.0[..] else {
+            panic!("unexpected glyphs: {:?}", pen.0);
+        };
+        assert!(mark_y < base_y, "{mark_y} >= {base_y}");
+    }
+
+    #[test]
+    fn test_render_compact() {
+        assert_eq!(format_number(0.5, 2), ".5");
+        assert_eq!(format_number(-0.125, 2), "-.13");
+        assert_eq!(format_number(-0.001, 2), "0");
+        assert_eq!(format_number(12.0, 2), "12");
+
+        let mut glyph_defs = GlyphDefs::new(1);
+        glyph_defs.set_origin(10.0, 20.0);
+        glyph_defs.move_to(0.0, 0.0);
+        glyph_defs.line_to(1.25, 0.0);
+        glyph_defs.line_to(1.25, -1.0);
+        glyph_defs.close();
+        assert_eq!(glyph_defs.data.text, "m0 0l1.3 0 0 1z");
+
+        let content = render_text(
+            "Ubuntu",
+            RenderOptions {
+                svg_precision: Some(2),
+                ..render_options(&fixture("Ubuntu-Light.ttf"))
+            },
+        )
+        .unwrap()
+        .unwrap();
+        let content = String::from_utf8(content).unwrap();
+        // "u" is defined once and placed twice.
+        assert_eq!(content.matches("<path").count(), 5);
+        assert_eq!(content.matches("<use").count(), 6);
+    }
+}
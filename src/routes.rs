@@ -1,47 +1,115 @@
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    collections::HashMap,
+    fs, iter,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, PoisonError},
+    time::{Duration, SystemTime},
+};
 
+use anyhow::Context;
 use axum::{
     Json,
+    body::Bytes,
     extract::{Query, Request},
-    http::{StatusCode, header},
-    response::IntoResponse,
+    http::{HeaderMap, HeaderValue, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
 };
+use harfrust::{Feature, Language, Variation};
+use tokio::{sync::Semaphore, time::Instant};
 use tower::ServiceExt;
 use tower_http::services::ServeFile;
 
 use crate::{
-    CONFIG, FONT_FILES,
+    CONFIG, FONT_FILES, FONT_FILES_PAYLOAD, PREVIEW_CACHE, PREVIEW_PERMITS,
+    collection::cached_face,
+    config::Config,
+    error::ApiError,
     font::{Font, FontFile, FontQuery, FontQueryResult, to_us_weight_class, to_us_width_class},
+    http::{EncodedBody, accepts_media_type, cache_headers, etag_matches, strong_etag},
+    inspector::{GlyphSelector, inspect_glyph, inspect_glyphs},
+    instancer::{InstanceOptions, cached_instance},
+    metrics,
+    paragraph::{ParagraphOptions, TextAlign, WritingMode, render_paragraph},
     payload::{
-        FontFilesEndpointPayload, FontPayload, VariationAxisPayload, VersionEndpointPayload,
+        FontFilesEndpointPayload, FontMetricsPayload, FontPayload, GlyphPayload,
+        GlyphsEndpointPayload, ShapingPayload, VariationAxisPayload, VersionEndpointPayload,
     },
-    renderer::{RenderOptions, render_text},
+    preview::{PreviewKey, render_preview},
+    renderer::{RenderFormat, RenderOptions},
     scan_font_files,
+    shaping::{ShapingOptions, shape_text},
+    specimen::{SpecimenFormat, render_specimen},
+    subsetter::{SubsetOptions, subset_font},
 };
 
-// These values are taken from the official Windows client as of 2025-11-09.
-const PACKAGE: &str = "125.9.10";
-const VERSION: u32 = 23;
-
 #[tracing::instrument]
 pub async fn version() -> impl IntoResponse {
+    let profile = CONFIG.load().profile();
     Json(VersionEndpointPayload {
-        package: PACKAGE.into(),
-        version: VERSION,
+        package: profile.package,
+        version: profile.version,
     })
 }
 
 // There are supposed to be some query parameters here, but we don't really
 // care about them, so we'll just ignore them for now.
-#[tracing::instrument]
-pub async fn font_files() -> impl IntoResponse {
-    if CONFIG.enable_font_rescan {
+#[tracing::instrument(skip(headers))]
+pub async fn font_files(headers: HeaderMap) -> Result<impl IntoResponse, ApiError> {
+    if CONFIG.load().enable_font_rescan {
         scan_font_files().await;
     }
 
-    let font_files = FONT_FILES.read().await;
+    let cached_payload = FONT_FILES_PAYLOAD.read().await.clone();
+    let payload = match cached_payload {
+        Some(payload) => payload,
+        None => {
+            let font_files = FONT_FILES.read().await;
+            let mut cached_payload = FONT_FILES_PAYLOAD.write().await;
+            match &*cached_payload {
+                Some(payload) => payload.clone(),
+                None => {
+                    let payload = font_files_payload(&font_files);
+                    let compress = CONFIG.load().enable_compression;
+                    // Other requests only need the font files while the payload is
+                    // encoded. A rescan waits for the payload lock before it clears
+                    // the payload, so a stale one can't stick around.
+                    drop(font_files);
+                    let payload = Arc::new(
+                        tokio::task::spawn_blocking(move || {
+                            encode_font_files_payload(&payload, compress)
+                        })
+                        .await?
+                        .context("Failed to encode font files payload")?,
+                    );
+                    cached_payload.replace(payload.clone());
+                    payload
+                }
+            }
+        }
+    };
+
+    Ok(payload.respond(&headers))
+}
+
+fn encode_font_files_payload(
+    payload: &FontFilesEndpointPayload,
+    compress: bool,
+) -> Result<EncodedBody, anyhow::Error> {
+    let body = serde_json::to_vec(payload)?;
+    let payload = EncodedBody::new("application/json", body, compress)?;
+    tracing::debug!(
+        "Encoded font files payload: {size} bytes, etag: {etag}",
+        size = payload.identity.len(),
+        etag = payload.etag,
+    );
+    Ok(payload)
+}
 
-    fn map_font(font: &Font, font_file: &FontFile) -> Vec<FontPayload> {
+fn font_files_payload(font_files: &HashMap<PathBuf, FontFile>) -> FontFilesEndpointPayload {
+    let profile = CONFIG.load().profile();
+
+    let map_font = |font: &Font, font_file: &FontFile| -> Vec<FontPayload> {
         let font_payload = FontPayload {
             family: font.family_name.clone().unwrap_or_default(),
             style: font.subfamily_name.clone().unwrap_or_default(),
@@ -49,7 +117,7 @@ pub async fn font_files() -> impl IntoResponse {
             weight: to_us_weight_class(font.weight),
             stretch: to_us_width_class(font.width),
             italic: font.is_italic || font.is_oblique,
-            variation_axes: if font.axes.is_empty() {
+            variation_axes: if font.axes.is_empty() || !profile.variation_axes {
                 None
             } else {
                 Some(
@@ -72,10 +140,9 @@ pub async fn font_files() -> impl IntoResponse {
                 .and_then(|modified_at| modified_at.duration_since(SystemTime::UNIX_EPOCH).ok())
                 .map(|duration| duration.as_secs())
                 .unwrap_or_default(),
-            // Not sure what this value exactly means; the official Windows and macOS
-            // clients have different implementations. Assuming this won't cause any
-            // issues for now.
-            user_installed: true,
+            // The official Windows and macOS clients have different implementations,
+            // so this can be overridden in the config.
+            user_installed: profile.user_installed.resolve(font_file.source),
         };
 
         if font.named_instances.is_empty() {
@@ -88,36 +155,31 @@ pub async fn font_files() -> impl IntoResponse {
                     font_payload.style = named_instance.subfamily_name.clone().unwrap_or_default();
                     font_payload.postscript =
                         named_instance.postscript_name.clone().unwrap_or_default();
+                    // The style of each instance is reported even when the axes aren't.
+                    let mut is_italic = font.is_italic;
+                    let mut is_oblique = font.is_oblique;
+                    for (axis, coordinate) in iter::zip(&font.axes, &named_instance.coordinates) {
+                        match axis.tag.as_str() {
+                            "wght" => font_payload.weight = to_us_weight_class(*coordinate),
+                            "wdth" => font_payload.stretch = to_us_width_class(*coordinate),
+                            "ital" => is_italic = *coordinate != 0.0,
+                            "slnt" => is_oblique = *coordinate != 0.0,
+                            _ => {}
+                        }
+                    }
+                    font_payload.italic = is_italic || is_oblique;
                     if let Some(variation_axes) = &mut font_payload.variation_axes {
-                        let mut is_italic = font.is_italic;
-                        let mut is_oblique = font.is_oblique;
-                        variation_axes
-                            .iter_mut()
-                            .zip(&named_instance.coordinates)
-                            .for_each(|(variation_axis, coordinate)| {
-                                variation_axis.value = *coordinate;
-                                if variation_axis.tag == "wght" {
-                                    font_payload.weight = to_us_weight_class(*coordinate);
-                                }
-                                if variation_axis.tag == "wdth" {
-                                    font_payload.stretch = to_us_width_class(*coordinate);
-                                }
-                                if variation_axis.tag == "ital" {
-                                    is_italic = *coordinate != 0.0;
-                                }
-                                if variation_axis.tag == "slnt" {
-                                    is_oblique = *coordinate != 0.0;
-                                }
-                            });
-                        font_payload.italic = is_italic || is_oblique;
+                        iter::zip(variation_axes, &named_instance.coordinates).for_each(
+                            |(variation_axis, coordinate)| variation_axis.value = *coordinate,
+                        );
                     }
                     font_payload
                 })
                 .collect()
         }
-    }
+    };
 
-    Json(FontFilesEndpointPayload {
+    FontFilesEndpointPayload {
         font_files: font_files
             .iter()
             .map(|(path, font_file)| {
@@ -137,33 +199,294 @@ pub async fn font_files() -> impl IntoResponse {
         modified_at: None,
         modified_fonts: None,
 
-        package: PACKAGE.into(),
-        version: VERSION,
-    })
+        package: profile.package,
+        version: profile.version,
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct FontFileQuery {
     pub file: PathBuf,
+    /// Index of a face in a font collection to serve as a standalone font.
+    pub index: Option<usize>,
+    /// Subfamily or PostScript name of a named instance to serve as a static font.
+    pub instance: Option<String>,
+    /// Axis coordinates such as `wght=600,wdth=80` to serve as a static font. They
+    /// can also be given as parameters named after the axes of the font, such as
+    /// `&wght=600`.
+    #[serde(default)]
+    pub variations: String,
 }
 
 #[tracing::instrument]
 pub async fn font_file(
-    Query(query): Query<FontFileQuery>,
+    Query(mut query): Query<FontFileQuery>,
     request: Request,
-) -> Result<impl IntoResponse, StatusCode> {
-    let font_path = {
+) -> Result<Response, ApiError> {
+    let (font_path, instance) = {
         let font_files = FONT_FILES.read().await;
 
-        let font_file = font_files.get(&query.file).ok_or_else(|| {
-            tracing::error!("Font file not found: {path:?}", path = query.file);
-            StatusCode::NOT_FOUND
-        })?;
+        let font_file = font_files
+            .get(&query.file)
+            .ok_or_else(|| ApiError::FontNotFound(query.file.clone()))?;
+
+        if let Some(index) = query.index
+            && !font_file.fonts.iter().any(|font| font.index == index)
+        {
+            return Err(ApiError::FaceNotFound(format!(
+                "{path:?} ({index})",
+                path = query.file
+            )));
+        }
+
+        // Coordinates in `variations` come last, so they win over the parameters.
+        let axis_parameters = axis_parameters(request.uri(), font_file, query.index);
+        if !axis_parameters.is_empty() {
+            query.variations = format!(
+                "{axis_parameters},{variations}",
+                variations = query.variations
+            );
+        }
+
+        let instance = if query.instance.is_some() || !query.variations.trim().is_empty() {
+            Some(font_instance(font_file, &query)?)
+        } else {
+            None
+        };
+
+        (font_file.path.clone(), instance)
+    };
+
+    let font_path = match instance {
+        Some(instance) => {
+            tokio::task::spawn_blocking(move || {
+                cached_instance(InstanceOptions {
+                    font: (&font_path, instance.index),
+                    coordinates: &instance.coordinates,
+                    subfamily_name: instance.subfamily_name.as_deref(),
+                    postscript_name: instance.postscript_name.as_deref(),
+                })
+            })
+            .await??
+        }
+        None => match query.index {
+            Some(index) if is_font_collection(&font_path) => {
+                tokio::task::spawn_blocking(move || cached_face((&font_path, index))).await??
+            }
+            _ => font_path,
+        },
+    };
+
+    // Derived fonts are cached under a name that changes with the source file, so
+    // the identity of the served file is enough to identify its content.
+    let etag = fs::metadata(&font_path)
+        .map(|metadata| strong_etag((&font_path, metadata.modified().ok(), metadata.len())))
+        .map_err(|_| ApiError::FontNotFound(font_path.clone()))?;
+    let cache_headers = cache_headers(&etag, &CONFIG.load().cache_control.font_file);
+
+    if etag_matches(request.headers(), &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    Ok((
+        cache_headers,
+        ServeFile::new(font_path).oneshot(request).await,
+    )
+        .into_response())
+}
+
+/// Axis coordinates given as parameters of their own, such as `wght=600`, in the
+/// form of the `variations` parameter. Only the tags of axes of the font, or of
+/// the face at the index, are taken; other parameters are left alone.
+fn axis_parameters(uri: &Uri, font_file: &FontFile, index: Option<usize>) -> String {
+    let Ok(Query(parameters)) = Query::<Vec<(String, String)>>::try_from_uri(uri) else {
+        return String::new();
+    };
+    let is_axis_tag = |name: &str| {
+        font_file
+            .fonts
+            .iter()
+            .filter(|font| index.is_none_or(|index| font.index == index))
+            .any(|font| font.axes.iter().any(|axis| axis.tag == name))
+    };
+
+    parameters
+        .iter()
+        .filter(|(name, _)| is_axis_tag(name))
+        .map(|(tag, value)| format!("{tag}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn is_font_collection(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("ttc") || extension.eq_ignore_ascii_case("otc")
+    })
+}
+
+#[derive(Debug, Clone)]
+struct FontInstance {
+    index: usize,
+    coordinates: Vec<(String, f32)>,
+    subfamily_name: Option<String>,
+    postscript_name: Option<String>,
+}
+
+/// Resolve the static instance requested by the query.
+fn font_instance(font_file: &FontFile, query: &FontFileQuery) -> Result<FontInstance, ApiError> {
+    let (font, named_instance) = match &query.instance {
+        Some(instance) => font_file
+            .fonts
+            .iter()
+            .filter(|font| query.index.is_none_or(|index| font.index == index))
+            .find_map(|font| {
+                font.named_instances
+                    .iter()
+                    .find(|named_instance| {
+                        named_instance.subfamily_name.as_ref() == Some(instance)
+                            || named_instance.postscript_name.as_ref() == Some(instance)
+                    })
+                    .map(|named_instance| (font, Some(named_instance)))
+            })
+            .ok_or_else(|| ApiError::InstanceNotFound(instance.clone()))?,
+        None => font_file
+            .fonts
+            .iter()
+            .filter(|font| query.index.is_none_or(|index| font.index == index))
+            .find(|font| !font.axes.is_empty())
+            .map(|font| (font, None))
+            .ok_or(ApiError::NotVariable)?,
+    };
+
+    let TextSettings { variations, .. } = TextSettings::parse(&query.variations, "", "")?;
+    let mut coordinates = named_instance
+        .map(|named_instance| {
+            iter::zip(&font.axes, &named_instance.coordinates)
+                .map(|(axis, coordinate)| (axis.tag.clone(), *coordinate))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for variation in &variations {
+        let tag = variation.tag.to_string();
+        coordinates.retain(|(axis_tag, _)| *axis_tag != tag);
+        coordinates.push((tag, variation.value));
+    }
+
+    // Names of the named instance no longer apply once any coordinate is overridden.
+    let named_instance = named_instance.filter(|_| variations.is_empty());
+
+    Ok(FontInstance {
+        index: font.index,
+        coordinates,
+        subfamily_name: named_instance
+            .and_then(|named_instance| named_instance.subfamily_name.clone()),
+        postscript_name: named_instance
+            .and_then(|named_instance| named_instance.postscript_name.clone()),
+    })
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontSubsetQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    /// Characters to keep, at most `MAX_TEXT_LENGTH` of them. Larger sets go in
+    /// `unicodes`.
+    #[serde(default)]
+    pub text: String,
+    /// Comma-separated hexadecimal codepoints or ranges to keep, such as `41-5A,20AC`.
+    #[serde(default)]
+    pub unicodes: String,
+    /// Comma-separated glyph IDs or ranges to keep, such as `1-10,42`.
+    #[serde(default)]
+    pub glyph_ids: String,
+}
+
+#[tracing::instrument]
+pub async fn font_subset(
+    Query(query): Query<FontSubsetQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_text_length(&query.text)?;
+    let codepoints = query
+        .text
+        .chars()
+        .map(u32::from)
+        .chain(parse_ranges(&query.unicodes, 16, MAX_CODEPOINT)?)
+        .collect::<Vec<_>>();
+    let glyph_ids = parse_ranges(&query.glyph_ids, 10, MAX_GLYPH_ID)?;
+
+    let (font_path, font_index) = {
+        let font_files = FONT_FILES.read().await;
+
+        let font_file = font_files
+            .get(&query.file)
+            .ok_or_else(|| ApiError::FontNotFound(query.file.clone()))?;
+
+        let FontQueryResult { font, .. } =
+            query_font(font_file, &query.family, &query.style, &query.postscript)?;
+
+        (font_file.path.clone(), font.index)
+    };
+
+    let (content, extension) = render_with_limits(move || {
+        Ok(subset_font(SubsetOptions {
+            font: (&font_path, font_index),
+            codepoints: &codepoints,
+            glyph_ids: &glyph_ids,
+        })?)
+    })
+    .await?;
+
+    let content_type = if extension == "otf" {
+        "font/otf"
+    } else {
+        "font/ttf"
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], content))
+}
+
+/// Upper bound of the values of all ranges together, enough for every Unicode
+/// codepoint or glyph ID.
+const MAX_RANGE_VALUES: usize = 0x110000;
+const MAX_CODEPOINT: u32 = 0x10FFFF;
+const MAX_GLYPH_ID: u32 = 0xFFFF;
 
-        font_file.path.clone()
+/// Parse a comma-separated list of numbers up to `max` and inclusive ranges of
+/// them, such as `1-10,42`.
+fn parse_ranges(text: &str, radix: u32, max: u32) -> Result<Vec<u32>, ApiError> {
+    let parse = |value: &str| {
+        u32::from_str_radix(value.trim(), radix)
+            .map_err(|error| ApiError::InvalidQuery(format!("invalid number {value:?}: {error}")))
     };
 
-    Ok(ServeFile::new(font_path).oneshot(request).await)
+    let mut values = Vec::new();
+    for item in text.split(',').filter(|item| !item.trim().is_empty()) {
+        let (start, end) = match item.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(item)?, parse(item)?),
+        };
+        if start > end {
+            return Err(ApiError::InvalidQuery(format!(
+                "range {item:?} ends before it starts"
+            )));
+        }
+        if end > max {
+            return Err(ApiError::InvalidQuery(format!(
+                "range {item:?} exceeds the maximum of {max:#X}"
+            )));
+        }
+        if values.len() + (end.saturating_sub(start) as usize) >= MAX_RANGE_VALUES {
+            return Err(ApiError::InvalidQuery(format!(
+                "ranges must cover at most {MAX_RANGE_VALUES} values"
+            )));
+        }
+        values.extend(start..=end);
+    }
+    Ok(values)
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -173,61 +496,1013 @@ pub struct FontPreviewQuery {
     pub style: String,
     pub postscript: String,
     pub font_size: f32,
+    /// Output format, negotiated from the `Accept` header when not given.
+    pub format: Option<RenderFormat>,
+    /// Device pixel ratio of PNG output.
+    pub dpr: Option<f32>,
 }
 
-#[tracing::instrument]
+/// Upper bound of the device pixel ratio, to keep PNG output reasonably small.
+const MAX_PIXEL_RATIO: f32 = 4.0;
+/// Upper bound of the font size in pt.
+const MAX_FONT_SIZE: f32 = 1000.0;
+/// Upper bound of the characters of rendered text.
+const MAX_TEXT_LENGTH: usize = 1000;
+/// How long a preview may wait for a permit and render before giving up.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tracing::instrument(skip(headers))]
 pub async fn font_preview(
     Query(query): Query<FontPreviewQuery>,
-) -> Result<impl IntoResponse, StatusCode> {
-    if !CONFIG.enable_font_preview {
-        return Err(StatusCode::NOT_FOUND);
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    // One snapshot for the whole request, so that a reload can't change the
+    // config between building the key and responding.
+    let config = CONFIG.load_full();
+    if !config.enable_font_preview {
+        return Err(ApiError::PreviewDisabled);
     }
 
+    let format = preview_format(query.format, &headers);
+    let font_size = preview_font_size(query.font_size)?;
+    let pixel_ratio = preview_pixel_ratio(query.dpr)?;
+
+    let preview_key = {
+        let font_files = FONT_FILES.read().await;
+        let font_file = font_files
+            .get(&query.file)
+            .ok_or_else(|| ApiError::FontNotFound(query.file.clone()))?;
+        let FontQueryResult {
+            font,
+            named_instance,
+        } = query_font(font_file, &query.family, &query.style, &query.postscript)?;
+        let (fallback_font, fallback_font_modified_at) = fallback_font(&config);
+
+        PreviewKey {
+            path: font_file.path.clone(),
+            modified_at: font_file.modified_at,
+            index: font.index,
+            named_instance_index: named_instance.map(|named_instance| named_instance.index),
+            text: query.family.clone(),
+            size: font_size.to_bits(),
+            format,
+            pixel_ratio: pixel_ratio.to_bits(),
+            variations: String::new(),
+            features: String::new(),
+            language: String::new(),
+            svg_precision: config.svg_precision,
+            fallbacks: config.preview_fallbacks.clone(),
+            fallback_font,
+            fallback_font_modified_at,
+        }
+    };
+
+    preview_response(&config, preview_key, query.format.is_none(), &headers).await
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontRenderQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    /// Text to render, the family name if empty.
+    #[serde(default)]
+    pub text: String,
+    pub font_size: f32,
+    /// Comma-separated axis coordinates, such as `wght=437,wdth=80`.
+    #[serde(default)]
+    pub variations: String,
+    /// Comma-separated OpenType feature settings, such as `ss01,tnum,-liga`.
+    #[serde(default)]
+    pub features: String,
+    /// BCP 47 language tag of the text, such as `tr`.
+    #[serde(default)]
+    pub language: String,
+    pub format: Option<RenderFormat>,
+    pub dpr: Option<f32>,
+    /// Decimals of compact SVG output, overriding `svg_precision` of the config.
+    pub precision: Option<u8>,
+}
+
+/// An extended preview that renders any text with explicit variation coordinates,
+/// OpenType features and language, without falling back to other samples.
+#[tracing::instrument(skip(headers))]
+pub async fn font_render(
+    Query(query): Query<FontRenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let config = CONFIG.load_full();
+    let preview_key = font_render_key(&config, &query, &headers).await?;
+    preview_response(&config, preview_key, query.format.is_none(), &headers).await
+}
+
+/// Check the query against the limits and build the key of the rendered text.
+async fn font_render_key(
+    config: &Config,
+    query: &FontRenderQuery,
+    headers: &HeaderMap,
+) -> Result<PreviewKey, ApiError> {
+    if !config.enable_font_preview {
+        return Err(ApiError::PreviewDisabled);
+    }
+
+    let format = preview_format(query.format, headers);
+    let font_size = preview_font_size(query.font_size)?;
+    let pixel_ratio = preview_pixel_ratio(query.dpr)?;
+    // Reject invalid settings before they end up in the cache key.
+    TextSettings::parse(&query.variations, &query.features, &query.language)?;
+    check_text_length(&query.text)?;
+
     let font_files = FONT_FILES.read().await;
+    let font_file = font_files
+        .get(&query.file)
+        .ok_or_else(|| ApiError::FontNotFound(query.file.clone()))?;
+    let FontQueryResult {
+        font,
+        named_instance,
+    } = query_font(font_file, &query.family, &query.style, &query.postscript)?;
+    let (fallback_font, fallback_font_modified_at) = fallback_font(config);
+
+    Ok(PreviewKey {
+        path: font_file.path.clone(),
+        modified_at: font_file.modified_at,
+        index: font.index,
+        named_instance_index: named_instance.map(|named_instance| named_instance.index),
+        text: if query.text.is_empty() {
+            font.family_name.clone().unwrap_or_default()
+        } else {
+            query.text.clone()
+        },
+        size: font_size.to_bits(),
+        format,
+        pixel_ratio: pixel_ratio.to_bits(),
+        variations: query.variations.clone(),
+        features: query.features.clone(),
+        language: query.language.clone(),
+        svg_precision: query.precision.or(config.svg_precision),
+        fallbacks: Vec::new(),
+        fallback_font,
+        fallback_font_modified_at,
+    })
+}
 
-    let font_file = font_files.get(&query.file).ok_or_else(|| {
-        tracing::error!("Font file not found: {path:?}", path = query.file);
-        StatusCode::NOT_FOUND
-    })?;
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontParagraphQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    /// Text to lay out, where each newline starts a new paragraph.
+    pub text: String,
+    pub font_size: f32,
+    /// Comma-separated axis coordinates, such as `wght=437,wdth=80`.
+    #[serde(default)]
+    pub variations: String,
+    /// Comma-separated OpenType feature settings, such as `ss01,tnum,-liga`.
+    #[serde(default)]
+    pub features: String,
+    /// BCP 47 language tag of the text, such as `ar`.
+    #[serde(default)]
+    pub language: String,
+    /// Maximum length of a line in px.
+    pub max_width: Option<f32>,
+    /// Distance between lines as a multiple of the font size.
+    pub line_height: Option<f32>,
+    #[serde(default)]
+    pub align: TextAlign,
+    #[serde(default)]
+    pub writing_mode: WritingMode,
+    /// Decimals of compact SVG output, overriding `svg_precision` of the config.
+    pub precision: Option<u8>,
+}
+
+/// Lay out text as wrapped, aligned and possibly bidirectional or vertical
+/// paragraphs, and render them to SVG.
+#[tracing::instrument]
+pub async fn font_paragraph(
+    Query(query): Query<FontParagraphQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let config = CONFIG.load_full();
+    if !config.enable_font_preview {
+        return Err(ApiError::PreviewDisabled);
+    }
+    let svg_precision = query.precision.or(config.svg_precision);
+
+    let font_size = preview_font_size(query.font_size)?;
+    let TextSettings {
+        variations,
+        features,
+        language,
+    } = TextSettings::parse(&query.variations, &query.features, &query.language)?;
+    check_text_length(&query.text)?;
+    for (name, value) in [
+        ("max_width", query.max_width),
+        ("line_height", query.line_height),
+    ] {
+        if value.is_some_and(|value| !(value > 0.0 && value.is_finite())) {
+            return Err(ApiError::InvalidQuery(format!(
+                "{name} must be a positive number: {value:?}"
+            )));
+        }
+    }
+
+    let (font_path, font_index, named_instance_index) =
+        glyph_font(&query.file, &query.family, &query.style, &query.postscript).await?;
+
+    let content = render_with_limits(move || {
+        Ok(render_paragraph(
+            &query.text,
+            ParagraphOptions {
+                font: (&font_path, font_index),
+                // In pt like the previews.
+                size: font_size / 72.0 * 96.0,
+                named_instance_index,
+                variations: &variations,
+                features: &features,
+                language: language.as_ref(),
+                max_width: query.max_width,
+                line_height: query.line_height,
+                align: query.align,
+                writing_mode: query.writing_mode,
+                svg_precision,
+            },
+        )?)
+    })
+    .await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, RenderFormat::Svg.content_type())],
+        content,
+    ))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontShapeQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    #[serde(default)]
+    pub text: String,
+    /// Point size to shape at, for fonts with size-specific tracking.
+    pub font_size: Option<f32>,
+    /// Comma-separated axis coordinates, such as `wght=437,wdth=80`.
+    #[serde(default)]
+    pub variations: String,
+    /// Comma-separated OpenType feature settings, such as `ss01,tnum,-liga`.
+    #[serde(default)]
+    pub features: String,
+    /// BCP 47 language tag of the text, such as `tr`.
+    #[serde(default)]
+    pub language: String,
+}
+
+/// Shape the text with the face and return the positioned glyphs, without
+/// rendering anything.
+#[tracing::instrument]
+pub async fn font_shape(
+    Query(query): Query<FontShapeQuery>,
+) -> Result<Json<ShapingPayload>, ApiError> {
+    let TextSettings {
+        variations,
+        features,
+        language,
+    } = TextSettings::parse(&query.variations, &query.features, &query.language)?;
+    let font_size = query.font_size.map(preview_font_size).transpose()?;
+    check_text_length(&query.text)?;
+
+    let (font_path, font_index, named_instance_index) =
+        glyph_font(&query.file, &query.family, &query.style, &query.postscript).await?;
+
+    let shaping = render_with_limits(move || {
+        Ok(shape_text(
+            &query.text,
+            ShapingOptions {
+                font: (&font_path, font_index),
+                size: font_size,
+                named_instance_index,
+                variations: &variations,
+                features: &features,
+                language: language.as_ref(),
+            },
+        )?)
+    })
+    .await?;
+
+    Ok(Json(shaping))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontMetricsQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    /// Comma-separated axis coordinates, such as `wght=437,wdth=80`.
+    #[serde(default)]
+    pub variations: String,
+}
 
+#[tracing::instrument]
+pub async fn font_metrics(
+    Query(query): Query<FontMetricsQuery>,
+) -> Result<Json<FontMetricsPayload>, ApiError> {
+    let TextSettings { variations, .. } = TextSettings::parse(&query.variations, "", "")?;
+
+    let (font_path, font_index, named_instance_index) =
+        glyph_font(&query.file, &query.family, &query.style, &query.postscript).await?;
+
+    let metrics = render_with_limits(move || {
+        Ok(metrics::font_metrics(
+            (&font_path, font_index),
+            named_instance_index,
+            &variations,
+        )?)
+    })
+    .await?;
+
+    Ok(Json(metrics))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontSpecimenQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    #[serde(default)]
+    pub format: SpecimenFormat,
+}
+
+#[tracing::instrument]
+pub async fn font_specimen(
+    Query(query): Query<FontSpecimenQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !CONFIG.load().enable_font_preview {
+        return Err(ApiError::PreviewDisabled);
+    }
+
+    let (font_path, font) = {
+        let font_files = FONT_FILES.read().await;
+
+        let font_file = font_files
+            .get(&query.file)
+            .ok_or_else(|| ApiError::FontNotFound(query.file.clone()))?;
+
+        let FontQueryResult { font, .. } =
+            query_font(font_file, &query.family, &query.style, &query.postscript)?;
+
+        (font_file.path.clone(), font.clone())
+    };
+
+    let format = query.format;
+    let content =
+        render_with_limits(move || Ok(render_specimen(&font_path, &font, format)?)).await?;
+
+    Ok(([(header::CONTENT_TYPE, format.content_type())], content))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontGlyphQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    pub glyph_id: Option<u32>,
+    /// Glyph name from the `post` or `CFF` table, such as `a.ss01`.
+    #[serde(default)]
+    pub name: String,
+    /// Hexadecimal codepoint mapped to the glyph, such as `61`.
+    #[serde(default)]
+    pub unicode: String,
+}
+
+#[tracing::instrument]
+pub async fn font_glyph(
+    Query(query): Query<FontGlyphQuery>,
+) -> Result<Json<GlyphPayload>, ApiError> {
+    let selector = match (query.glyph_id, &*query.name, &*query.unicode) {
+        (Some(glyph_id), "", "") => GlyphSelector::Id(glyph_id),
+        (None, name, "") if !name.is_empty() => GlyphSelector::Name(name.to_string()),
+        (None, "", unicode) if !unicode.is_empty() => {
+            GlyphSelector::Codepoint(u32::from_str_radix(unicode, 16).map_err(|error| {
+                ApiError::InvalidQuery(format!("invalid number {unicode:?}: {error}"))
+            })?)
+        }
+        _ => {
+            return Err(ApiError::InvalidQuery(
+                "exactly one of glyph_id, name and unicode is required".into(),
+            ));
+        }
+    };
+
+    let (font_path, font_index, named_instance_index) =
+        glyph_font(&query.file, &query.family, &query.style, &query.postscript).await?;
+
+    let glyph = render_with_limits({
+        let selector = selector.clone();
+        move || {
+            Ok(inspect_glyph(
+                (&font_path, font_index),
+                named_instance_index,
+                &selector,
+            )?)
+        }
+    })
+    .await?;
+
+    glyph
+        .map(Json)
+        .ok_or_else(|| ApiError::GlyphNotFound(selector.to_string()))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FontGlyphsQuery {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub style: String,
+    #[serde(default)]
+    pub postscript: String,
+    /// Glyph ID of the first glyph of the page.
+    #[serde(default)]
+    pub offset: u32,
+    pub limit: Option<u32>,
+}
+
+const DEFAULT_GLYPH_LIMIT: u32 = 100;
+/// Upper bound of the page size, since every glyph carries its outline.
+const MAX_GLYPH_LIMIT: u32 = 1000;
+
+#[tracing::instrument]
+pub async fn font_glyphs(
+    Query(query): Query<FontGlyphsQuery>,
+) -> Result<Json<GlyphsEndpointPayload>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_GLYPH_LIMIT);
+    if limit > MAX_GLYPH_LIMIT {
+        return Err(ApiError::InvalidQuery(format!(
+            "limit must be at most {MAX_GLYPH_LIMIT}: {limit}"
+        )));
+    }
+
+    let (font_path, font_index, named_instance_index) =
+        glyph_font(&query.file, &query.family, &query.style, &query.postscript).await?;
+
+    let offset = query.offset;
+    let (glyph_count, glyphs) = render_with_limits(move || {
+        Ok(inspect_glyphs(
+            (&font_path, font_index),
+            named_instance_index,
+            offset,
+            limit,
+        )?)
+    })
+    .await?;
+
+    Ok(Json(GlyphsEndpointPayload {
+        glyph_count,
+        offset,
+        limit,
+        glyphs,
+    }))
+}
+
+/// Path, index and named instance index of the face the glyphs are read from.
+async fn glyph_font(
+    file: &Path,
+    family_name: &str,
+    subfamily_name: &str,
+    postscript_name: &str,
+) -> Result<(PathBuf, usize, Option<usize>), ApiError> {
+    let font_files = FONT_FILES.read().await;
+    let font_file = font_files
+        .get(file)
+        .ok_or_else(|| ApiError::FontNotFound(file.into()))?;
     let FontQueryResult {
         font,
         named_instance,
-    } = font_file
+    } = query_font(font_file, family_name, subfamily_name, postscript_name)?;
+
+    Ok((
+        font_file.path.clone(),
+        font.index,
+        named_instance.map(|named_instance| named_instance.index),
+    ))
+}
+
+/// Find the face of a font file by its names, ignoring empty ones.
+fn query_font<'a>(
+    font_file: &'a FontFile,
+    family_name: &str,
+    subfamily_name: &str,
+    postscript_name: &str,
+) -> Result<FontQueryResult<'a>, ApiError> {
+    font_file
         .query(FontQuery {
-            family_name: Some(query.family.as_str()).filter(|family| !family.is_empty()),
-            subfamily_name: Some(query.style.as_str()).filter(|style| !style.is_empty()),
-            postscript_name: Some(query.postscript.as_str()).filter(|postscript| !postscript.is_empty()),
+            family_name: Some(family_name).filter(|family| !family.is_empty()),
+            subfamily_name: Some(subfamily_name).filter(|style| !style.is_empty()),
+            postscript_name: Some(postscript_name).filter(|postscript| !postscript.is_empty()),
         })
         .ok_or_else(|| {
-            tracing::error!(
-                "Font not found: {family_name:?}, subfamily: {subfamily_name:?}, postscript: {postscript_name:?}",
-                family_name = query.family,
-                subfamily_name = query.style,
-                postscript_name = query.postscript,
-            );
-            StatusCode::NOT_FOUND
-        })?;
+            ApiError::FaceNotFound(format!(
+                "family: {family_name:?}, subfamily: {subfamily_name:?}, postscript: {postscript_name:?}",
+            ))
+        })
+}
+
+/// The fallback font of the config and when it was modified, so that previews
+/// taking characters from it are rendered again after it changes.
+fn fallback_font(config: &Config) -> (Option<PathBuf>, Option<SystemTime>) {
+    let path = config.fallback_font();
+    let modified_at = path
+        .as_ref()
+        .and_then(|path| fs::metadata(path).ok()?.modified().ok());
+    (path, modified_at)
+}
+
+/// Pick the requested format, or negotiate it from the `Accept` header.
+fn preview_format(format: Option<RenderFormat>, headers: &HeaderMap) -> RenderFormat {
+    // SVG stays the default since that's what the official client expects.
+    format.unwrap_or_else(|| {
+        if accepts_media_type(headers, RenderFormat::Png.content_type())
+            && !accepts_media_type(headers, RenderFormat::Svg.content_type())
+        {
+            RenderFormat::Png
+        } else {
+            RenderFormat::Svg
+        }
+    })
+}
+
+fn preview_font_size(font_size: f32) -> Result<f32, ApiError> {
+    if font_size > 0.0 && font_size <= MAX_FONT_SIZE {
+        Ok(font_size)
+    } else {
+        Err(ApiError::InvalidQuery(format!(
+            "font_size must be in (0, {MAX_FONT_SIZE}]: {font_size}"
+        )))
+    }
+}
+
+fn check_text_length(text: &str) -> Result<(), ApiError> {
+    if text.chars().count() > MAX_TEXT_LENGTH {
+        return Err(ApiError::InvalidQuery(format!(
+            "text must be at most {MAX_TEXT_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
 
-    let content = render_text(
-        &query.family,
+fn preview_pixel_ratio(dpr: Option<f32>) -> Result<f32, ApiError> {
+    let pixel_ratio = dpr.unwrap_or(1.0);
+    if pixel_ratio > 0.0 && pixel_ratio <= MAX_PIXEL_RATIO {
+        Ok(pixel_ratio)
+    } else {
+        Err(ApiError::InvalidQuery(format!(
+            "dpr must be in (0, {MAX_PIXEL_RATIO}]: {pixel_ratio}"
+        )))
+    }
+}
+
+/// Shaping settings parsed from their query strings.
+#[derive(Debug, Clone, Default)]
+struct TextSettings {
+    variations: Vec<Variation>,
+    features: Vec<Feature>,
+    language: Option<Language>,
+}
+
+impl TextSettings {
+    fn parse(variations: &str, features: &str, language: &str) -> Result<Self, ApiError> {
+        fn parse_list<T: FromStr>(text: &str, name: &str) -> Result<Vec<T>, ApiError> {
+            text.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse()
+                        .map_err(|_| ApiError::InvalidQuery(format!("invalid {name}: {item:?}")))
+                })
+                .collect()
+        }
+
+        Ok(TextSettings {
+            variations: parse_list(variations, "variation")?,
+            features: parse_list(features, "feature")?,
+            language: Some(language.trim())
+                .filter(|language| !language.is_empty())
+                .map(|language| {
+                    language.parse().map_err(|_| {
+                        ApiError::InvalidQuery(format!("invalid language: {language:?}"))
+                    })
+                })
+                .transpose()?,
+        })
+    }
+}
+
+/// Respond with the cached or freshly rendered preview.
+async fn preview_response(
+    config: &Config,
+    preview_key: PreviewKey,
+    negotiated: bool,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let etag = strong_etag(&preview_key);
+    let mut cache_headers = cache_headers(&etag, &config.cache_control.font_preview);
+    if negotiated {
+        cache_headers.insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+
+    if etag_matches(headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let cached_content = PREVIEW_CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&preview_key);
+    let content = match cached_content {
+        Some(content) => content,
+        None => {
+            let content = render_font_preview_with_limits(preview_key.clone())
+                .await?
+                .map(Bytes::from);
+            PREVIEW_CACHE
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(preview_key.clone(), content.clone());
+            content
+        }
+    };
+
+    if let Some(content) = content {
+        Ok((
+            cache_headers,
+            [(header::CONTENT_TYPE, preview_key.format.content_type())],
+            content,
+        )
+            .into_response())
+    } else {
+        Err(ApiError::GlyphsMissing)
+    }
+}
+
+/// Render the preview on a blocking thread, within the limits of `render_with_limits`.
+async fn render_font_preview_with_limits(
+    preview_key: PreviewKey,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    render_with_limits(move || render_font_preview(&preview_key)).await
+}
+
+/// Render on a blocking thread with one of `PREVIEW_PERMITS`, waiting at most
+/// `PREVIEW_TIMEOUT` for the permit and the render. Everything that reads glyphs
+/// for a request goes through here, not only the previews.
+async fn render_with_limits<T: Send + 'static>(
+    render: impl FnOnce() -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    render_within(&PREVIEW_PERMITS, PREVIEW_TIMEOUT, render).await
+}
+
+/// Render on a blocking thread with one of the permits, waiting at most the
+/// timeout for the permit and the render. A render that times out keeps its
+/// permit until it finishes, so runaway renders still count against the limit.
+async fn render_within<T: Send + 'static>(
+    permits: &'static Semaphore,
+    timeout: Duration,
+    render: impl FnOnce() -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    let deadline = Instant::now() + timeout;
+    let permit = tokio::time::timeout_at(deadline, permits.acquire())
+        .await
+        .map_err(|_| ApiError::PreviewBusy)?
+        .map_err(anyhow::Error::from)?;
+
+    let render = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        render()
+    });
+    tokio::time::timeout_at(deadline, render)
+        .await
+        .map_err(|_| ApiError::PreviewTimeout(timeout))??
+}
+
+fn render_font_preview(preview_key: &PreviewKey) -> Result<Option<Vec<u8>>, ApiError> {
+    let TextSettings {
+        variations,
+        features,
+        language,
+    } = TextSettings::parse(
+        &preview_key.variations,
+        &preview_key.features,
+        &preview_key.language,
+    )?;
+
+    Ok(render_preview(
+        &preview_key.text,
         RenderOptions {
-            font: (&font_file.path, font.index),
+            font: (&preview_key.path, preview_key.index),
             // The font_size from the query appears to be in pt rather than px,
             // based on the official implementation. However, the rendering result
             // still differs from the official one. Further investigation may be needed.
-            size: query.font_size / 72.0 * 96.0,
-            named_instance_index: named_instance.map(|named_instance| named_instance.index),
+            size: f32::from_bits(preview_key.size) / 72.0 * 96.0,
+            named_instance_index: preview_key.named_instance_index,
+            variations: &variations,
+            features: &features,
+            language: language.as_ref(),
+            format: preview_key.format,
+            pixel_ratio: f32::from_bits(preview_key.pixel_ratio),
+            svg_precision: preview_key.svg_precision,
+            fallback_font: preview_key.fallback_font.as_deref().map(|path| (path, 0)),
         },
-    )
-    .map_err(|error| {
-        tracing::error!("Failed to render font preview, error: {error:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+        &preview_key.fallbacks,
+    )?)
+}
 
-    if let Some(content) = content {
-        Ok(([(header::CONTENT_TYPE, "image/svg+xml")], content))
-    } else {
-        Err(StatusCode::NOT_FOUND)
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(
+            parse_ranges("41-43, 61,", 16, MAX_CODEPOINT).unwrap(),
+            [0x41, 0x42, 0x43, 0x61]
+        );
+        assert!(parse_ranges("", 10, MAX_GLYPH_ID).unwrap().is_empty());
+        assert!(parse_ranges("x", 10, MAX_GLYPH_ID).is_err());
+        assert_eq!(
+            parse_ranges("100-41", 16, MAX_CODEPOINT)
+                .unwrap_err()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert!(parse_ranges("110000", 16, MAX_CODEPOINT).is_err());
+        assert!(parse_ranges("10FFFF-110000", 16, MAX_CODEPOINT).is_err());
+        assert!(parse_ranges("65536", 10, MAX_GLYPH_ID).is_err());
+        assert_eq!(parse_ranges("65535", 10, MAX_GLYPH_ID).unwrap(), [65535]);
+
+        // The cap applies to all ranges together, not to each of them.
+        assert_eq!(
+            parse_ranges("0-10FFFF", 16, MAX_CODEPOINT).unwrap().len(),
+            0x110000
+        );
+        assert!(parse_ranges("0-10FFFF,0", 16, MAX_CODEPOINT).is_err());
+        assert!(parse_ranges(&["0-FFFFF"; 2].join(","), 16, MAX_CODEPOINT).is_err());
+        assert!(parse_ranges("0-FFFFFFFF", 16, MAX_CODEPOINT).is_err());
+    }
+
+    #[test]
+    fn test_font_instance() {
+        let font_file = FontFile::from_path(fixture("NotoSansHebrew-VF.ttf")).unwrap();
+        let query = |instance: Option<&str>, variations: &str| FontFileQuery {
+            file: fixture("NotoSansHebrew-VF.ttf"),
+            index: None,
+            instance: instance.map(String::from),
+            variations: variations.into(),
+        };
+
+        let instance = font_instance(&font_file, &query(Some("SemiBold"), "")).unwrap();
+        assert_eq!(
+            instance.coordinates,
+            [("wght".into(), 600.0), ("wdth".into(), 100.0)]
+        );
+        assert_eq!(instance.subfamily_name.as_deref(), Some("SemiBold"));
+        assert_eq!(
+            instance.postscript_name.as_deref(),
+            Some("NotoSansHebrew-SemiBold")
+        );
+
+        // Overriding a coordinate of a named instance drops its names.
+        let instance =
+            font_instance(&font_file, &query(Some("NotoSansHebrew-Bold"), "wdth=80")).unwrap();
+        assert_eq!(
+            instance.coordinates,
+            [("wght".into(), 700.0), ("wdth".into(), 80.0)]
+        );
+        assert_eq!(instance.subfamily_name, None);
+        assert_eq!(instance.postscript_name, None);
+
+        let instance = font_instance(&font_file, &query(None, "wght=650")).unwrap();
+        assert_eq!(instance.coordinates, [("wght".into(), 650.0)]);
+
+        assert!(matches!(
+            font_instance(&font_file, &query(Some("Oblique"), "")),
+            Err(ApiError::InstanceNotFound(_))
+        ));
+        assert!(matches!(
+            font_instance(&font_file, &query(None, "wght")),
+            Err(ApiError::InvalidQuery(_))
+        ));
+
+        let font_file = FontFile::from_path(fixture("FiraMono-Medium.ttf")).unwrap();
+        assert!(matches!(
+            font_instance(&font_file, &query(None, "wght=700")),
+            Err(ApiError::NotVariable)
+        ));
+    }
+
+    #[test]
+    fn test_font_file_query() {
+        let font_file = FontFile::from_path(fixture("NotoSansHebrew-VF.ttf")).unwrap();
+        let uri = "/font-file?file=a.ttf&wght=700&variations=wdth%3D80&v=2"
+            .parse()
+            .unwrap();
+        let Query(query) = Query::<FontFileQuery>::try_from_uri(&uri).unwrap();
+        assert_eq!(query.variations, "wdth=80");
+        assert_eq!(query.instance, None);
+        assert_eq!(axis_parameters(&uri, &font_file, None), "wght=700");
+
+        let uri = "/font-file?file=a.ttf&wght=600&wdth=80".parse().unwrap();
+        assert_eq!(axis_parameters(&uri, &font_file, None), "wght=600,wdth=80");
+        assert_eq!(axis_parameters(&uri, &font_file, Some(1)), "");
+        // Parameters that aren't axes of the font are left alone.
+        let uri = "/font-file?file=a.ttf&slnt=-10&utm_x=1&wdth=80"
+            .parse()
+            .unwrap();
+        assert_eq!(axis_parameters(&uri, &font_file, None), "wdth=80");
+        assert_eq!(
+            axis_parameters(&"/font-file?file=a.ttf".parse().unwrap(), &font_file, None),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_fallback_font() {
+        let path = env::temp_dir().join(format!("figma-agent-fallback-{}.ttf", process::id()));
+        fs::copy(fixture("DejaVuSans.ttf"), &path).unwrap();
+        let config = Config {
+            fallback_font: Some(path.clone()),
+            ..Config::default()
+        };
+
+        let (font, modified_at) = fallback_font(&config);
+        assert_eq!(font.as_deref(), Some(path.as_path()));
+        assert!(modified_at.is_some());
+
+        // Replacing the file changes the preview keys that use it.
+        let replaced_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(replaced_at)
+            .unwrap();
+        assert_eq!(fallback_font(&config).1, Some(replaced_at));
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(fallback_font(&config), (Some(path), None));
+        assert_eq!(fallback_font(&Config::default()), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_render_query_limits() {
+        let config = Config::default();
+        let query = |font_size: f32, text: &str| FontRenderQuery {
+            file: PathBuf::from("missing.ttf"),
+            family: String::new(),
+            style: String::new(),
+            postscript: String::new(),
+            text: text.into(),
+            font_size,
+            variations: String::new(),
+            features: String::new(),
+            language: String::new(),
+            format: None,
+            dpr: None,
+            precision: None,
+        };
+        let headers = HeaderMap::new();
+
+        for font_size in [f32::NAN, 0.0, -12.0, 1000.5, f32::INFINITY] {
+            assert!(matches!(
+                font_render_key(&config, &query(font_size, ""), &headers).await,
+                Err(ApiError::InvalidQuery(_))
+            ));
+        }
+        let text = "a".repeat(MAX_TEXT_LENGTH + 1);
+        assert!(matches!(
+            font_render_key(&config, &query(12.0, &text), &headers).await,
+            Err(ApiError::InvalidQuery(_))
+        ));
+        // Valid queries get as far as looking up the font.
+        let text = "a".repeat(MAX_TEXT_LENGTH);
+        assert!(matches!(
+            font_render_key(&config, &query(MAX_FONT_SIZE, &text), &headers).await,
+            Err(ApiError::FontNotFound(_))
+        ));
+        let config = Config {
+            enable_font_preview: false,
+            ..Config::default()
+        };
+        assert!(matches!(
+            font_render_key(&config, &query(12.0, ""), &headers).await,
+            Err(ApiError::PreviewDisabled)
+        ));
+
+        let query = |font_size: Option<f32>| {
+            Query(FontShapeQuery {
+                file: PathBuf::from("missing.ttf"),
+                family: String::new(),
+                style: String::new(),
+                postscript: String::new(),
+                text: String::new(),
+                font_size,
+                variations: String::new(),
+                features: String::new(),
+                language: String::new(),
+            })
+        };
+        assert!(matches!(
+            font_shape(query(Some(f32::NAN))).await,
+            Err(ApiError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            font_shape(query(Some(0.0))).await,
+            Err(ApiError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            font_shape(query(None)).await,
+            Err(ApiError::FontNotFound(_))
+        ));
+        let query = Query(FontShapeQuery {
+            text: "a".repeat(MAX_TEXT_LENGTH + 1),
+            ..query(None).0
+        });
+        assert!(matches!(
+            font_shape(query).await,
+            Err(ApiError::InvalidQuery(_))
+        ));
+    }
+
+    /// Glyph reads wait for a permit like the previews do, and give up when
+    /// none is free in time.
+    #[tokio::test(start_paused = true)]
+    async fn test_glyph_query_limits() {
+        static PERMITS: Semaphore = Semaphore::const_new(1);
+        let timeout = Duration::from_millis(100);
+        let inspect = || {
+            let path = fixture("Inter-Regular.ttf");
+            move || Ok(inspect_glyphs((&path, 0), None, 0, 1)?)
+        };
+
+        let permit = PERMITS.acquire().await.unwrap();
+        assert!(matches!(
+            render_within(&PERMITS, timeout, inspect()).await,
+            Err(ApiError::PreviewBusy)
+        ));
+
+        // With a permit free, they render.
+        drop(permit);
+        let (_, glyphs) = render_within(&PERMITS, timeout, inspect()).await.unwrap();
+        assert_eq!(glyphs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_render_within() {
+        static PERMITS: Semaphore = Semaphore::const_new(1);
+        let timeout = Duration::from_millis(100);
+
+        assert_eq!(render_within(&PERMITS, timeout, || Ok(1)).await.unwrap(), 1);
+
+        // A render past the timeout keeps its permit until it finishes, so the
+        // next one can't get a permit.
+        let slow = || {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        };
+        assert!(matches!(
+            render_within(&PERMITS, timeout, slow).await,
+            Err(ApiError::PreviewTimeout(_))
+        ));
+        assert!(matches!(
+            render_within(&PERMITS, timeout, || Ok(())).await,
+            Err(ApiError::PreviewBusy)
+        ));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(PERMITS.available_permits(), 1);
     }
 }
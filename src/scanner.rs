@@ -5,14 +5,38 @@ use walkdir::WalkDir;
 
 const FONT_EXTENSIONS: [&str; 4] = ["ttf", "ttc", "otf", "otc"];
 
-pub fn scan_font_paths(
-    directories: impl IntoIterator<Item = impl AsRef<Path>>,
-) -> impl Iterator<Item = PathBuf> {
+/// Where a font directory comes from.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum FontSource {
+    /// A directory listed in `font_directories` of the config.
+    Configured,
+    /// A Fontconfig directory under the user's home directory.
+    User,
+    /// Any other Fontconfig directory.
+    #[default]
+    System,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FontDirectory {
+    pub path: PathBuf,
+    pub source: FontSource,
+}
+
+/// Scan font files in the given directories. If a font file is reachable from
+/// multiple directories, the source of the first directory wins.
+pub fn scan_font_paths<'a>(
+    directories: impl IntoIterator<Item = &'a FontDirectory>,
+) -> impl Iterator<Item = (PathBuf, FontSource)> {
     directories
         .into_iter()
-        .flat_map(|directory| WalkDir::new(directory))
-        .filter_map(|entry| match entry {
-            Ok(entry) => Some(entry),
+        .flat_map(|directory| {
+            WalkDir::new(&directory.path)
+                .into_iter()
+                .map(|entry| (entry, directory.source))
+        })
+        .filter_map(|(entry, source)| match entry {
+            Ok(entry) => Some((entry, source)),
             Err(error) => {
                 tracing::debug!(
                     "Skipped font file/directory: {path:?}, error: {error:?}",
@@ -21,7 +45,7 @@ pub fn scan_font_paths(
                 None
             }
         })
-        .filter(|entry| {
+        .filter(|(entry, _)| {
             entry.file_type().is_file()
                 && match entry.path().extension() {
                     Some(extension) => FONT_EXTENSIONS
@@ -30,8 +54,8 @@ pub fn scan_font_paths(
                     None => false,
                 }
         })
-        .filter_map(|entry| match entry.path().canonicalize() {
-            Ok(path) => Some(path),
+        .filter_map(|(entry, source)| match entry.path().canonicalize() {
+            Ok(path) => Some((path, source)),
             Err(error) => {
                 tracing::debug!(
                     "Skipped font file: {path:?}, error: {error:?}",
@@ -40,5 +64,5 @@ pub fn scan_font_paths(
                 None
             }
         })
-        .unique()
+        .unique_by(|(path, _)| path.clone())
 }
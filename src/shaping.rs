@@ -0,0 +1,192 @@
+use std::{fs, iter, path::Path};
+
+use harfrust::{
+    Direction, Feature, GlyphBuffer, Language, Script, ShapeOptions, ShaperData, ShaperInstance,
+    UnicodeBuffer, Variation,
+};
+use read_fonts::TableProvider;
+use skrifa::{FontRef, GlyphNameSource, MetadataProvider};
+
+use crate::{
+    payload::{ShapedGlyphPayload, ShapingPayload},
+    renderer::{RenderError, RunFont},
+};
+
+#[derive(Debug, Clone)]
+pub struct ShapingOptions<'a> {
+    pub font: (&'a Path, usize),
+    /// Point size the font is shaped at, for fonts that track or pick glyphs by
+    /// size. Positions are in font units regardless.
+    pub size: Option<f32>,
+    pub named_instance_index: Option<usize>,
+    /// Axis coordinates in user space, overriding those of the named instance.
+    pub variations: &'a [Variation],
+    /// OpenType features applied during shaping, such as `ss01` or `-liga`.
+    pub features: &'a [Feature],
+    /// Language of the text, selecting language-specific forms with `locl`.
+    pub language: Option<&'a Language>,
+}
+
+/// Text of a run, with the text around it as context and the direction and script
+/// it is shaped with when they are known rather than guessed.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TextRun<'a> {
+    pub text: &'a str,
+    pub pre_context: &'a str,
+    pub post_context: &'a str,
+    pub direction: Option<Direction>,
+    pub script: Option<Script>,
+}
+
+impl<'a> From<&'a str> for TextRun<'a> {
+    fn from(text: &'a str) -> Self {
+        TextRun {
+            text,
+            ..TextRun::default()
+        }
+    }
+}
+
+/// A run of text shaped with a single font.
+pub(crate) struct ShapedRun {
+    pub direction: Direction,
+    pub script: Script,
+    pub glyphs: GlyphBuffer,
+}
+
+/// Shape the run with the font, guessing its direction and script unless given.
+pub(crate) fn shape_run<'a>(
+    run_font: &RunFont,
+    run: impl Into<TextRun<'a>>,
+    features: &[Feature],
+    language: Option<&Language>,
+    point_size: Option<f32>,
+) -> ShapedRun {
+    let shaper_data = ShaperData::new(&run_font.font);
+    let shaper_instance = ShaperInstance::from_variations(&run_font.font, &run_font.variations);
+    let shaper = shaper_data
+        .shaper(&run_font.font)
+        .instance(Some(&shaper_instance))
+        .build();
+
+    let run = run.into();
+    let mut buffer = UnicodeBuffer::new();
+    buffer.set_pre_context(run.pre_context);
+    buffer.push_str(run.text);
+    buffer.set_post_context(run.post_context);
+    if let Some(direction) = run.direction {
+        buffer.set_direction(direction);
+    }
+    if let Some(script) = run.script {
+        buffer.set_script(script);
+    }
+    if let Some(language) = language {
+        buffer.set_language(language.clone());
+    }
+    buffer.guess_segment_properties();
+    let (direction, script) = (buffer.direction(), buffer.script());
+
+    let glyphs = shaper.shape(
+        buffer,
+        ShapeOptions::new()
+            .point_size(point_size)
+            .features(features),
+    );
+    ShapedRun {
+        direction,
+        script,
+        glyphs,
+    }
+}
+
+/// Shape the text with the face as a single run, without falling back to other
+/// fonts for missing characters, which map to glyph 0.
+pub fn shape_text(
+    text: impl AsRef<str>,
+    ShapingOptions {
+        font: (font_path, font_index),
+        size,
+        named_instance_index,
+        variations,
+        features,
+        language,
+    }: ShapingOptions,
+) -> Result<ShapingPayload, RenderError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+    let run_font = RunFont::new(font.clone(), named_instance_index, variations);
+
+    let ShapedRun {
+        direction,
+        script,
+        glyphs,
+    } = shape_run(&run_font, text.as_ref(), features, language, size);
+
+    let names = font.glyph_names();
+    let has_names = names.source() != GlyphNameSource::Synthesized;
+    let glyphs = iter::zip(glyphs.glyph_infos(), glyphs.glyph_positions())
+        .map(|(info, position)| ShapedGlyphPayload {
+            id: info.glyph_id,
+            name: has_names
+                .then(|| names.get(info.glyph_id.into()))
+                .flatten()
+                .map(|name| name.to_string()),
+            cluster: info.cluster,
+            x_advance: position.x_advance,
+            y_advance: position.y_advance,
+            x_offset: position.x_offset,
+            y_offset: position.y_offset,
+        })
+        .collect();
+
+    Ok(ShapingPayload {
+        units_per_em: font.head()?.units_per_em(),
+        direction: match direction {
+            Direction::LeftToRight => "ltr",
+            Direction::RightToLeft => "rtl",
+            Direction::TopToBottom => "ttb",
+            Direction::BottomToTop => "btt",
+            Direction::Invalid => "invalid",
+        },
+        script: script.tag().to_string(),
+        glyphs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::fixture;
+
+    #[test]
+    fn test_shape_text() {
+        let path = fixture("FiraMono-Medium.ttf");
+        let options = ShapingOptions {
+            font: (&path, 0),
+            size: None,
+            named_instance_index: None,
+            variations: &[],
+            features: &[],
+            language: None,
+        };
+
+        let shaping = shape_text("Fira", options.clone()).unwrap();
+        assert_eq!(shaping.direction, "ltr");
+        assert_eq!(shaping.script, "Latn");
+        assert_eq!(shaping.units_per_em, 1000);
+        assert_eq!(shaping.glyphs.len(), 4);
+        assert_eq!(shaping.glyphs[0].name.as_deref(), Some("F"));
+        assert!(shaping.glyphs.iter().all(|glyph| glyph.x_advance == 600));
+
+        // Right-to-left runs come out in visual order, with missing characters
+        // mapped to glyph 0.
+        let shaping = shape_text("\u{5E2}\u{5D1}", options).unwrap();
+        assert_eq!(shaping.direction, "rtl");
+        let clusters = shaping
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.id, glyph.cluster))
+            .collect::<Vec<_>>();
+        assert_eq!(clusters, [(0, 2), (0, 0)]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    time::Instant,
+};
+
+use crate::ACTIVITY;
+
+/// Requests in flight and when the service was last busy, for the idle timeout.
+#[derive(Debug)]
+pub struct Activity {
+    in_flight: AtomicUsize,
+    last_active_at: Mutex<Instant>,
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Activity {
+            in_flight: AtomicUsize::new(0),
+            last_active_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn touch(&self) {
+        *self
+            .last_active_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    /// When the last request finished, or `None` while requests are in flight.
+    pub fn idle_since(&self) -> Option<Instant> {
+        let last_active_at = *self
+            .last_active_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        (self.in_flight.load(Ordering::SeqCst) == 0).then_some(last_active_at)
+    }
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Activity::new()
+    }
+}
+
+/// Counts a request as in flight until it's dropped, even if it's cancelled.
+struct InFlight<'a>(&'a Activity);
+
+impl<'a> InFlight<'a> {
+    fn new(activity: &'a Activity) -> Self {
+        activity.in_flight.fetch_add(1, Ordering::SeqCst);
+        activity.touch();
+        InFlight(activity)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware that records the activity of the requests it handles.
+pub async fn track_activity(request: Request, next: Next) -> Response {
+    let _in_flight = InFlight::new(&ACTIVITY);
+    next.run(request).await
+}
+
+/// Why the service is shutting down.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShutdownReason {
+    Terminate,
+    Interrupt,
+    Idle,
+}
+
+/// Wait for SIGTERM or SIGINT, or for the service to be idle for `idle_timeout`.
+pub async fn shutdown_signal(idle_timeout: Option<Duration>) -> ShutdownReason {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => Some(terminate),
+        Err(error) => {
+            tracing::warn!("Failed to listen for SIGTERM, error: {error:?}");
+            None
+        }
+    };
+    let terminate = async {
+        match &mut terminate {
+            Some(terminate) => terminate.recv().await,
+            None => std::future::pending().await,
+        }
+    };
+    let idle = async {
+        match idle_timeout {
+            Some(idle_timeout) => idle(&ACTIVITY, idle_timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        _ = terminate => ShutdownReason::Terminate,
+        _ = tokio::signal::ctrl_c() => ShutdownReason::Interrupt,
+        _ = idle => ShutdownReason::Idle,
+    }
+}
+
+/// Wait until no request has been in flight for the timeout.
+async fn idle(activity: &Activity, timeout: Duration) {
+    loop {
+        match activity.idle_since() {
+            Some(idle_since) if idle_since.elapsed() >= timeout => return,
+            Some(idle_since) => tokio::time::sleep_until(idle_since + timeout).await,
+            // Check again once the requests in flight could have finished.
+            None => tokio::time::sleep(timeout).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle() {
+        let timeout = Duration::from_millis(50);
+        let activity = Activity::new();
+        let started_at = Instant::now();
+        idle(&activity, timeout).await;
+        assert!(started_at.elapsed() >= timeout);
+
+        // A request in flight keeps the service busy until it finishes.
+        let in_flight = InFlight::new(&activity);
+        assert_eq!(activity.idle_since(), None);
+        let started_at = Instant::now();
+        tokio::join!(idle(&activity, timeout), async {
+            tokio::time::sleep(timeout * 2).await;
+            drop(in_flight);
+        });
+        assert!(started_at.elapsed() >= timeout * 3);
+    }
+}
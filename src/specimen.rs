@@ -0,0 +1,775 @@
+use std::{fs, io::Write, path::Path};
+
+use flate2::{Compression, write::ZlibEncoder};
+
+use kurbo::{Affine, BezPath, PathEl, Point};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use skrifa::{
+    FontRef, MetadataProvider,
+    instance::{LocationRef, Size},
+    string::StringId,
+};
+use svg::{Document, node::element};
+
+use crate::{
+    font::{Font, SkrifaFontRefExt},
+    paragraph::{ParagraphOptions, TextAlign, WritingMode, draw_paragraph},
+    preview::script_samples,
+    renderer::{
+        OutlinePath, RenderError, RenderFormat, RenderOptions, RunFont, draw_glyphs, draw_text,
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecimenFormat {
+    /// All pages stacked in a single SVG document.
+    #[default]
+    Svg,
+    Pdf,
+}
+
+impl SpecimenFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SpecimenFormat::Svg => "image/svg+xml",
+            SpecimenFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// A4 in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const PAGE_MARGIN: f32 = 48.0;
+/// Gap between pages stacked in an SVG specimen.
+const PAGE_GAP: f32 = 24.0;
+
+const LABEL_SIZE: f32 = 9.0;
+const LABEL_LEADING: f32 = 14.0;
+const HEADING_SIZE: f32 = 14.0;
+
+const PANGRAM: &str = "The quick brown fox jumps over the lazy dog";
+/// Font sizes of the waterfall, in points.
+const WATERFALL_SIZES: [f32; 14] = [
+    8.0, 9.0, 10.0, 11.0, 12.0, 14.0, 16.0, 18.0, 24.0, 30.0, 36.0, 48.0, 60.0, 72.0,
+];
+const INSTANCE_SIZE: f32 = 24.0;
+const PARAGRAPH_SIZE: f32 = 12.0;
+/// Distance between the lines of the paragraph as a multiple of its size.
+const PARAGRAPH_LINE_HEIGHT: f32 = 1.5;
+
+const GRID_COLUMNS: usize = 12;
+const GRID_CELL_HEIGHT: f32 = 48.0;
+const GRID_GLYPH_SIZE: f32 = 22.0;
+/// Characters beyond this are left out of the grid, so that specimens of CJK
+/// fonts don't run to thousands of pages.
+const MAX_GRID_CHARS: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Page {
+    /// Glyph outlines in page coordinates, with y pointing down.
+    path: BezPath,
+    labels: Vec<Label>,
+}
+
+/// A line of text set in the viewer's sans-serif font rather than the specimen font.
+#[derive(Debug)]
+struct Label {
+    x: f32,
+    /// Baseline of the text.
+    y: f32,
+    size: f32,
+    gray: bool,
+    text: String,
+}
+
+struct Specimen<'a> {
+    font_path: &'a Path,
+    font: &'a Font,
+    pages: Vec<Page>,
+    cursor_y: f32,
+}
+
+/// Render a multi-page type specimen of the font: its metadata, a waterfall of
+/// sizes, every named instance and the characters it supports.
+pub fn render_specimen(
+    font_path: &Path,
+    font: &Font,
+    format: SpecimenFormat,
+) -> Result<Vec<u8>, RenderError> {
+    let data = fs::read(font_path)?;
+    let font_ref = FontRef::from_index(&data, font.index as u32)?;
+
+    let mut specimen = Specimen {
+        font_path,
+        font,
+        pages: vec![Page::default()],
+        cursor_y: PAGE_MARGIN,
+    };
+
+    let family_name = font.family_name.as_deref().unwrap_or_default();
+    let sample = sample_text(&font_ref, family_name);
+
+    specimen.title(family_name)?;
+    specimen.metadata(font_path);
+    if let Some(sample) = &sample {
+        specimen.waterfall(sample)?;
+        specimen.paragraph(sample)?;
+        specimen.named_instances(sample)?;
+    }
+    specimen.character_grid(&font_ref)?;
+
+    match format {
+        SpecimenFormat::Svg => Ok(specimen.into_svg()),
+        SpecimenFormat::Pdf => specimen.into_pdf(),
+    }
+}
+
+/// The first of the pangram, the sample text of the font and the script samples
+/// that the font has all characters for.
+fn sample_text(font: &FontRef, family_name: &str) -> Option<String> {
+    let charmap = font.charmap();
+    Some(PANGRAM.to_string())
+        .into_iter()
+        .chain(font.string(StringId::SAMPLE_TEXT))
+        .chain(script_samples(font))
+        .chain(Some(family_name.to_string()))
+        .find(|text| {
+            !text.trim().is_empty()
+                && text
+                    .chars()
+                    .all(|char| char.is_whitespace() || charmap.map(char).is_some())
+        })
+}
+
+impl Specimen<'_> {
+    fn content_width() -> f32 {
+        PAGE_WIDTH - PAGE_MARGIN * 2.0
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("specimen has a page")
+    }
+
+    /// Start a new page unless the block fits on the current one.
+    fn reserve(&mut self, height: f32) {
+        if self.cursor_y + height > PAGE_HEIGHT - PAGE_MARGIN && self.cursor_y > PAGE_MARGIN {
+            self.pages.push(Page::default());
+            self.cursor_y = PAGE_MARGIN;
+        }
+    }
+
+    fn label(&mut self, x: f32, size: f32, gray: bool, text: impl Into<String>) {
+        let y = self.cursor_y + size;
+        self.page().labels.push(Label {
+            x,
+            y,
+            size,
+            gray,
+            text: text.into(),
+        });
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.reserve(HEADING_SIZE * 4.0);
+        self.cursor_y += HEADING_SIZE;
+        self.label(PAGE_MARGIN, HEADING_SIZE, false, text);
+        self.cursor_y += HEADING_SIZE * 2.0;
+    }
+
+    /// Lay out a line of text in the font with its top left corner at `(x, y)`,
+    /// returning its height.
+    fn text(
+        &mut self,
+        text: &str,
+        size: f32,
+        named_instance_index: Option<usize>,
+        x: f32,
+    ) -> Result<Option<f32>, RenderError> {
+        let mut outline_path = OutlinePath::default();
+        let Some((_, height)) = draw_text(
+            text,
+            self.render_options(size, named_instance_index),
+            &mut outline_path,
+        )?
+        else {
+            return Ok(None);
+        };
+
+        let mut path = outline_path.into_path();
+        path.apply_affine(Affine::translate((x as f64, self.cursor_y as f64)));
+        self.page().path.extend(path);
+        Ok(Some(height))
+    }
+
+    fn render_options(&self, size: f32, named_instance_index: Option<usize>) -> RenderOptions<'_> {
+        RenderOptions {
+            font: (self.font_path, self.font.index),
+            size,
+            named_instance_index,
+            variations: &[],
+            features: &[],
+            language: None,
+            format: RenderFormat::Svg,
+            pixel_ratio: 1.0,
+            svg_precision: None,
+            fallback_font: None,
+        }
+    }
+
+    /// The longest prefix of the text that fits in `max_width` at the size.
+    fn fit_text(
+        &self,
+        text: &str,
+        size: f32,
+        named_instance_index: Option<usize>,
+        max_width: f32,
+    ) -> Result<String, RenderError> {
+        let measure = |text: &str| -> Result<f32, RenderError> {
+            let mut outline_path = OutlinePath::default();
+            let size = draw_text(
+                text,
+                self.render_options(size, named_instance_index),
+                &mut outline_path,
+            )?;
+            Ok(size.map_or(0.0, |(width, _)| width))
+        };
+
+        let chars = text.chars().collect::<Vec<_>>();
+        let width = measure(text)?;
+        if width <= max_width {
+            return Ok(text.to_string());
+        }
+
+        // Advances scale linearly with the size, so start from the proportional
+        // estimate and only drop characters that still overflow.
+        let mut length = ((chars.len() as f32 * max_width / width) as usize).min(chars.len());
+        loop {
+            let prefix = chars[..length].iter().collect::<String>();
+            let prefix = prefix.trim_end();
+            if length <= 1 || measure(prefix)? <= max_width {
+                return Ok(prefix.to_string());
+            }
+            length -= 1;
+        }
+    }
+
+    fn title(&mut self, family_name: &str) -> Result<(), RenderError> {
+        let subfamily_name = self.font.subfamily_name.as_deref().unwrap_or_default();
+        let title = format!("{family_name} {subfamily_name}");
+        let title = self.fit_text(title.trim(), 36.0, None, Self::content_width())?;
+        match self.text(&title, 36.0, None, PAGE_MARGIN)? {
+            Some(height) => self.cursor_y += height,
+            None => {
+                self.label(PAGE_MARGIN, 24.0, false, title);
+                self.cursor_y += 36.0;
+            }
+        }
+        self.cursor_y += LABEL_LEADING;
+        Ok(())
+    }
+
+    fn metadata(&mut self, font_path: &Path) {
+        let font = self.font;
+        let slope = if font.is_italic {
+            "Italic"
+        } else if font.is_oblique {
+            "Oblique"
+        } else {
+            "Upright"
+        };
+
+        let mut lines = vec![
+            ("Family", font.family_name.clone().unwrap_or_default()),
+            ("Style", font.subfamily_name.clone().unwrap_or_default()),
+            (
+                "PostScript name",
+                font.postscript_name.clone().unwrap_or_default(),
+            ),
+            ("Version", font.version.clone().unwrap_or_default()),
+            ("Weight", font.weight.to_string()),
+            ("Width", format!("{}%", font.width)),
+            ("Slope", slope.to_string()),
+            (
+                "File",
+                format!("{} (index {})", font_path.display(), font.index),
+            ),
+        ];
+        for axis in &font.axes {
+            lines.push((
+                "Axis",
+                format!(
+                    "{} {}: {} to {}, default {}{}",
+                    axis.tag,
+                    axis.name.as_deref().unwrap_or_default(),
+                    axis.min_value,
+                    axis.max_value,
+                    axis.default_value,
+                    if axis.is_hidden { " (hidden)" } else { "" },
+                ),
+            ));
+        }
+
+        for (name, value) in lines {
+            self.reserve(LABEL_LEADING);
+            self.label(PAGE_MARGIN, LABEL_SIZE, true, name);
+            self.label(PAGE_MARGIN + 96.0, LABEL_SIZE, false, value);
+            self.cursor_y += LABEL_LEADING;
+        }
+    }
+
+    fn waterfall(&mut self, sample: &str) -> Result<(), RenderError> {
+        self.heading("Waterfall");
+        let indent = 40.0;
+        for size in WATERFALL_SIZES {
+            let text = self.fit_text(sample, size, None, Self::content_width() - indent)?;
+            self.reserve(size * 1.5);
+            self.label(PAGE_MARGIN, LABEL_SIZE, true, format!("{size} pt"));
+            if let Some(height) = self.text(&text, size, None, PAGE_MARGIN + indent)? {
+                self.cursor_y += height.max(LABEL_LEADING);
+            }
+            self.cursor_y += size * 0.25;
+        }
+        Ok(())
+    }
+
+    /// The sample set as a paragraph wrapped to the width of the page.
+    fn paragraph(&mut self, sample: &str) -> Result<(), RenderError> {
+        self.heading("Paragraph");
+        let text = [sample; 4].join(" ");
+        let mut outline_path = OutlinePath::default();
+        let (_, height) = draw_paragraph(
+            &text,
+            ParagraphOptions {
+                font: (self.font_path, self.font.index),
+                size: PARAGRAPH_SIZE,
+                named_instance_index: None,
+                variations: &[],
+                features: &[],
+                language: None,
+                max_width: Some(Self::content_width()),
+                line_height: Some(PARAGRAPH_LINE_HEIGHT),
+                align: TextAlign::Start,
+                writing_mode: WritingMode::HorizontalTb,
+                svg_precision: None,
+            },
+            &mut outline_path,
+        )?;
+
+        self.reserve(height);
+        let mut path = outline_path.into_path();
+        path.apply_affine(Affine::translate((
+            PAGE_MARGIN as f64,
+            self.cursor_y as f64,
+        )));
+        self.page().path.extend(path);
+        self.cursor_y += height + LABEL_LEADING;
+        Ok(())
+    }
+
+    fn named_instances(&mut self, sample: &str) -> Result<(), RenderError> {
+        let font = self.font;
+        if font.named_instances.is_empty() {
+            return Ok(());
+        }
+
+        self.heading("Named instances");
+        for named_instance in &font.named_instances {
+            let coordinates = font
+                .axes
+                .iter()
+                .zip(&named_instance.coordinates)
+                .map(|(axis, value)| format!("{}={value}", axis.tag))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let text = self.fit_text(
+                sample,
+                INSTANCE_SIZE,
+                Some(named_instance.index),
+                Self::content_width(),
+            )?;
+
+            self.reserve(LABEL_LEADING + INSTANCE_SIZE * 1.5);
+            self.label(
+                PAGE_MARGIN,
+                LABEL_SIZE,
+                true,
+                format!(
+                    "{} ({coordinates})",
+                    named_instance.subfamily_name.as_deref().unwrap_or_default()
+                ),
+            );
+            self.cursor_y += LABEL_LEADING;
+            if let Some(height) = self.text(
+                &text,
+                INSTANCE_SIZE,
+                Some(named_instance.index),
+                PAGE_MARGIN,
+            )? {
+                self.cursor_y += height;
+            }
+            self.cursor_y += LABEL_LEADING / 2.0;
+        }
+        Ok(())
+    }
+
+    fn character_grid(&mut self, font_ref: &FontRef) -> Result<(), RenderError> {
+        let mut chars = font_ref
+            .charmap()
+            .mappings()
+            .filter_map(|(codepoint, glyph_id)| Some((char::from_u32(codepoint)?, glyph_id)))
+            .filter(|(char, _)| !char.is_control() && !char.is_whitespace())
+            .collect::<Vec<_>>();
+        if chars.is_empty() {
+            return Ok(());
+        }
+
+        self.heading(&format!("Character set ({} characters)", chars.len()));
+        let omitted = chars.len().saturating_sub(MAX_GRID_CHARS);
+        chars.truncate(MAX_GRID_CHARS);
+
+        let fonts = [RunFont::new(font_ref.clone(), None, &[])];
+        let size = Size::new(GRID_GLYPH_SIZE);
+        let metrics = font_ref.metrics(size, &fonts[0].location);
+        let glyph_metrics = font_ref.glyph_metrics(size, &fonts[0].location);
+        let line_height = metrics.ascent - metrics.descent;
+        let cell_width = Self::content_width() / GRID_COLUMNS as f32;
+        let glyph_area = GRID_CELL_HEIGHT - LABEL_LEADING;
+
+        for row in chars.chunks(GRID_COLUMNS) {
+            self.reserve(GRID_CELL_HEIGHT);
+            let baseline = self.cursor_y + (glyph_area - line_height) / 2.0 + metrics.ascent;
+
+            let mut glyphs = Vec::new();
+            for (column, (char, glyph_id)) in row.iter().enumerate() {
+                let cell_x = PAGE_MARGIN + column as f32 * cell_width;
+                let advance = glyph_metrics.advance_width(*glyph_id).unwrap_or_default();
+                glyphs.push((
+                    0,
+                    *glyph_id,
+                    cell_x + (cell_width - advance) / 2.0,
+                    baseline,
+                ));
+
+                let cursor_y = self.cursor_y;
+                self.cursor_y += glyph_area;
+                self.label(
+                    cell_x + 4.0,
+                    LABEL_SIZE * 0.75,
+                    true,
+                    format!("{:04X}", *char as u32),
+                );
+                self.cursor_y = cursor_y;
+            }
+
+            let mut outline_path = OutlinePath::default();
+            draw_glyphs(&mut outline_path, &fonts, &glyphs, size)?;
+            self.page().path.extend(outline_path.into_path());
+            self.cursor_y += GRID_CELL_HEIGHT;
+        }
+
+        if omitted > 0 {
+            self.reserve(LABEL_LEADING);
+            self.label(
+                PAGE_MARGIN,
+                LABEL_SIZE,
+                true,
+                format!("and {omitted} more characters"),
+            );
+        }
+        Ok(())
+    }
+
+    fn into_svg(self) -> Vec<u8> {
+        let page_count = self.pages.len() as f32;
+        let height = PAGE_HEIGHT * page_count + PAGE_GAP * (page_count - 1.0);
+        let mut document = Document::new()
+            .set("width", PAGE_WIDTH)
+            .set("height", height)
+            .set("viewBox", (0.0, 0.0, PAGE_WIDTH, height))
+            .set("font-family", "sans-serif");
+
+        for (index, page) in self.pages.into_iter().enumerate() {
+            let mut group = element::SVG::new()
+                .set("y", index as f32 * (PAGE_HEIGHT + PAGE_GAP))
+                .set("width", PAGE_WIDTH)
+                .set("height", PAGE_HEIGHT)
+                .add(
+                    element::Rectangle::new()
+                        .set("width", PAGE_WIDTH)
+                        .set("height", PAGE_HEIGHT)
+                        .set("fill", "white"),
+                )
+                .add(element::Path::new().set("d", page.path.to_svg()));
+            for label in page.labels {
+                let mut text = element::Text::new(label.text)
+                    .set("x", label.x)
+                    .set("y", label.y)
+                    .set("font-size", label.size);
+                if label.gray {
+                    text = text.set("fill", "gray");
+                }
+                group = group.add(text);
+            }
+            document = document.add(group);
+        }
+
+        document.to_string().into_bytes()
+    }
+
+    fn into_pdf(self) -> Result<Vec<u8>, RenderError> {
+        let mut pdf = Pdf::new();
+        let mut next_ref = Ref::new(1);
+        let mut alloc = || next_ref.bump();
+
+        let catalog_id = alloc();
+        let page_tree_id = alloc();
+        let info_id = alloc();
+        let font_id = alloc();
+        let font_name = Name(b"F1");
+
+        let page_ids = self.pages.iter().map(|_| alloc()).collect::<Vec<_>>();
+        let content_ids = self.pages.iter().map(|_| alloc()).collect::<Vec<_>>();
+
+        pdf.catalog(catalog_id).pages(page_tree_id);
+        pdf.pages(page_tree_id)
+            .kids(page_ids.iter().copied())
+            .count(page_ids.len() as i32);
+        let title = [&self.font.family_name, &self.font.subfamily_name]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        pdf.document_info(info_id).title(TextStr(&title));
+        pdf.type1_font(font_id)
+            .base_font(Name(b"Helvetica"))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+        // Ascent of the specimen font per unit of size, to place outlined labels.
+        let data = fs::read(self.font_path)?;
+        let ascent = FontRef::from_index(&data, self.font.index as u32)?
+            .metrics(Size::new(1.0), LocationRef::default())
+            .ascent;
+
+        for ((page, page_id), content_id) in self.pages.iter().zip(page_ids).zip(content_ids) {
+            let mut pdf_page = pdf.page(page_id);
+            pdf_page
+                .media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+                .parent(page_tree_id)
+                .contents(content_id);
+            pdf_page.resources().fonts().pair(font_name, font_id);
+            pdf_page.finish();
+
+            let mut content = Content::new();
+            if !page.path.is_empty() {
+                write_path(&mut content, &page.path);
+                content.fill_nonzero();
+            }
+
+            for label in &page.labels {
+                let gray = if label.gray { 0.5 } else { 0.0 };
+                if let Some(text) = label
+                    .text
+                    .chars()
+                    .map(win_ansi_code)
+                    .collect::<Option<Vec<_>>>()
+                {
+                    content
+                        .begin_text()
+                        .set_fill_gray(gray)
+                        .set_font(font_name, label.size)
+                        .next_line(label.x, PAGE_HEIGHT - label.y)
+                        .show(Str(&text))
+                        .end_text();
+                    continue;
+                }
+
+                // The standard fonts only cover Latin, so other labels are drawn as
+                // outlines of the specimen font, which usually has its own names.
+                let mut outline_path = OutlinePath::default();
+                if draw_text(
+                    &label.text,
+                    self.render_options(label.size, None),
+                    &mut outline_path,
+                )?
+                .is_none()
+                {
+                    tracing::debug!("Dropped specimen label: {:?}", label.text);
+                    continue;
+                }
+                let mut path = outline_path.into_path();
+                path.apply_affine(Affine::translate((
+                    label.x as f64,
+                    (label.y - ascent * label.size) as f64,
+                )));
+                content.set_fill_gray(gray);
+                write_path(&mut content, &path);
+                content.fill_nonzero();
+            }
+
+            let content = content.finish();
+            let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+            zlib.write_all(&content)
+                .map_err(|error| RenderError::Encode(error.into()))?;
+            let content = zlib
+                .finish()
+                .map_err(|error| RenderError::Encode(error.into()))?;
+            pdf.stream(content_id, &content).filter(Filter::FlateDecode);
+        }
+
+        Ok(pdf.finish())
+    }
+}
+
+/// Write a path in page coordinates to the content, flipping y for PDF.
+fn write_path(content: &mut Content, path: &BezPath) {
+    let to_pdf = |point: Point| (point.x as f32, PAGE_HEIGHT - point.y as f32);
+    let mut current = Point::ZERO;
+    for element in path.elements() {
+        match *element {
+            PathEl::MoveTo(p) => {
+                let (x, y) = to_pdf(p);
+                content.move_to(x, y);
+                current = p;
+            }
+            PathEl::LineTo(p) => {
+                let (x, y) = to_pdf(p);
+                content.line_to(x, y);
+                current = p;
+            }
+            PathEl::QuadTo(p1, p) => {
+                // PDF has no quadratic curves, so elevate them to cubic ones.
+                let (x1, y1) = to_pdf(current.lerp(p1, 2.0 / 3.0));
+                let (x2, y2) = to_pdf(p.lerp(p1, 2.0 / 3.0));
+                let (x, y) = to_pdf(p);
+                content.cubic_to(x1, y1, x2, y2, x, y);
+                current = p;
+            }
+            PathEl::CurveTo(p1, p2, p) => {
+                let (x1, y1) = to_pdf(p1);
+                let (x2, y2) = to_pdf(p2);
+                let (x, y) = to_pdf(p);
+                content.cubic_to(x1, y1, x2, y2, x, y);
+                current = p;
+            }
+            PathEl::ClosePath => {
+                content.close_path();
+            }
+        }
+    }
+}
+
+/// The code of a character in `WinAnsiEncoding`, which covers Latin-1 and some
+/// punctuation.
+fn win_ansi_code(char: char) -> Option<u8> {
+    let code = match char {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => char as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        '‹' => 0x8b,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9a,
+        '›' => 0x9b,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{fixtures::fixture, font::FontFile};
+
+    #[test]
+    fn test_render_specimen() {
+        let path = fixture("FiraMono-Medium.ttf");
+        let font_file = FontFile::from_path(&path).unwrap();
+        let font = &font_file.fonts[0];
+
+        let svg =
+            String::from_utf8(render_specimen(&path, font, SpecimenFormat::Svg).unwrap()).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Waterfall"));
+        assert!(svg.contains("Paragraph"));
+        assert!(svg.contains("Character set (7 characters)"));
+
+        let pdf = render_specimen(&path, font, SpecimenFormat::Pdf).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn test_pdf_labels() {
+        assert_eq!(win_ansi_code('A'), Some(b'A'));
+        assert_eq!(win_ansi_code('é'), Some(0xe9));
+        assert_eq!(win_ansi_code('—'), Some(0x97));
+        assert_eq!(win_ansi_code('א'), None);
+
+        let path = fixture("NotoSansHebrew-VF.ttf");
+        let font_file = FontFile::from_path(&path).unwrap();
+        let label = |text: &str| Label {
+            x: PAGE_MARGIN,
+            y: PAGE_MARGIN,
+            size: LABEL_SIZE,
+            gray: false,
+            text: text.into(),
+        };
+        let specimen = Specimen {
+            font_path: &path,
+            font: &font_file.fonts[0],
+            pages: vec![Page {
+                path: BezPath::new(),
+                labels: vec![label("Café"), label("אבג"), label("☃")],
+            }],
+            cursor_y: PAGE_MARGIN,
+        };
+        let pdf = specimen.into_pdf().unwrap();
+
+        let start = pdf
+            .windows(8)
+            .rposition(|bytes| bytes == b"\nstream\n")
+            .unwrap()
+            + 8;
+        let end = start
+            + pdf[start..]
+                .windows(10)
+                .position(|bytes| bytes == b"\nendstream")
+                .unwrap();
+        let mut content = Vec::new();
+        flate2::read::ZlibDecoder::new(&pdf[start..end])
+            .read_to_end(&mut content)
+            .unwrap();
+
+        // Latin-1 is encoded as is, Hebrew is drawn with the font and the snowman,
+        // which no font has, is left out rather than replaced.
+        let text = String::from_utf8(content).unwrap();
+        assert!(text.contains("<436166E9> Tj"), "{text}");
+        assert_eq!(text.matches(" Tj").count(), 1);
+        assert_eq!(text.lines().filter(|line| *line == "f").count(), 1);
+        assert!(!text.contains('?'));
+    }
+}
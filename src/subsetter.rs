@@ -0,0 +1,979 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs, iter,
+    ops::Range,
+    path::Path,
+};
+
+use read_fonts::{
+    FontData, FontRead, FontRef, ReadError, TableProvider,
+    collections::IntSet,
+    ps::{
+        cff::{CffFontRef, Subfont, blend::BlendState, index::Index},
+        cs::{self, CharstringContext, CharstringKind, CommandSink},
+        encoding::PredefinedEncoding,
+        error::Error as CffError,
+    },
+    tables::{
+        cff::Cff,
+        cff2::Cff2,
+        glyf::{Glyf, Glyph},
+        gvar::Gvar,
+        loca::Loca,
+    },
+    types::{Fixed, GlyphId, Tag},
+};
+use skrifa::MetadataProvider;
+use write_fonts::{
+    FontBuilder,
+    from_obj::ToOwnedTable,
+    tables::{
+        cmap::Cmap,
+        head::Head,
+        hhea::Hhea,
+        hmtx::{Hmtx, LongMetric},
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubsetError {
+    #[error("Failed to read font file")]
+    Read(#[from] std::io::Error),
+    #[error("Failed to parse font file")]
+    Parse(#[from] read_fonts::ReadError),
+    #[error("Failed to build cmap table")]
+    Cmap(#[from] write_fonts::tables::cmap::CmapConflict),
+    #[error("Failed to build font table")]
+    Build(#[from] write_fonts::BuilderError),
+}
+
+#[derive(Debug, Clone)]
+pub struct SubsetOptions<'a> {
+    pub font: (&'a Path, usize),
+    pub codepoints: &'a [u32],
+    pub glyph_ids: &'a [u32],
+}
+
+/// Tables that describe device-specific data of glyphs we might have removed.
+const DROPPED_TABLES: [&[u8; 4]; 4] = [b"DSIG", b"hdmx", b"LTSH", b"VDMX"];
+
+/// Build a subset of a font that only contains the requested codepoints and glyphs,
+/// plus anything reachable from them through GSUB substitutions and composite glyphs.
+///
+/// Glyph IDs are retained, so layout tables stay consistent without being
+/// rewritten. Glyphs outside the subset are left empty in `glyf`, `CFF ` and
+/// `gvar`, and their metrics are zeroed so that `hmtx` can shrink.
+pub fn subset_font(
+    SubsetOptions {
+        font: (font_path, font_index),
+        codepoints,
+        glyph_ids,
+    }: SubsetOptions,
+) -> Result<(Vec<u8>, &'static str), SubsetError> {
+    let data = fs::read(font_path)?;
+    let font = FontRef::from_index(&data, font_index as u32)?;
+
+    let num_glyphs = font.maxp()?.num_glyphs() as u32;
+    let charmap = font.charmap();
+
+    let mut glyphs = IntSet::<GlyphId>::empty();
+    glyphs.insert(GlyphId::NOTDEF);
+    glyphs.extend(
+        glyph_ids
+            .iter()
+            .filter(|glyph_id| **glyph_id < num_glyphs)
+            .map(|glyph_id| GlyphId::new(*glyph_id)),
+    );
+    glyphs.extend(
+        codepoints
+            .iter()
+            .filter_map(|codepoint| charmap.map(*codepoint)),
+    );
+
+    if let Ok(gsub) = font.gsub() {
+        let lookups = gsub.collect_lookups(&IntSet::all())?;
+        gsub.closure_glyphs(&lookups, &mut glyphs)?;
+    }
+
+    let mut font_builder = FontBuilder::new();
+
+    let requested_codepoints = codepoints.iter().collect::<HashSet<_>>();
+    let requested_glyph_ids = glyph_ids.iter().collect::<HashSet<_>>();
+    let mappings = charmap
+        .mappings()
+        .filter(|(codepoint, glyph_id)| {
+            requested_codepoints.contains(codepoint)
+                || requested_glyph_ids.contains(&glyph_id.to_u32())
+        })
+        .filter_map(|(codepoint, glyph_id)| char::from_u32(codepoint).zip(Some(glyph_id)));
+    font_builder.add_table(&Cmap::from_mappings(mappings)?)?;
+
+    if let (Ok(glyf), Ok(loca)) = (font.glyf(), font.loca(None)) {
+        let mut pending = glyphs.iter().collect::<Vec<_>>();
+        while let Some(glyph_id) = pending.pop() {
+            if let Ok(Some(Glyph::Composite(glyph))) = loca.get_glyf(glyph_id, &glyf) {
+                for component in glyph.components() {
+                    if glyphs.insert(component.glyph.into()) {
+                        pending.push(component.glyph.into());
+                    }
+                }
+            }
+        }
+
+        let (glyf_data, loca_data, is_long) = subset_glyf(&glyf, &loca, num_glyphs, &glyphs);
+        let mut head: Head = font.head()?.to_owned_table();
+        head.index_to_loc_format = is_long as i16;
+
+        font_builder
+            .add_table(&head)?
+            .add_raw(Tag::new(b"glyf"), glyf_data)
+            .add_raw(Tag::new(b"loca"), loca_data);
+    }
+
+    for tag in [Tag::new(b"CFF "), Tag::new(b"CFF2")] {
+        if let Some(cff_data) = font.data_for_tag(tag) {
+            match subset_cff(cff_data.as_bytes(), &mut glyphs) {
+                Ok(cff_data) => {
+                    font_builder.add_raw(tag, cff_data);
+                }
+                Err(error) => {
+                    tracing::warn!("Failed to subset {tag} table, error: {error:?}");
+                }
+            }
+        }
+    }
+
+    if let (Ok(hhea), Ok(hmtx)) = (font.hhea(), font.hmtx()) {
+        let hmtx = subset_hmtx(&hmtx, num_glyphs, &glyphs);
+        let mut hhea: Hhea = hhea.to_owned_table();
+        hhea.number_of_h_metrics = hmtx.h_metrics.len() as u16;
+        font_builder.add_table(&hhea)?.add_table(&hmtx)?;
+    }
+
+    if let Ok(gvar) = font.gvar() {
+        font_builder.add_raw(Tag::new(b"gvar"), subset_gvar(&gvar, &glyphs)?);
+    }
+
+    let dropped_tables = DROPPED_TABLES.map(Tag::new);
+    for record in font.table_directory().table_records() {
+        let tag = record.tag();
+        if !font_builder.contains(tag)
+            && !dropped_tables.contains(&tag)
+            && let Some(data) = font.data_for_tag(tag)
+        {
+            font_builder.add_raw(tag, data);
+        }
+    }
+
+    let extension = if font.cff().is_ok() || font.cff2().is_ok() {
+        "otf"
+    } else {
+        "ttf"
+    };
+
+    Ok((font_builder.build(), extension))
+}
+
+/// Copy the outlines of the glyphs in the subset, leaving the others empty.
+/// Returns the `glyf` and `loca` data and whether `loca` uses long offsets.
+fn subset_glyf(
+    glyf: &Glyf,
+    loca: &Loca,
+    num_glyphs: u32,
+    glyphs: &IntSet<GlyphId>,
+) -> (Vec<u8>, Vec<u8>, bool) {
+    let mut glyf_data = Vec::new();
+    let mut loca_offsets = vec![0u32];
+    for glyph_id in 0..num_glyphs {
+        if glyphs.contains(GlyphId::new(glyph_id)) {
+            let start = loca.get_raw(glyph_id as usize);
+            let end = loca.get_raw(glyph_id as usize + 1);
+            if let Some(glyph_data) = start.zip(end).and_then(|(start, end)| {
+                glyf.offset_data()
+                    .as_bytes()
+                    .get(start as usize..end as usize)
+            }) {
+                glyf_data.extend_from_slice(glyph_data);
+                glyf_data.resize(glyf_data.len().next_multiple_of(4), 0);
+            }
+        }
+        loca_offsets.push(glyf_data.len() as u32);
+    }
+
+    let is_long = glyf_data.len() / 2 > u16::MAX as usize;
+    let loca_data = if is_long {
+        loca_offsets
+            .iter()
+            .flat_map(|offset| offset.to_be_bytes())
+            .collect()
+    } else {
+        loca_offsets
+            .iter()
+            .flat_map(|offset| ((offset / 2) as u16).to_be_bytes())
+            .collect()
+    };
+    (glyf_data, loca_data, is_long)
+}
+
+/// Zero the metrics of the glyphs outside the subset. Glyphs after the last one
+/// in the subset share its advance instead, so that they only take a side
+/// bearing each.
+fn subset_hmtx(
+    hmtx: &read_fonts::tables::hmtx::Hmtx,
+    num_glyphs: u32,
+    glyphs: &IntSet<GlyphId>,
+) -> Hmtx {
+    let last_glyph_id = glyphs.last().map_or(0, |glyph_id| glyph_id.to_u32());
+    let mut metrics = (0..num_glyphs)
+        .map(GlyphId::new)
+        .map(|glyph_id| {
+            if glyphs.contains(glyph_id) {
+                LongMetric::new(
+                    hmtx.advance(glyph_id).unwrap_or_default(),
+                    hmtx.side_bearing(glyph_id).unwrap_or_default(),
+                )
+            } else {
+                LongMetric::new(0, 0)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let last_advance = metrics
+        .get(last_glyph_id as usize)
+        .map_or(0, |metric| metric.advance);
+    for metric in metrics.iter_mut().skip(last_glyph_id as usize + 1) {
+        metric.advance = last_advance;
+    }
+    let number_of_h_metrics = metrics
+        .iter()
+        .rposition(|metric| metric.advance != last_advance)
+        .map_or(1, |index| index + 2)
+        .min(metrics.len());
+    let left_side_bearings = metrics
+        .split_off(number_of_h_metrics)
+        .into_iter()
+        .map(|metric| metric.side_bearing)
+        .collect();
+    Hmtx::new(metrics, left_side_bearings)
+}
+
+/// Copy the variation data of the glyphs in the subset, leaving the others
+/// without variations.
+fn subset_gvar(gvar: &Gvar, glyphs: &IntSet<GlyphId>) -> Result<Vec<u8>, SubsetError> {
+    let glyph_count = gvar.glyph_count();
+    let mut data = Vec::new();
+    let mut offsets = vec![0u32];
+    for glyph_id in (0..glyph_count as u32).map(GlyphId::new) {
+        if glyphs.contains(glyph_id)
+            && let Some(glyph_data) = gvar.data_for_gid(glyph_id)?
+        {
+            data.extend_from_slice(glyph_data.as_bytes());
+            data.resize(data.len().next_multiple_of(2), 0);
+        }
+        offsets.push(data.len() as u32);
+    }
+
+    let shared_tuples_start = gvar.shared_tuples_offset().to_u32() as usize;
+    let shared_tuples_length = gvar.shared_tuple_count() as usize * gvar.axis_count() as usize * 2;
+    let shared_tuples = gvar
+        .as_bytes()
+        .get(shared_tuples_start..shared_tuples_start + shared_tuples_length)
+        .ok_or(ReadError::OutOfBounds)?;
+
+    let is_long = data.len() / 2 > u16::MAX as usize;
+    let offsets = if is_long {
+        offsets
+            .iter()
+            .flat_map(|offset| offset.to_be_bytes())
+            .collect::<Vec<_>>()
+    } else {
+        offsets
+            .iter()
+            .flat_map(|offset| ((offset / 2) as u16).to_be_bytes())
+            .collect::<Vec<_>>()
+    };
+    let shared_tuples_offset = 20 + offsets.len() as u32;
+    let data_offset = shared_tuples_offset + shared_tuples.len() as u32;
+
+    let mut gvar_data = Vec::with_capacity(data_offset as usize + data.len());
+    gvar_data.extend_from_slice(&[0, 1, 0, 0]);
+    gvar_data.extend_from_slice(&gvar.axis_count().to_be_bytes());
+    gvar_data.extend_from_slice(&gvar.shared_tuple_count().to_be_bytes());
+    gvar_data.extend_from_slice(&shared_tuples_offset.to_be_bytes());
+    gvar_data.extend_from_slice(&glyph_count.to_be_bytes());
+    gvar_data.extend_from_slice(&(is_long as u16).to_be_bytes());
+    gvar_data.extend_from_slice(&data_offset.to_be_bytes());
+    gvar_data.extend_from_slice(&offsets);
+    gvar_data.extend_from_slice(shared_tuples);
+    gvar_data.extend_from_slice(&data);
+    Ok(gvar_data)
+}
+
+const CHARSET: u16 = 15;
+const ENCODING: u16 = 16;
+const CHARSTRINGS: u16 = 17;
+const PRIVATE: u16 = 18;
+const SUBRS: u16 = 19;
+const VSTORE: u16 = 24;
+const FD_ARRAY: u16 = 12 << 8 | 36;
+const FD_SELECT: u16 = 12 << 8 | 37;
+
+/// The charstring of glyphs outside the subset in `CFF `. `CFF2` charstrings
+/// don't end with `endchar`, so they're left empty instead.
+const ENDCHAR: &[u8] = &[14];
+
+/// Rebuild a `CFF ` or `CFF2` table with empty charstrings for the glyphs
+/// outside the subset and empty subroutines where no glyph in the subset calls
+/// them. Glyph IDs and subroutine numbers are retained, so the remaining
+/// charstrings are copied as they are. The glyphs that accented glyphs are
+/// built from are added to the subset.
+fn subset_cff(data: &[u8], glyphs: &mut IntSet<GlyphId>) -> Result<Vec<u8>, CffError> {
+    let font = CffFontRef::new(data, 0, None)?;
+    let is_cff2 = font.version() == 2;
+    let num_glyphs = font.num_glyphs();
+
+    let contexts = (0..font.num_subfonts())
+        .map(|index| CffContext::new(&font, &font.subfont(index, &[])?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pending = glyphs.iter().collect::<Vec<_>>();
+    while let Some(glyph_id) = pending.pop() {
+        let Some(context) = font
+            .subfont_index(glyph_id)
+            .and_then(|index| contexts.get(index as usize))
+        else {
+            continue;
+        };
+        let charstring = font.charstrings().get(glyph_id.to_u32() as usize)?;
+        let blend_state = font
+            .var_store()
+            .and_then(|store| BlendState::new(store.clone(), &[], context.vs_index).ok());
+        cs::evaluate(context, blend_state, charstring, &mut NullSink)?;
+        for glyph_id in context.seac_glyphs.take() {
+            if glyphs.insert(glyph_id) {
+                pending.push(glyph_id);
+            }
+        }
+    }
+
+    let mut used_global_subrs = IntSet::empty();
+    for context in &contexts {
+        used_global_subrs.union(&context.used_global_subrs.borrow());
+    }
+    let global_subrs = subset_index(font.global_subrs(), is_cff2, |index| {
+        used_global_subrs.contains(index)
+    })?;
+    let empty_charstring = if is_cff2 { &[] } else { ENDCHAR };
+    let charstrings = (0..num_glyphs)
+        .map(|glyph_id| {
+            if glyphs.contains(GlyphId::new(glyph_id)) {
+                font.charstrings().get(glyph_id as usize)
+            } else {
+                Ok(empty_charstring)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let charstrings = write_index(&charstrings, is_cff2);
+
+    // The header, names and strings are copied as they are, in CFF2 the top
+    // DICT length in the header is updated below.
+    let (mut prefix, top_dict, strings) = if is_cff2 {
+        let cff2 = Cff2::read(FontData::new(data))?;
+        let header_size = cff2.header().header_size() as usize;
+        let header = data.get(..header_size).ok_or(ReadError::OutOfBounds)?;
+        (header.to_vec(), cff2.top_dict_data(), &[][..])
+    } else {
+        let cff = Cff::read(FontData::new(data))?;
+        let top_dicts = cff.top_dicts();
+        if top_dicts.count() != 1 {
+            return Err(CffError::InvalidFontFormat);
+        }
+        let names_end = cff.header().hdr_size() as usize + cff.names().size_in_bytes()?;
+        let strings_start = names_end + top_dicts.size_in_bytes()?;
+        let strings_end = strings_start + cff.strings().size_in_bytes()?;
+        (
+            data.get(..names_end)
+                .ok_or(ReadError::OutOfBounds)?
+                .to_vec(),
+            top_dicts.get(0)?,
+            data.get(strings_start..strings_end)
+                .ok_or(ReadError::OutOfBounds)?,
+        )
+    };
+
+    let top_dict = dict_entries(top_dict)?;
+    let mut copied_data = Vec::new();
+    let mut font_dicts = None;
+    let mut private_dict_ranges = Vec::new();
+    for (operands, operator) in &top_dict {
+        let offset = || {
+            dict_integers(operands)?
+                .last()
+                .map(|offset| *offset as usize)
+                .ok_or(CffError::StackUnderflow)
+        };
+        let (offset, size) = match *operator {
+            CHARSET if !is_cff2 && offset()? > 2 => {
+                (offset()?, charset_size(data, offset()?, num_glyphs)?)
+            }
+            ENCODING if !is_cff2 && offset()? > 1 => (offset()?, encoding_size(data, offset()?)?),
+            FD_SELECT => (offset()?, fd_select_size(data, offset()?, num_glyphs)?),
+            VSTORE if is_cff2 => {
+                let size = FontData::new(data).read_at::<u16>(offset()?)?;
+                (offset()?, 2 + size as usize)
+            }
+            FD_ARRAY => {
+                let data = data.get(offset()?..).ok_or(ReadError::OutOfBounds)?;
+                let index = Index::new(data, is_cff2)?;
+                let entries = (0..index.count() as usize)
+                    .map(|font_dict| dict_entries(index.get(font_dict)?))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for font_dict in &entries {
+                    let (operands, _) = font_dict
+                        .iter()
+                        .find(|(_, operator)| *operator == PRIVATE)
+                        .ok_or(CffError::MissingPrivateDict)?;
+                    private_dict_ranges.push(private_dict_range(operands)?);
+                }
+                font_dicts = Some(entries);
+                continue;
+            }
+            PRIVATE if !is_cff2 => {
+                private_dict_ranges.push(private_dict_range(operands)?);
+                continue;
+            }
+            _ => continue,
+        };
+        let copied = data
+            .get(offset..offset + size)
+            .ok_or(ReadError::OutOfBounds)?;
+        copied_data.push((*operator, copied));
+    }
+    if private_dict_ranges.len() != contexts.len() {
+        return Err(CffError::MissingPrivateDict);
+    }
+
+    // Private DICTs are followed by their local subroutines.
+    let private_dicts = iter::zip(&private_dict_ranges, &contexts)
+        .map(|(range, context)| {
+            let private_dict = data.get(range.clone()).ok_or(ReadError::OutOfBounds)?;
+            if context.subrs_offset == 0 {
+                return Ok((private_dict.to_vec(), Vec::new()));
+            }
+            let private_dict = dict_entries(private_dict)?;
+            let used_subrs = context.used_subrs.borrow();
+            let subrs = subset_index(&context.subrs, is_cff2, |index| used_subrs.contains(index))?;
+            let size = write_dict(&private_dict, |operator| {
+                (operator == SUBRS).then(|| vec![0])
+            })
+            .len();
+            let private_dict = write_dict(&private_dict, |operator| {
+                (operator == SUBRS).then(|| vec![size as i32])
+            });
+            Ok((private_dict, subrs))
+        })
+        .collect::<Result<Vec<_>, CffError>>()?;
+
+    // Relocated offsets are written as 5-byte integers, so the DICTs that hold
+    // them have the same size whatever the offsets are.
+    let relocated = copied_data
+        .iter()
+        .map(|(operator, _)| *operator)
+        .chain([
+            CHARSTRINGS,
+            if font_dicts.is_some() {
+                FD_ARRAY
+            } else {
+                PRIVATE
+            },
+        ])
+        .collect::<Vec<_>>();
+    let top_dict_size = write_dict(&top_dict, |operator| {
+        relocated
+            .contains(&operator)
+            .then(|| vec![0; if operator == PRIVATE { 2 } else { 1 }])
+    })
+    .len();
+    let mut offset = prefix.len()
+        + if is_cff2 {
+            top_dict_size
+        } else {
+            write_index(&[&vec![0; top_dict_size]], false).len()
+        }
+        + strings.len()
+        + global_subrs.len();
+
+    let mut offsets = HashMap::new();
+    for (operator, copied) in &copied_data {
+        offsets.insert(*operator, vec![offset as i32]);
+        offset += copied.len();
+    }
+    offsets.insert(CHARSTRINGS, vec![offset as i32]);
+    offset += charstrings.len();
+
+    let mut fd_array = Vec::new();
+    if let Some(font_dicts) = &font_dicts {
+        let placeholders = font_dicts
+            .iter()
+            .map(|font_dict| {
+                write_dict(font_dict, |operator| {
+                    (operator == PRIVATE).then(|| vec![0, 0])
+                })
+            })
+            .collect::<Vec<_>>();
+        offsets.insert(FD_ARRAY, vec![offset as i32]);
+        offset += write_index(
+            &placeholders.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+            is_cff2,
+        )
+        .len();
+
+        let mut private_dict_offset = offset;
+        let font_dicts = iter::zip(font_dicts, &private_dicts)
+            .map(|(font_dict, (private_dict, subrs))| {
+                let font_dict = write_dict(font_dict, |operator| {
+                    (operator == PRIVATE)
+                        .then(|| vec![private_dict.len() as i32, private_dict_offset as i32])
+                });
+                private_dict_offset += private_dict.len() + subrs.len();
+                font_dict
+            })
+            .collect::<Vec<_>>();
+        fd_array = write_index(
+            &font_dicts.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+            is_cff2,
+        );
+    } else if let [(private_dict, _)] = private_dicts.as_slice() {
+        offsets.insert(PRIVATE, vec![private_dict.len() as i32, offset as i32]);
+    }
+
+    let top_dict = write_dict(&top_dict, |operator| offsets.get(&operator).cloned());
+    if is_cff2 {
+        prefix
+            .get_mut(3..5)
+            .ok_or(ReadError::OutOfBounds)?
+            .copy_from_slice(&(top_dict.len() as u16).to_be_bytes());
+        prefix.extend_from_slice(&top_dict);
+    } else {
+        prefix.extend_from_slice(&write_index(&[&top_dict], false));
+    }
+    let mut cff_data = prefix;
+    cff_data.extend_from_slice(strings);
+    cff_data.extend_from_slice(&global_subrs);
+    for (_, copied) in copied_data {
+        cff_data.extend_from_slice(copied);
+    }
+    cff_data.extend_from_slice(&charstrings);
+    cff_data.extend_from_slice(&fd_array);
+    for (private_dict, subrs) in private_dicts {
+        cff_data.extend_from_slice(&private_dict);
+        cff_data.extend_from_slice(&subrs);
+    }
+    Ok(cff_data)
+}
+
+/// Records the subroutines and the components of accented glyphs that
+/// charstrings of a subfont use.
+struct CffContext<'a> {
+    font: &'a CffFontRef<'a>,
+    subrs: Index<'a>,
+    subrs_offset: u32,
+    vs_index: u16,
+    used_global_subrs: RefCell<IntSet<u32>>,
+    used_subrs: RefCell<IntSet<u32>>,
+    seac_glyphs: RefCell<Vec<GlyphId>>,
+}
+
+impl<'a> CffContext<'a> {
+    fn new(font: &'a CffFontRef<'a>, subfont: &Subfont) -> Result<Self, CffError> {
+        let subrs_offset = subfont.subrs_offset();
+        let subrs = if subrs_offset != 0 {
+            let data = font
+                .data()
+                .get(subrs_offset as usize..)
+                .ok_or(ReadError::OutOfBounds)?;
+            Index::new(data, font.version() == 2)?
+        } else {
+            Index::Empty
+        };
+        Ok(Self {
+            font,
+            subrs,
+            subrs_offset,
+            vs_index: subfont.vs_index(),
+            used_global_subrs: RefCell::default(),
+            used_subrs: RefCell::default(),
+            seac_glyphs: RefCell::default(),
+        })
+    }
+}
+
+impl CharstringContext for CffContext<'_> {
+    fn kind(&self) -> CharstringKind {
+        CharstringKind::Type2
+    }
+
+    fn seac_components(&self, base_code: i32, accent_code: i32) -> Result<[&[u8]; 2], CffError> {
+        let charset = self.font.charset().ok_or(CffError::MissingCharset)?;
+        let glyph_id = |code: i32| {
+            u8::try_from(code)
+                .ok()
+                .and_then(|code| PredefinedEncoding::Standard.sid(code))
+                .and_then(|sid| charset.glyph_id(sid).ok())
+                .ok_or(CffError::InvalidSeacCode(code))
+        };
+        let glyph_ids = [glyph_id(base_code)?, glyph_id(accent_code)?];
+        self.seac_glyphs.borrow_mut().extend(glyph_ids);
+        Ok([
+            self.font
+                .charstrings()
+                .get(glyph_ids[0].to_u32() as usize)?,
+            self.font
+                .charstrings()
+                .get(glyph_ids[1].to_u32() as usize)?,
+        ])
+    }
+
+    fn global_subr(&self, index: i32) -> Result<&[u8], CffError> {
+        let subrs = self.font.global_subrs();
+        let index = u32::try_from(index + subrs.subr_bias()).map_err(|_| ReadError::OutOfBounds)?;
+        self.used_global_subrs.borrow_mut().insert(index);
+        subrs.get(index as usize)
+    }
+
+    fn subr(&self, index: i32) -> Result<&[u8], CffError> {
+        let index =
+            u32::try_from(index + self.subrs.subr_bias()).map_err(|_| ReadError::OutOfBounds)?;
+        self.used_subrs.borrow_mut().insert(index);
+        self.subrs.get(index as usize)
+    }
+}
+
+struct NullSink;
+
+impl CommandSink for NullSink {
+    fn move_to(&mut self, _: Fixed, _: Fixed) {}
+    fn line_to(&mut self, _: Fixed, _: Fixed) {}
+    fn curve_to(&mut self, _: Fixed, _: Fixed, _: Fixed, _: Fixed, _: Fixed, _: Fixed) {}
+    fn close(&mut self) {}
+}
+
+/// Write an INDEX with the items in `index` that `keep` accepts, leaving the
+/// others empty.
+fn subset_index(
+    index: &Index,
+    is_cff2: bool,
+    keep: impl Fn(u32) -> bool,
+) -> Result<Vec<u8>, CffError> {
+    let items = (0..index.count())
+        .map(|item| {
+            if keep(item) {
+                index.get(item as usize)
+            } else {
+                Ok(&[][..])
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(write_index(&items, is_cff2))
+}
+
+fn write_index(items: &[&[u8]], is_cff2: bool) -> Vec<u8> {
+    let mut index = if is_cff2 {
+        (items.len() as u32).to_be_bytes().to_vec()
+    } else {
+        (items.len() as u16).to_be_bytes().to_vec()
+    };
+    if items.is_empty() {
+        return index;
+    }
+
+    let last_offset = 1 + items.iter().map(|item| item.len()).sum::<usize>();
+    let offset_size = match last_offset {
+        ..0x100 => 1,
+        0x100..0x10000 => 2,
+        0x10000..0x1000000 => 3,
+        _ => 4,
+    };
+    index.push(offset_size as u8);
+    let mut offset = 1;
+    index.extend_from_slice(&(offset as u32).to_be_bytes()[4 - offset_size..]);
+    for item in items {
+        offset += item.len();
+        index.extend_from_slice(&(offset as u32).to_be_bytes()[4 - offset_size..]);
+    }
+    for item in items {
+        index.extend_from_slice(item);
+    }
+    index
+}
+
+/// Split DICT data into its entries, each with the raw operands and the
+/// operator, where escaped operators are `12 << 8 | operator`.
+fn dict_entries(data: &[u8]) -> Result<Vec<(&[u8], u16)>, CffError> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut position = 0;
+    while let Some(&b0) = data.get(position) {
+        position += match b0 {
+            12 => {
+                let b1 = *data.get(position + 1).ok_or(ReadError::OutOfBounds)?;
+                entries.push((&data[start..position], 12 << 8 | b1 as u16));
+                start = position + 2;
+                2
+            }
+            0..=27 => {
+                entries.push((&data[start..position], b0 as u16));
+                start = position + 1;
+                1
+            }
+            28 => 3,
+            29 => 5,
+            30 => {
+                let length = data
+                    .get(position + 1..)
+                    .and_then(|nibbles| {
+                        nibbles
+                            .iter()
+                            .position(|byte| byte & 0x0f == 0x0f || byte & 0xf0 == 0xf0)
+                    })
+                    .ok_or(ReadError::OutOfBounds)?;
+                length + 2
+            }
+            32..=246 => 1,
+            247..=254 => 2,
+            _ => return Err(CffError::InvalidDictOperator(b0)),
+        };
+    }
+    if start != data.len() {
+        return Err(ReadError::OutOfBounds.into());
+    }
+    Ok(entries)
+}
+
+/// Write DICT entries, replacing the operands of the operators that `relocate`
+/// returns new ones for with 5-byte integers.
+fn write_dict(entries: &[(&[u8], u16)], relocate: impl Fn(u16) -> Option<Vec<i32>>) -> Vec<u8> {
+    let mut dict = Vec::new();
+    for (operands, operator) in entries {
+        match relocate(*operator) {
+            Some(integers) => {
+                for integer in integers {
+                    dict.push(29);
+                    dict.extend_from_slice(&integer.to_be_bytes());
+                }
+            }
+            None => dict.extend_from_slice(operands),
+        }
+        if operator >> 8 == 12 {
+            dict.extend_from_slice(&[12, *operator as u8]);
+        } else {
+            dict.push(*operator as u8);
+        }
+    }
+    dict
+}
+
+fn dict_integers(operands: &[u8]) -> Result<Vec<i32>, CffError> {
+    let data = FontData::new(operands);
+    let mut integers = Vec::new();
+    let mut position = 0;
+    while let Ok(b0) = data.read_at::<u8>(position) {
+        let (integer, size) = match b0 {
+            28 => (data.read_at::<i16>(position + 1)? as i32, 3),
+            29 => (data.read_at::<i32>(position + 1)?, 5),
+            32..=246 => (b0 as i32 - 139, 1),
+            247..=250 => (
+                (b0 as i32 - 247) * 256 + data.read_at::<u8>(position + 1)? as i32 + 108,
+                2,
+            ),
+            251..=254 => (
+                -(b0 as i32 - 251) * 256 - data.read_at::<u8>(position + 1)? as i32 - 108,
+                2,
+            ),
+            _ => return Err(CffError::InvalidNumber),
+        };
+        integers.push(integer);
+        position += size;
+    }
+    Ok(integers)
+}
+
+fn private_dict_range(operands: &[u8]) -> Result<Range<usize>, CffError> {
+    match dict_integers(operands)?[..] {
+        [size, offset] => Ok(offset as usize..offset as usize + size as usize),
+        _ => Err(CffError::MissingPrivateDict),
+    }
+}
+
+fn charset_size(data: &[u8], offset: usize, num_glyphs: u32) -> Result<usize, CffError> {
+    let data = FontData::new(data);
+    let format = data.read_at::<u8>(offset)?;
+    if format == 0 {
+        return Ok(1 + 2 * num_glyphs.saturating_sub(1) as usize);
+    }
+
+    let mut size = 1;
+    let mut covered = 1;
+    while covered < num_glyphs {
+        let left = match format {
+            1 => data.read_at::<u8>(offset + size + 2)? as u32,
+            2 => data.read_at::<u16>(offset + size + 2)? as u32,
+            _ => return Err(CffError::InvalidFontFormat),
+        };
+        size += if format == 1 { 3 } else { 4 };
+        covered += left + 1;
+    }
+    Ok(size)
+}
+
+fn encoding_size(data: &[u8], offset: usize) -> Result<usize, CffError> {
+    let data = FontData::new(data);
+    let format = data.read_at::<u8>(offset)?;
+    let count = data.read_at::<u8>(offset + 1)? as usize;
+    let mut size = match format & 0x7f {
+        0 => 2 + count,
+        1 => 2 + 2 * count,
+        _ => return Err(CffError::InvalidFontFormat),
+    };
+    if format & 0x80 != 0 {
+        size += 1 + 3 * data.read_at::<u8>(offset + size)? as usize;
+    }
+    Ok(size)
+}
+
+fn fd_select_size(data: &[u8], offset: usize, num_glyphs: u32) -> Result<usize, CffError> {
+    let data = FontData::new(data);
+    match data.read_at::<u8>(offset)? {
+        0 => Ok(1 + num_glyphs as usize),
+        3 => Ok(1 + 2 + 3 * data.read_at::<u16>(offset + 1)? as usize + 2),
+        4 => Ok(1 + 4 + 6 * data.read_at::<u32>(offset + 1)? as usize + 4),
+        _ => Err(CffError::InvalidFontFormat),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use skrifa::{
+        instance::{Location, Size},
+        outline::{DrawSettings, pen::SvgPen},
+    };
+
+    use super::*;
+    use crate::fixtures::fixture;
+
+    fn draw_glyph(font: &FontRef, glyph_id: GlyphId, location: &Location) -> String {
+        let mut pen = SvgPen::default();
+        font.outline_glyphs()
+            .get(glyph_id)
+            .unwrap()
+            .draw(DrawSettings::unhinted(Size::unscaled(), location), &mut pen)
+            .unwrap();
+        pen.to_string()
+    }
+
+    /// Subset a fixture to one character and check that its glyph draws the
+    /// same at each location, while another glyph is left empty.
+    fn check_subset(file_name: &str, kept: char, dropped: char, locations: &[&[(&str, f32)]]) {
+        let path = fixture(file_name);
+        let data = fs::read(&path).unwrap();
+        let (subset_data, _) = subset_font(SubsetOptions {
+            font: (&path, 0),
+            codepoints: &[kept as u32],
+            glyph_ids: &[],
+        })
+        .unwrap();
+        assert!(subset_data.len() < data.len());
+
+        let font = FontRef::new(&data).unwrap();
+        let subset = FontRef::new(&subset_data).unwrap();
+        let (kept_char, dropped_char) = (kept, dropped);
+        let kept = font.charmap().map(kept_char).unwrap();
+        let dropped = font.charmap().map(dropped_char).unwrap();
+        assert_eq!(subset.charmap().map(kept_char), Some(kept));
+        assert_eq!(subset.charmap().map(dropped_char), None);
+        for settings in locations {
+            let location = font.axes().location(settings.iter().copied());
+            let outline = draw_glyph(&font, kept, &location);
+            assert!(!outline.is_empty());
+            assert_eq!(draw_glyph(&subset, kept, &location), outline);
+            assert!(!draw_glyph(&font, dropped, &location).is_empty());
+            assert_eq!(draw_glyph(&subset, dropped, &location), "");
+        }
+
+        // Glyphs after the last one in the subset only take a side bearing.
+        let hmtx = subset.hmtx().unwrap();
+        assert_eq!(hmtx.advance(kept), font.hmtx().unwrap().advance(kept));
+        assert_eq!(hmtx.side_bearing(dropped), Some(0));
+        assert!(subset.hhea().unwrap().number_of_h_metrics() as u32 <= kept.to_u32() + 1);
+    }
+
+    #[test]
+    fn test_subset_glyf() {
+        check_subset(
+            "NotoSansHebrew-VF.ttf",
+            '\u{5D0}',
+            '\u{5D1}',
+            &[&[], &[("wght", 900.0), ("wdth", 62.5)]],
+        );
+
+        let path = fixture("NotoSansHebrew-VF.ttf");
+        let (subset_data, extension) = subset_font(SubsetOptions {
+            font: (&path, 0),
+            codepoints: &['\u{5D0}' as u32],
+            glyph_ids: &[],
+        })
+        .unwrap();
+        assert_eq!(extension, "ttf");
+        let subset = FontRef::new(&subset_data).unwrap();
+        let gvar = subset.gvar().unwrap();
+        let kept = subset.charmap().map('\u{5D0}').unwrap();
+        let dropped = FontRef::new(&fs::read(&path).unwrap())
+            .unwrap()
+            .charmap()
+            .map('\u{5D1}')
+            .unwrap();
+        assert!(gvar.data_for_gid(kept).unwrap().is_some());
+        assert!(gvar.data_for_gid(dropped).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_subset_cff() {
+        check_subset("FontAwesome.otf", '\u{F000}', '\u{F001}', &[&[]]);
+
+        // Subroutines only the dropped glyphs call are emptied.
+        let path = fixture("FontAwesome.otf");
+        let (subset_data, extension) = subset_font(SubsetOptions {
+            font: (&path, 0),
+            codepoints: &[0xF000],
+            glyph_ids: &[],
+        })
+        .unwrap();
+        assert_eq!(extension, "otf");
+        let count_subrs = |data: &[u8]| {
+            let font = FontRef::new(data).unwrap();
+            let cff =
+                CffFontRef::new(font.cff().unwrap().offset_data().as_bytes(), 0, None).unwrap();
+            let context = CffContext::new(&cff, &cff.subfont(0, &[]).unwrap()).unwrap();
+            let count = |index: &Index| {
+                (0..index.count() as usize)
+                    .filter(|item| !index.get(*item).unwrap().is_empty())
+                    .count()
+            };
+            (count(cff.global_subrs()), count(&context.subrs))
+        };
+        let (global_subrs, subrs) = count_subrs(&fs::read(&path).unwrap());
+        let (subset_global_subrs, subset_subrs) = count_subrs(&subset_data);
+        assert!(subset_global_subrs < global_subrs);
+        assert!(subset_subrs < subrs);
+    }
+
+    #[test]
+    fn test_subset_cff2() {
+        check_subset(
+            "Cantarell-VF.otf",
+            'C',
+            'a',
+            &[&[], &[("wght", 100.0)], &[("wght", 800.0)]],
+        );
+    }
+}